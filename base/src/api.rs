@@ -1,29 +1,123 @@
 #![allow(dead_code)]
+use serde::Deserialize;
 use serde_json::{from_str, from_value};
 use std::collections::BTreeMap;
 
-use simeis_data::crew::CrewMemberType;
+use simeis_data::crew::{CrewId, CrewMemberType};
+use simeis_data::errors::ErrorCategory;
 use simeis_data::galaxy::scan::ScanResult;
 use simeis_data::galaxy::station::StationId;
+use simeis_data::market::history::Candle;
+use simeis_data::market::orderbook::OrderId;
 use simeis_data::market::MarketTx;
+use simeis_data::player::alert::AlertId;
 use simeis_data::player::PlayerId;
+use simeis_data::session::SessionId;
 use simeis_data::ship::cargo::ShipCargo;
-use simeis_data::ship::module::ShipModuleType;
+use simeis_data::ship::module::{ShipModuleId, ShipModuleType};
 use simeis_data::ship::navigation::TravelCost;
 use simeis_data::ship::resources::{ExtractionInfo, Resource};
 use simeis_data::ship::upgrade::ShipUpgrade;
-use simeis_data::ship::Ship;
+use simeis_data::ship::{Ship, ShipId};
 use simeis_data::syslog::SyslogEvent;
 use std::path::PathBuf;
 
 use crate::data::{Player, SpaceCoord, Station};
 use crate::json::{get_float, get_json, get_string, get_unsigned};
 
+// Errors returned by the server, built from the "type" and "details" fields of the reply
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", content = "details")]
+pub enum ServerError {
+    NoPlayerKey,
+    PlayerNotFound {
+        player: PlayerId,
+    },
+    PlayerAlreadyExists {
+        player: PlayerId,
+        name: String,
+    },
+    NoPlayerWithKey,
+    ShipNotFound {
+        ship: ShipId,
+    },
+    NotEnoughMoney {
+        have: f64,
+        need: f64,
+    },
+    InvalidArgument {
+        argument: String,
+    },
+    ShipNotExtracting,
+    ShipNotIdle,
+    CrewMemberNotIdle {
+        crew: CrewId,
+    },
+    CrewNotNeeded,
+    CannotPerformTravel,
+    NullDistance,
+    NoSuchStation {
+        station: StationId,
+    },
+    NoSuchModule {
+        module: ShipModuleId,
+    },
+    CannotExtractWithoutPlanet,
+    ShipNotInStation,
+    WrongCrewType {
+        expected: CrewMemberType,
+    },
+    CargoFull,
+    NoTraderAssigned,
+    NoPilotAssigned,
+    BuyNothing,
+    SellNothing,
+    NoFuelInCargo,
+    NoHullPlateInCargo,
+    CrewMemberNotFound {
+        crew: CrewId,
+    },
+    PlayerLost,
+    NoSuchOrder {
+        order: OrderId,
+    },
+    NoSuchAlert {
+        alert: AlertId,
+    },
+    TooManyAlerts {
+        max: usize,
+    },
+    InvalidInvite,
+    NotAdmin,
+    RateLimited {
+        retry_after: f64,
+    },
+    GameNotStarted {
+        start: f64,
+    },
+    GameOver {
+        session: SessionId,
+    },
+    NoSuchSession {
+        session: SessionId,
+    },
+    NoSuchWorld {
+        world: String,
+    },
+    StationAlreadyExists {
+        station: StationId,
+    },
+    #[serde(other)]
+    Unknown,
+}
+
 #[derive(Clone, Debug)]
 pub enum ApiError {
     NotFound,
     Error {
-        errtype: String,
+        code: u16,
+        category: ErrorCategory,
+        error: ServerError,
         message: String,
     },
 
@@ -50,20 +144,27 @@ impl From<ureq::Error> for ApiError {
 pub struct ApiClient {
     server: String,
     key: Option<String>,
+    // Name under which the syslogs are acknowledged, other clients of the player keep their own
+    client: String,
 }
 
 impl ApiClient {
     pub fn init<T: ToString>(server: T) -> ApiClient {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or(0);
         ApiClient {
             server: server.to_string(),
             key: None,
+            client: format!("base-{}-{nanos}", std::process::id()),
         }
     }
 
     fn get<T: ToString>(&self, path: T) -> Result<serde_json::Value, ApiError> {
         let mut qry = ureq::get(format!("{}{}", self.server, path.to_string()));
         if let Some(ref key) = self.key {
-            qry = qry.query("key", key)
+            qry = qry.header("Authorization", format!("Bearer {key}"))
         }
 
         let body = qry
@@ -88,9 +189,12 @@ impl ApiClient {
         if error == "ok" {
             Ok(data)
         } else {
-            let errtype = get_string(&data, "type")?;
+            let code = get_unsigned(&data, "code")? as u16;
+            let category = from_value(get_json(&data, "category")?).unwrap();
             Err(ApiError::Error {
-                errtype,
+                code,
+                category,
+                error: from_value(data).unwrap_or(ServerError::Unknown),
                 message: error,
             })
         }
@@ -101,15 +205,15 @@ impl ApiClient {
         Ok(get_string(&got, "ping")? == "pong")
     }
 
-    pub fn new_player<T: ToString>(&mut self, name: T) -> Result<u16, ApiError> {
+    pub fn new_player<T: ToString>(&mut self, name: T) -> Result<PlayerId, ApiError> {
         let got = self.get(format!("/player/new/{}", name.to_string()))?;
         let id = get_unsigned(&got, "playerId")?;
         let key = get_string(&got, "key")?;
         self.key = Some(key);
-        Ok(u16::try_from(id).unwrap())
+        Ok(PlayerId::try_from(id).unwrap())
     }
 
-    pub fn get_player(&self, id: u16) -> Result<Player, ApiError> {
+    pub fn get_player(&self, id: PlayerId) -> Result<Player, ApiError> {
         let got = self.get(format!("/player/{id}"))?;
         let player = from_value(got).unwrap();
         Ok(player)
@@ -301,6 +405,28 @@ impl ApiClient {
         Ok(data)
     }
 
+    pub fn station_resource_prices(
+        &self,
+        station_id: StationId,
+    ) -> Result<BTreeMap<Resource, f64>, ApiError> {
+        let got = self.get(format!("/market/prices?station={station_id}"))?;
+        let data = serde_json::from_value(get_json(&got, "prices")?).unwrap();
+        Ok(data)
+    }
+
+    pub fn price_history(
+        &self,
+        resource: &Resource,
+        interval: f64,
+        count: usize,
+    ) -> Result<Vec<Candle>, ApiError> {
+        let resource: &'static str = resource.into();
+        let got = self.get(format!(
+            "/market/history/{resource}?interval={interval}&count={count}"
+        ))?;
+        Ok(serde_json::from_value(get_json(&got, "candles")?).unwrap())
+    }
+
     pub fn buy_resource(
         &self,
         station_id: StationId,
@@ -396,17 +522,22 @@ impl ApiClient {
     }
 
     pub fn get_syslogs(&self) -> Result<Vec<(f64, SyslogEvent)>, ApiError> {
-        let got = self.get(format!("/syslogs"))?;
+        let got = self.get(format!("/syslogs?client={}", self.client))?;
         let nb = get_unsigned(&got, "nb")?;
         let mut res = Vec::with_capacity(nb as usize);
         let data: Vec<serde_json::Value> =
             serde_json::from_value(get_json(&got, "events")?).unwrap();
+        let mut last = None;
         for ev in data {
+            last = Some(get_unsigned(&ev, "seq")?);
             res.push((
                 get_float(&ev, "timestamp")?,
                 serde_json::from_value(get_json(&ev, "event")?).unwrap(),
             ));
         }
+        if let Some(seq) = last {
+            self.get(format!("/syslogs/ack/{seq}?client={}", self.client))?;
+        }
         Ok(res)
     }
 
@@ -414,10 +545,12 @@ impl ApiClient {
         let fname = PathBuf::from(fname.to_string());
         let data = std::fs::read(&fname).expect("Unable to read file");
         let json: serde_json::Value = serde_json::from_slice(&data).expect("Unable to load");
+        // Files exported with the older 16 bits IDs still load
         let id = get_unsigned(&json, "id").unwrap();
+        let id = PlayerId::try_from(id).expect("Invalid player ID");
         let key = get_string(&json, "key").unwrap();
         let key = if key == "none" { None } else { Some(key) };
-        (id as PlayerId, key)
+        (id, key)
     }
 
     pub fn export_player<T: ToString>(&self, id: PlayerId, fname: T) {
@@ -430,3 +563,108 @@ impl ApiClient {
         std::fs::write(&fname, json).expect("Unable to export player");
     }
 }
+
+#[test]
+fn test_server_errors() {
+    use simeis_data::errors::Errcode;
+
+    let errors = [
+        Errcode::NoPlayerKey,
+        Errcode::PlayerNotFound(1),
+        Errcode::PlayerAlreadyExists(1, "name".to_string()),
+        Errcode::NoPlayerWithKey,
+        Errcode::ShipNotFound(2),
+        Errcode::NotEnoughMoney(10.0, 20.0),
+        Errcode::InvalidArgument("amount"),
+        Errcode::ShipNotExtracting,
+        Errcode::ShipNotIdle,
+        Errcode::CrewMemberNotIdle(3),
+        Errcode::CrewNotNeeded,
+        Errcode::CannotPerformTravel,
+        Errcode::NullDistance,
+        Errcode::NoSuchStation(4),
+        Errcode::NoSuchModule(5),
+        Errcode::CannotExtractWithoutPlanet,
+        Errcode::ShipNotInStation,
+        Errcode::WrongCrewType(CrewMemberType::Pilot),
+        Errcode::CargoFull,
+        Errcode::NoTraderAssigned,
+        Errcode::NoPilotAssigned,
+        Errcode::BuyNothing,
+        Errcode::SellNothing,
+        Errcode::NoFuelInCargo,
+        Errcode::NoHullPlateInCargo,
+        Errcode::CrewMemberNotFound(6),
+        Errcode::PlayerLost,
+        Errcode::NoSuchOrder(7),
+        Errcode::NoSuchAlert(8),
+        Errcode::TooManyAlerts(9),
+        Errcode::InvalidInvite,
+        Errcode::NotAdmin,
+        Errcode::RateLimited(1.5),
+        Errcode::GameNotStarted(2.5),
+        Errcode::GameOver(10),
+        Errcode::NoSuchSession(11),
+        Errcode::NoSuchWorld("world".to_string()),
+        Errcode::StationAlreadyExists(12),
+    ];
+    for err in errors.iter() {
+        // Fails to build when the server gets a new error, so that it is added above
+        match err {
+            Errcode::NoPlayerKey
+            | Errcode::PlayerNotFound(_)
+            | Errcode::PlayerAlreadyExists(..)
+            | Errcode::NoPlayerWithKey
+            | Errcode::ShipNotFound(_)
+            | Errcode::NotEnoughMoney(..)
+            | Errcode::InvalidArgument(_)
+            | Errcode::ShipNotExtracting
+            | Errcode::ShipNotIdle
+            | Errcode::CrewMemberNotIdle(_)
+            | Errcode::CrewNotNeeded
+            | Errcode::CannotPerformTravel
+            | Errcode::NullDistance
+            | Errcode::NoSuchStation(_)
+            | Errcode::NoSuchModule(_)
+            | Errcode::CannotExtractWithoutPlanet
+            | Errcode::ShipNotInStation
+            | Errcode::WrongCrewType(_)
+            | Errcode::CargoFull
+            | Errcode::NoTraderAssigned
+            | Errcode::NoPilotAssigned
+            | Errcode::BuyNothing
+            | Errcode::SellNothing
+            | Errcode::NoFuelInCargo
+            | Errcode::NoHullPlateInCargo
+            | Errcode::CrewMemberNotFound(_)
+            | Errcode::PlayerLost
+            | Errcode::NoSuchOrder(_)
+            | Errcode::NoSuchAlert(_)
+            | Errcode::TooManyAlerts(_)
+            | Errcode::InvalidInvite
+            | Errcode::NotAdmin
+            | Errcode::RateLimited(_)
+            | Errcode::GameNotStarted(_)
+            | Errcode::GameOver(_)
+            | Errcode::NoSuchSession(_)
+            | Errcode::NoSuchWorld(_)
+            | Errcode::StationAlreadyExists(_) => {}
+        }
+        let data = serde_json::to_value(err).unwrap();
+        let error: ServerError = from_value(data.clone())
+            .unwrap_or_else(|e| panic!("Unable to read the error {data}: {e}"));
+        let errtype = data["type"].as_str().unwrap();
+        assert!(
+            !matches!(error, ServerError::Unknown),
+            "{errtype} is unknown"
+        );
+        assert!(format!("{error:?}").starts_with(errtype));
+    }
+
+    let error: ServerError =
+        from_str(r#"{"type": "StationAlreadyExists", "details": {"station": 12}}"#).unwrap();
+    assert!(matches!(
+        error,
+        ServerError::StationAlreadyExists { station: 12 }
+    ));
+}
@@ -2,13 +2,13 @@
 use std::collections::BTreeMap;
 
 use serde::Deserialize;
-use simeis_data::{crew::{Crew, CrewId, CrewMemberType}, galaxy::station::StationId, ship::{cargo::ShipCargo, module::ShipModule, Ship}};
+use simeis_data::{crew::{Crew, CrewId, CrewMemberType}, galaxy::station::StationId, player::PlayerId, ship::{cargo::ShipCargo, module::ShipModule, Ship}};
 
 pub type SpaceCoord = (u32, u32, u32);
 
 #[derive(Deserialize, Debug)]
 pub struct Player {
-    pub id: u16,
+    pub id: PlayerId,
     pub costs: Option<f64>,
     pub money: Option<f64>,
     pub name: String,
@@ -4,6 +4,7 @@ use crate::interface::*;
 use ::ratatui::buffer::Buffer;
 use ::ratatui::layout::Rect;
 use ::simeis_data::crew::CrewMemberType;
+use ::simeis_data::player::PlayerId;
 use ::simeis_data::syslog::SyslogEvent;
 
 pub struct Game {
@@ -20,7 +21,7 @@ pub struct Game {
 impl Game {
     pub fn init() -> Game {
         let mut api: ApiClient = ApiClient::init("http://localhost:8080");
-        let player_id: u16 = api.new_player("Luc11").unwrap();
+        let player_id: PlayerId = api.new_player("Luc11").unwrap();
         let player = api.get_player(player_id).unwrap();
         let money = player.money.unwrap();
 
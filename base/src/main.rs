@@ -61,7 +61,7 @@ fn run(mut terminal: DefaultTerminal) -> Result<()> {
 
 // fn init_game() {
 //     let mut api: ApiClient = ApiClient::init("http://localhost:8080");
-//     let player_id: u16 = api.new_player("Lucas").unwrap();
+//     let player_id: PlayerId = api.new_player("Lucas").unwrap();
 //     let player = api.get_player(player_id).unwrap();
 //     let money = player.money.unwrap();
 //     let station_id = player.stations.keys().nth(0).unwrap();
@@ -13,6 +13,10 @@ impl Crew {
     pub fn sum_wages(&self) -> f64 {
         self.0.values().map(|crew| crew.wage()).sum::<f64>()
     }
+
+    pub fn sum_ranks_value(&self) -> f64 {
+        self.0.values().map(|crew| crew.ranks_value()).sum::<f64>()
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -45,6 +49,19 @@ impl CrewMember {
     pub fn price_next_rank(&self) -> f64 {
         self.wage() * RANK_PRICE_WAGE_MULT
     }
+
+    // Money spent to bring the member from the first rank to its current one
+    pub fn ranks_value(&self) -> f64 {
+        (1..self.rank)
+            .map(|rank| {
+                let member = CrewMember {
+                    member_type: self.member_type.clone(),
+                    rank,
+                };
+                member.price_next_rank()
+            })
+            .sum()
+    }
 }
 
 #[allow(dead_code)]
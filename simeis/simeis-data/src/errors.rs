@@ -1,4 +1,20 @@
-#[derive(Debug)]
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::{json, Value};
+use strum::IntoStaticStr;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCategory {
+    Auth,
+    NotFound,
+    InvalidArgument,
+    Funds,
+    Conflict,
+    RateLimited,
+}
+
+#[derive(Debug, IntoStaticStr)]
 pub enum Errcode {
     NoPlayerKey,
     PlayerNotFound(crate::player::PlayerId),
@@ -27,6 +43,17 @@ pub enum Errcode {
     NoHullPlateInCargo,
     CrewMemberNotFound(crate::crew::CrewId),
     PlayerLost,
+    NoSuchOrder(crate::market::orderbook::OrderId),
+    NoSuchAlert(crate::player::alert::AlertId),
+    TooManyAlerts(usize),
+    InvalidInvite,
+    NotAdmin,
+    RateLimited(f64),
+    GameNotStarted(f64),
+    GameOver(crate::session::SessionId),
+    NoSuchSession(crate::session::SessionId),
+    NoSuchWorld(String),
+    StationAlreadyExists(crate::galaxy::station::StationId),
 }
 
 impl Errcode {
@@ -67,6 +94,160 @@ impl Errcode {
             Errcode::CrewMemberNotFound(id) => format!("Crew member of id {id} not found"),
             Errcode::PlayerLost => "This player lost the game and cannot play anymore".to_string(),
             Errcode::NoPilotAssigned => "No pilot is assigned on this ship".to_string(),
+            Errcode::NoSuchOrder(id) => format!("You don't have any open order of id {id}"),
+            Errcode::NoSuchAlert(id) => format!("You don't have any alert of id {id}"),
+            Errcode::TooManyAlerts(max) => format!("Cannot have more than {max} alerts"),
+            Errcode::InvalidInvite => "A valid invite token is required to join this game".to_string(),
+            Errcode::NotAdmin => "This request requires the admin key".to_string(),
+            Errcode::RateLimited(retry) => format!("Too many requests, retry in {retry:.3} seconds"),
+            Errcode::GameNotStarted(start) => format!("The session is in its lobby phase until {start:.0}"),
+            Errcode::GameOver(id) => format!("The session {id} is over"),
+            Errcode::NoSuchSession(id) => format!("No session was found with this ID: {id}"),
+            Errcode::NoSuchWorld(name) => format!("No world was found with this name: {name}"),
+            Errcode::StationAlreadyExists(id) => format!("A station of id {id} already exists"),
+        }
+    }
+
+    // Stable identifier of the error, never reuse or change a code once released
+    pub fn code(&self) -> u16 {
+        match self {
+            Errcode::NoPlayerKey => 1,
+            Errcode::PlayerNotFound(_) => 2,
+            Errcode::PlayerAlreadyExists(..) => 3,
+            Errcode::NoPlayerWithKey => 4,
+            Errcode::ShipNotFound(_) => 5,
+            Errcode::NotEnoughMoney(..) => 6,
+            Errcode::InvalidArgument(_) => 7,
+            Errcode::ShipNotExtracting => 8,
+            Errcode::ShipNotIdle => 9,
+            Errcode::CrewMemberNotIdle(_) => 10,
+            Errcode::CrewNotNeeded => 11,
+            Errcode::CannotPerformTravel => 12,
+            Errcode::NullDistance => 13,
+            Errcode::NoSuchStation(_) => 14,
+            Errcode::NoSuchModule(_) => 15,
+            Errcode::CannotExtractWithoutPlanet => 16,
+            Errcode::ShipNotInStation => 17,
+            Errcode::WrongCrewType(_) => 18,
+            Errcode::CargoFull => 19,
+            Errcode::NoTraderAssigned => 20,
+            Errcode::NoPilotAssigned => 21,
+            Errcode::BuyNothing => 22,
+            Errcode::SellNothing => 23,
+            Errcode::NoFuelInCargo => 24,
+            Errcode::NoHullPlateInCargo => 25,
+            Errcode::CrewMemberNotFound(_) => 26,
+            Errcode::PlayerLost => 27,
+            Errcode::NoSuchOrder(_) => 28,
+            Errcode::NoSuchAlert(_) => 29,
+            Errcode::TooManyAlerts(_) => 30,
+            Errcode::InvalidInvite => 31,
+            Errcode::NotAdmin => 32,
+            Errcode::RateLimited(_) => 33,
+            Errcode::GameNotStarted(_) => 34,
+            Errcode::GameOver(_) => 35,
+            Errcode::NoSuchSession(_) => 36,
+            Errcode::NoSuchWorld(_) => 37,
+            Errcode::StationAlreadyExists(_) => 38,
+        }
+    }
+
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            Errcode::NoPlayerKey
+            | Errcode::NoPlayerWithKey
+            | Errcode::PlayerLost
+            | Errcode::InvalidInvite
+            | Errcode::NotAdmin => ErrorCategory::Auth,
+            Errcode::PlayerNotFound(_)
+            | Errcode::ShipNotFound(_)
+            | Errcode::NoSuchStation(_)
+            | Errcode::NoSuchModule(_)
+            | Errcode::CrewMemberNotFound(_)
+            | Errcode::NoSuchOrder(_)
+            | Errcode::NoSuchAlert(_)
+            | Errcode::NoSuchSession(_)
+            | Errcode::NoSuchWorld(_) => ErrorCategory::NotFound,
+            Errcode::InvalidArgument(_) => ErrorCategory::InvalidArgument,
+            Errcode::NotEnoughMoney(..) => ErrorCategory::Funds,
+            Errcode::RateLimited(_) => ErrorCategory::RateLimited,
+            Errcode::PlayerAlreadyExists(..)
+            | Errcode::ShipNotExtracting
+            | Errcode::ShipNotIdle
+            | Errcode::CrewMemberNotIdle(_)
+            | Errcode::CrewNotNeeded
+            | Errcode::CannotPerformTravel
+            | Errcode::NullDistance
+            | Errcode::CannotExtractWithoutPlanet
+            | Errcode::ShipNotInStation
+            | Errcode::WrongCrewType(_)
+            | Errcode::CargoFull
+            | Errcode::NoTraderAssigned
+            | Errcode::NoPilotAssigned
+            | Errcode::BuyNothing
+            | Errcode::SellNothing
+            | Errcode::NoFuelInCargo
+            | Errcode::NoHullPlateInCargo
+            | Errcode::TooManyAlerts(_)
+            | Errcode::GameNotStarted(_)
+            | Errcode::GameOver(_)
+            | Errcode::StationAlreadyExists(_) => ErrorCategory::Conflict,
+        }
+    }
+
+    // Values carried by the error, with named fields
+    pub fn details(&self) -> Option<Value> {
+        Some(match self {
+            Errcode::PlayerNotFound(id) => json!({ "player": id }),
+            Errcode::PlayerAlreadyExists(id, name) => json!({ "player": id, "name": name }),
+            Errcode::ShipNotFound(id) => json!({ "ship": id }),
+            Errcode::NotEnoughMoney(have, need) => json!({ "have": have, "need": need }),
+            Errcode::InvalidArgument(arg) => json!({ "argument": arg }),
+            Errcode::CrewMemberNotIdle(id) => json!({ "crew": id }),
+            Errcode::NoSuchStation(id) => json!({ "station": id }),
+            Errcode::NoSuchModule(id) => json!({ "module": id }),
+            Errcode::WrongCrewType(ctype) => json!({ "expected": ctype }),
+            Errcode::CrewMemberNotFound(id) => json!({ "crew": id }),
+            Errcode::NoSuchOrder(id) => json!({ "order": id }),
+            Errcode::NoSuchAlert(id) => json!({ "alert": id }),
+            Errcode::TooManyAlerts(max) => json!({ "max": max }),
+            Errcode::RateLimited(retry) => json!({ "retry_after": retry }),
+            Errcode::GameNotStarted(start) => json!({ "start": start }),
+            Errcode::GameOver(id) => json!({ "session": id }),
+            Errcode::NoSuchSession(id) => json!({ "session": id }),
+            Errcode::NoSuchWorld(name) => json!({ "world": name }),
+            Errcode::StationAlreadyExists(id) => json!({ "station": id }),
+            _ => return None,
+        })
+    }
+}
+
+impl Serialize for Errcode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let details = self.details();
+        let mut s = serializer.serialize_struct("Errcode", 5)?;
+        s.serialize_field("error", &self.errmsg())?;
+        s.serialize_field("code", &self.code())?;
+        let errtype: &'static str = self.into();
+        s.serialize_field("type", errtype)?;
+        s.serialize_field("category", &self.category())?;
+        if let Some(ref details) = details {
+            s.serialize_field("details", details)?;
         }
+        s.end()
     }
 }
+
+#[test]
+fn test_errcode_serialize() {
+    let err = serde_json::to_value(Errcode::NotEnoughMoney(123.4, 500.0)).unwrap();
+    assert_eq!(err["code"], 6);
+    assert_eq!(err["type"], "NotEnoughMoney");
+    assert_eq!(err["category"], "funds");
+    assert_eq!(err["details"]["have"], 123.4);
+    assert_eq!(err["details"]["need"], 500.0);
+
+    let err = serde_json::to_value(Errcode::CargoFull).unwrap();
+    assert_eq!(err["type"], "CargoFull");
+    assert!(err.get("details").is_none());
+}
@@ -1,8 +1,12 @@
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use scan::ScanResult;
 use station::StationId;
-use std::collections::BTreeMap;
-use std::sync::{Arc, RwLock};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+
+use crate::lock::level::{self, Before};
+use crate::lock::{LockToken, OrderedLock};
 
 pub type SpaceUnit = u32;
 pub type SpaceCoord = (SpaceUnit, SpaceUnit, SpaceUnit);
@@ -20,13 +24,15 @@ pub mod scan;
 pub mod station;
 
 #[allow(dead_code)]
+#[derive(Clone)]
 pub enum SpaceObject {
-    BaseStation(Arc<RwLock<station::Station>>),
+    BaseStation(Arc<station::StationLock>),
     Planet(Arc<planet::Planet>),
 }
 
 struct GalaxyMap {
     objects: BTreeMap<SpaceCoord, SpaceObject>,
+    station_ids: BTreeSet<StationId>,
     discovered: Vec<GalaxySector>,
 }
 
@@ -34,12 +40,13 @@ impl GalaxyMap {
     pub fn empty() -> GalaxyMap {
         GalaxyMap {
             objects: BTreeMap::new(),
+            station_ids: BTreeSet::new(),
             discovered: vec![],
         }
     }
 
     // X, Y and Z can be any point from the given sector
-    pub fn generate_sector(&mut self, coord: &SpaceCoord) {
+    pub fn generate_sector<R: Rng>(&mut self, coord: &SpaceCoord, rng: &mut R) {
         let (x, y, z) = coord;
         let (secx, secy, secz) = compute_sector(*x, *y, *z);
         log::debug!(
@@ -51,12 +58,11 @@ impl GalaxyMap {
             secz.0,
             secz.1,
         );
-        let mut rng = rand::rng();
         for _ in 0..PLANETS_PER_SECTOR {
             let x = rng.random_range(secx.0..secx.1);
             let y = rng.random_range(secy.0..secy.1);
             let z = rng.random_range(secz.0..secz.1);
-            let planet = planet::Planet::random((x, y, z), &mut rng);
+            let planet = planet::Planet::random((x, y, z), rng);
             if self
                 .insert(&(x, y, z), SpaceObject::Planet(Arc::new(planet)))
                 .is_err()
@@ -108,35 +114,59 @@ impl GalaxyMap {
     }
 }
 
+// The random generator is only held for short moments, without taking any other lock
 #[derive(Clone)]
-pub struct Galaxy(Arc<RwLock<GalaxyMap>>);
+pub struct Galaxy(
+    Arc<OrderedLock<GalaxyMap, level::Galaxy>>,
+    Arc<OrderedLock<StdRng, level::GalaxyRng>>,
+);
 
 impl Galaxy {
-    pub fn init() -> Galaxy {
-        Galaxy(Arc::new(RwLock::new(GalaxyMap::empty())))
+    // The same seed generates the same stations and planets, for the same players
+    pub fn init(seed: u64) -> Galaxy {
+        Galaxy(
+            Arc::new(OrderedLock::new(GalaxyMap::empty())),
+            Arc::new(OrderedLock::new(StdRng::seed_from_u64(seed))),
+        )
     }
 
     // TODO (#11) Generate based on the galaxy
-    pub fn init_new_station(&self) -> (StationId, SpaceCoord) {
-        let mut rng = rand::rng();
-        let coord = (rng.random(), rng.random(), rng.random());
-        let id = rng.random();
-        let station = Arc::new(RwLock::new(station::Station::init(id, coord)));
+    pub fn init_new_station(
+        &self,
+        token: &mut LockToken<impl Before<level::Galaxy> + Before<level::GalaxyRng>>,
+    ) -> (StationId, SpaceCoord) {
+        let (coord, id) = {
+            let mut rng = self.1.write(token).unwrap();
+            ((rng.random(), rng.random(), rng.random()), rng.random())
+        };
+        let station = Arc::new(OrderedLock::new(station::Station::init(id, coord)));
 
-        let mut galaxy = self.0.write().unwrap();
+        // Station IDs key the markets, so both the position and the ID are rolled again on collision
+        let mut galaxy = self.0.write(token).unwrap();
+        if galaxy.station_ids.contains(&id) {
+            drop(galaxy);
+            return self.init_new_station(token);
+        }
         let res = galaxy.insert(&coord, SpaceObject::BaseStation(station));
         if res.is_err() {
-            return self.init_new_station();
+            drop(galaxy);
+            return self.init_new_station(token);
         }
+        galaxy.station_ids.insert(id);
         if !galaxy.is_discovered(&coord) {
-            galaxy.generate_sector(&coord);
+            let (galaxy, token) = galaxy.split();
+            galaxy.generate_sector(&coord, &mut *self.1.write(token).unwrap());
         }
 
         (id, coord)
     }
 
-    pub fn get_station(&self, coord: &SpaceCoord) -> Option<Arc<RwLock<station::Station>>> {
-        let galaxy = self.0.read().unwrap();
+    pub fn get_station(
+        &self,
+        token: &mut LockToken<impl Before<level::Galaxy>>,
+        coord: &SpaceCoord,
+    ) -> Option<Arc<station::StationLock>> {
+        let galaxy = self.0.read(token).unwrap();
         let obj = galaxy.get(coord)?;
         let SpaceObject::BaseStation(station) = obj else {
             return None;
@@ -144,8 +174,12 @@ impl Galaxy {
         Some(station.clone())
     }
 
-    pub fn get_planet(&self, coord: &SpaceCoord) -> Option<Arc<planet::Planet>> {
-        let galaxy = self.0.read().unwrap();
+    pub fn get_planet(
+        &self,
+        token: &mut LockToken<impl Before<level::Galaxy>>,
+        coord: &SpaceCoord,
+    ) -> Option<Arc<planet::Planet>> {
+        let galaxy = self.0.read(token).unwrap();
         let obj = galaxy.get(coord)?;
         let SpaceObject::Planet(planet) = obj else {
             return None;
@@ -153,13 +187,22 @@ impl Galaxy {
         Some(planet.clone())
     }
 
-    pub fn scan_sector(&self, rank: u8, center: &SpaceCoord) -> ScanResult {
+    // The stations come after the galaxy in the lock order, they are read once it is released
+    pub fn scan_sector(
+        &self,
+        token: &mut LockToken<impl Before<level::Galaxy> + Before<level::Station>>,
+        rank: u8,
+        center: &SpaceCoord,
+    ) -> ScanResult {
         let strengh = (rank - 1) as f64;
-        let mut results = ScanResult::empty();
+        let mut objects = vec![];
         for sector in sectors_around(center, strengh) {
-            for obj in self.0.read().unwrap().list_objects_in_sector(sector) {
-                results.add(rank, obj);
-            }
+            let galaxy = self.0.read(token).unwrap();
+            objects.extend(galaxy.list_objects_in_sector(sector).into_iter().cloned());
+        }
+        let mut results = ScanResult::empty();
+        for obj in objects.iter() {
+            results.add(token, rank, obj);
         }
         results
     }
@@ -2,6 +2,9 @@ use std::ops::Deref;
 
 use serde::{Deserialize, Serialize};
 
+use crate::lock::level::{self, Before};
+use crate::lock::LockToken;
+
 use super::planet::PlanetInfo;
 use super::station::StationInfo;
 use super::{get_distance, SpaceCoord, SpaceObject};
@@ -20,10 +23,15 @@ impl ScanResult {
         }
     }
 
-    pub fn add(&mut self, rank: u8, obj: &SpaceObject) {
+    pub fn add(
+        &mut self,
+        token: &mut LockToken<impl Before<level::Station>>,
+        rank: u8,
+        obj: &SpaceObject,
+    ) {
         match obj {
             SpaceObject::BaseStation(station) => {
-                let station = station.read().unwrap();
+                let station = station.read(token).unwrap();
                 self.stations.push(StationInfo::scan(rank, station.deref()));
             }
             SpaceObject::Planet(planet) => {
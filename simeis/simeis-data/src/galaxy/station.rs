@@ -2,7 +2,11 @@ use serde::{Deserialize, Serialize};
 
 use crate::crew::{Crew, CrewId, CrewMemberType};
 use crate::errors::Errcode;
+use crate::lock::level::{self, Before};
+use crate::lock::{LockToken, OrderedLock};
+use crate::market::orderbook::{Order, OrderRequest, OrderSide, Settlement};
 use crate::market::{Market, MarketTx};
+use crate::player::ledger::{LedgerCategory, LedgerEntity};
 use crate::player::Player;
 use crate::ship::cargo::ShipCargo;
 use crate::ship::module::ShipModuleId;
@@ -19,6 +23,7 @@ const CARGO_PRICE_INCDIV: f64 = 1000.0;
 const STATION_INIT_CARGO: f64 = 1000.0;
 
 pub type StationId = u16;
+pub type StationLock = OrderedLock<Station, level::Station>;
 
 // TODO (#43) Add refineries to create fuel & hull plate from raw resources
 #[derive(Serialize, Deserialize, Debug)]
@@ -46,6 +51,8 @@ pub struct Station {
     pub shipyard: Vec<Ship>,
     pub cargo: ShipCargo,
     pub trader: Option<CrewId>,
+    // Cargo bought on the order book that didn't fit, delivered once some space is freed
+    pub undelivered: Vec<(Resource, f64)>,
 }
 
 impl Station {
@@ -58,12 +65,18 @@ impl Station {
             shipyard: Ship::init_shipyard(position),
             cargo: ShipCargo::with_capacity(STATION_INIT_CARGO),
             trader: None,
+            undelivered: vec![],
         }
     }
 
     // TODO (#27) Allow to build improvements for the scanner
-    pub fn scan(&self, galaxy: &Galaxy) -> ScanResult {
-        galaxy.scan_sector(1, &self.position)
+    // The scan reads the stations around, this one included: it must not be locked
+    pub fn scan(
+        token: &mut LockToken<impl Before<level::Galaxy> + Before<level::Station>>,
+        galaxy: &Galaxy,
+        position: &SpaceCoord,
+    ) -> ScanResult {
+        galaxy.scan_sector(token, 1, position)
     }
 
     pub fn cargo_price(&self) -> f64 {
@@ -75,8 +88,10 @@ impl Station {
         if cost > player.money {
             return Err(Errcode::NotEnoughMoney(player.money, cost));
         }
-        player.money -= cost;
+        let entity = LedgerEntity::Station(self.id);
+        player.debit(cost, LedgerCategory::Equipment, Some(entity));
         self.cargo.capacity += *amnt as f64;
+        self.deliver_undelivered();
 
         Ok(&self.cargo)
     }
@@ -155,8 +170,14 @@ impl Station {
             return Err(Errcode::BuyNothing);
         }
 
-        let tx = market.buy(cm, resource, amnt);
-        player.money -= tx.removed_money.unwrap();
+        let tx = market.buy(&self.id, cm, resource, amnt)?;
+        let entity = Some(LedgerEntity::Resource(*resource));
+        player.debit(
+            tx.removed_money.unwrap() - tx.fees,
+            LedgerCategory::Trading,
+            entity,
+        );
+        player.debit(tx.fees, LedgerCategory::Fees, entity);
         let (r, a) = tx.added_cargo.unwrap();
         self.cargo.add_resource(&r, a);
         Ok(tx)
@@ -181,14 +202,149 @@ impl Station {
             return Err(Errcode::SellNothing);
         }
 
-        let tx = market.sell(cm, resource, amnt);
-        player.money += tx.added_money.unwrap();
+        let tx = market.sell(&self.id, cm, resource, amnt)?;
+        let entity = Some(LedgerEntity::Resource(*resource));
+        player.credit(
+            tx.added_money.unwrap() + tx.fees,
+            LedgerCategory::Trading,
+            entity,
+        );
+        player.debit(tx.fees, LedgerCategory::Fees, entity);
         let (r, a) = tx.removed_cargo.unwrap();
         let unloaded = self.cargo.unload(&r, a);
         debug_assert_eq!(unloaded, a);
+        self.deliver_undelivered();
         Ok(tx)
     }
 
+    // Escrows the money or cargo needed by the order, and places it on the order book
+    pub fn place_order(
+        &mut self,
+        req: &OrderRequest,
+        player: &mut Player,
+        market: &mut Market,
+        now: f64,
+    ) -> Result<(Order, Vec<MarketTx>), Errcode> {
+        if self.trader.is_none() {
+            return Err(Errcode::NoTraderAssigned);
+        }
+        req.check()?;
+
+        let resource = req.resource;
+        let amnt = match req.side {
+            OrderSide::Buy => {
+                let amnt = req.amount.min(self.cargo.space_for(&resource));
+                if amnt <= 0.0 {
+                    return Err(Errcode::BuyNothing);
+                }
+                let escrow = amnt * req.price;
+                if escrow > player.money {
+                    return Err(Errcode::NotEnoughMoney(player.money, escrow));
+                }
+                let entity = LedgerEntity::Resource(resource);
+                player.debit(escrow, LedgerCategory::Trading, Some(entity));
+                amnt
+            }
+            OrderSide::Sell => {
+                let Some(can_cargo) = self.cargo.resources.get(&resource) else {
+                    return Err(Errcode::SellNothing);
+                };
+                let amnt = req.amount.min(*can_cargo);
+                if amnt <= 0.0 {
+                    return Err(Errcode::SellNothing);
+                }
+                self.cargo.unload(&resource, amnt)
+            }
+        };
+
+        let (order, fills) = market.orderbook.place(
+            player.id, self.id, req.side, resource, req.price, amnt, req.ttl, now,
+        );
+        market.record_trades(fills.len() as u64);
+
+        let mut txs = vec![];
+        for fill in fills {
+            market.record_fill(&self.id, &resource, fill.price, fill.amount);
+            let cost = fill.amount * fill.price;
+            let (tx, delivered) = match req.side {
+                OrderSide::Buy => {
+                    // The escrow was computed with our limit price, give back the surplus
+                    let surplus = (req.price - fill.price) * fill.amount;
+                    if surplus > 0.0 {
+                        let entity = LedgerEntity::Order(order.id);
+                        player.credit(surplus, LedgerCategory::Trading, Some(entity));
+                    }
+                    let tx = MarketTx {
+                        added_cargo: Some((resource, fill.amount)),
+                        removed_money: Some(cost),
+                        ..Default::default()
+                    };
+                    let delivered = MarketTx {
+                        added_cargo: tx.added_cargo,
+                        ..Default::default()
+                    };
+                    (tx, delivered)
+                }
+                OrderSide::Sell => {
+                    let tx = MarketTx {
+                        removed_cargo: Some((resource, fill.amount)),
+                        added_money: Some(cost),
+                        ..Default::default()
+                    };
+                    let delivered = MarketTx {
+                        added_money: tx.added_money,
+                        ..Default::default()
+                    };
+                    (tx, delivered)
+                }
+            };
+            let settlement = Settlement {
+                order: order.id,
+                station: self.id,
+                tx: delivered,
+                fill: Some(fill),
+            };
+            self.apply_settlement(player, settlement);
+            txs.push(tx);
+        }
+        if req.side == OrderSide::Sell {
+            self.deliver_undelivered();
+        }
+        Ok((order, txs))
+    }
+
+    // Gives the money and cargo of a settlement to the player
+    // The cargo that doesn't fit in the station waits in it until some space is freed,
+    //     returns true if some had to wait
+    pub fn apply_settlement(&mut self, player: &mut Player, settlement: Settlement) -> bool {
+        if let Some(money) = settlement.tx.added_money {
+            let entity = LedgerEntity::Order(settlement.order);
+            player.credit(money, LedgerCategory::Trading, Some(entity));
+        }
+        let Some((r, amnt)) = settlement.tx.added_cargo else {
+            return false;
+        };
+        let added = self.cargo.add_resource(&r, amnt);
+        if added >= amnt {
+            return false;
+        }
+        self.undelivered.push((r, amnt - added));
+        true
+    }
+
+    // Moves the cargo waiting for some space into the station, as much as fits
+    fn deliver_undelivered(&mut self) {
+        if self.undelivered.is_empty() || self.cargo.is_full() {
+            return;
+        }
+        for (r, amnt) in std::mem::take(&mut self.undelivered) {
+            let added = self.cargo.add_resource(&r, amnt);
+            if added < amnt {
+                self.undelivered.push((r, amnt - added));
+            }
+        }
+    }
+
     pub fn refuel_ship(&mut self, ship: &mut Ship) -> Result<f64, Errcode> {
         let Some(qty) = self.cargo.resources.get(&Resource::Fuel) else {
             return Err(Errcode::NoFuelInCargo);
@@ -202,6 +358,7 @@ impl Station {
         let unloaded = self.cargo.unload(&Resource::Fuel, needed.min(*qty));
         ship.fuel_tank += unloaded;
         debug_assert!(ship.fuel_tank_capacity >= ship.fuel_tank);
+        self.deliver_undelivered();
         Ok(unloaded)
     }
 
@@ -228,6 +385,7 @@ impl Station {
         );
         debug_assert!(ship.hull_decay >= 0.0, "{}", ship.hull_decay);
         debug_assert!(unloaded >= 0.0, "{}", unloaded);
+        self.deliver_undelivered();
         Ok(unloaded)
     }
 
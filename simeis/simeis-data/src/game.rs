@@ -1,149 +1,1456 @@
 use base64::{prelude::BASE64_STANDARD, Engine};
-use std::collections::{BTreeMap, HashMap};
-use std::sync::mpsc::{Receiver, Sender, TryRecvError};
-use std::sync::{Arc, RwLock};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc};
 use std::thread::JoinHandle;
 use std::time::{Duration, Instant};
 
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
+use serde_json::json;
 
 use crate::errors::Errcode;
+use crate::galaxy::station::{Station, StationId};
 use crate::galaxy::Galaxy;
-use crate::market::{Market, MARKET_CHANGE_SEC};
-use crate::player::{Player, PlayerId, PlayerKey};
+use crate::lock::level::{self, Before, Unlocked};
+use crate::lock::{LockToken, OrderedLock, OrderedMutex};
+use crate::market::orderbook::{OrderId, Settlement};
+use crate::market::{Market, MarketPrices, MarketTx, MARKET_CHANGE_SEC};
+use crate::player::alert::{AlertKind, AlertRule};
+use crate::player::ledger::LedgerCategory;
+use crate::player::{Player, PlayerId, PlayerKey, PlayerLock};
+use crate::score::{
+    rank, RankEntry, Score, ScoreFormula, ScoreHistory, ScoreSnapshot, SCORE_SNAPSHOT_PERIOD,
+};
+use crate::session::{Session, SessionConfig, SessionPhase, Sessions};
+use crate::ship::resources::Resource;
 use crate::ship::ShipState;
-use crate::syslog::{SyslogEvent, SyslogFifo, SyslogRecv, SyslogSend};
+use crate::syslog::{
+    SyslogBuffer, SyslogEvent, SyslogHistories, SyslogNotify, SyslogRecv, SyslogSend, SyslogStats,
+    SYSLOG_HISTORY_DEFAULT_SIZE,
+};
 
-const ITER_PERIOD: Duration = Duration::from_millis(50);
+pub const ITER_PERIOD: Duration = Duration::from_millis(50);
+// Longest real time simulated by an iteration, beyond that the game slows down
+const MAX_ITER_GAP: Duration = Duration::from_secs(1);
+// The slow iterations are logged as a summary, at most once per period
+const OVERRUN_LOG_PERIOD: Duration = Duration::from_secs(10);
+
+// Part of the fuel tank / hull left under which the player is warned
+const LOW_FUEL_RATIO: f64 = 20.0 / 100.0;
+const LOW_HULL_RATIO: f64 = 20.0 / 100.0;
+
+// Below this number of players per worker, the chunk isn't worth sending to another thread
+const PLAYERS_PER_WORKER_MIN: usize = 64;
+
+// Time elapsed in the game simulation, in seconds, advanced by the game thread
+#[derive(Clone, Default)]
+pub struct GameClock(Arc<AtomicU64>);
+
+impl GameClock {
+    pub fn now(&self) -> f64 {
+        f64::from_bits(self.0.load(Ordering::Acquire))
+    }
+
+    fn advance(&self, tdelta: f64) {
+        let now = self.now() + tdelta;
+        self.0.store(now.to_bits(), Ordering::Release);
+    }
+}
+
+// Time taken by the iterations of the game thread, in seconds
+#[derive(Default)]
+pub struct TickStats {
+    iterations: AtomicU64,
+    overruns: AtomicU64,
+    total: AtomicU64,
+    last: AtomicU64,
+    max: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl TickStats {
+    fn record(&self, took: Duration) {
+        let took = took.as_secs_f64();
+        self.iterations.fetch_add(1, Ordering::Relaxed);
+        if took > ITER_PERIOD.as_secs_f64() {
+            self.overruns.fetch_add(1, Ordering::Relaxed);
+        }
+        let total = self.total() + took;
+        self.total.store(total.to_bits(), Ordering::Relaxed);
+        self.last.store(took.to_bits(), Ordering::Relaxed);
+        if took > self.max() {
+            self.max.store(took.to_bits(), Ordering::Relaxed);
+        }
+    }
+
+    pub fn iterations(&self) -> u64 {
+        self.iterations.load(Ordering::Relaxed)
+    }
+
+    // Iterations that took longer than ITER_PERIOD
+    pub fn overruns(&self) -> u64 {
+        self.overruns.load(Ordering::Relaxed)
+    }
+
+    pub fn total(&self) -> f64 {
+        f64::from_bits(self.total.load(Ordering::Relaxed))
+    }
+
+    pub fn last(&self) -> f64 {
+        f64::from_bits(self.last.load(Ordering::Relaxed))
+    }
+
+    pub fn max(&self) -> f64 {
+        f64::from_bits(self.max.load(Ordering::Relaxed))
+    }
+
+    fn record_dropped(&self, gap: Duration) {
+        let dropped = self.dropped() + gap.as_secs_f64();
+        self.dropped.store(dropped.to_bits(), Ordering::Relaxed);
+    }
+
+    // Real time that was not simulated because an iteration came too late
+    pub fn dropped(&self) -> f64 {
+        f64::from_bits(self.dropped.load(Ordering::Relaxed))
+    }
+}
+
+pub struct GameConfig {
+    // Number of events kept in the syslog of each player
+    pub syslog_history: usize,
+    // Key giving access to the admin endpoints, disabled if not set
+    pub admin_key: Option<String>,
+    // If set, new players need an invite token issued by an admin
+    pub require_invite: bool,
+    // If set, players whose name starts with "test-rich" start with a lot more money
+    pub rich_players: bool,
+    // Directory the admin snapshots are written in, if set
+    pub snapshot_dir: Option<String>,
+    pub score_formula: ScoreFormula,
+    pub session: SessionConfig,
+    // Game seconds simulated per real second
+    pub time_scale: f64,
+    // Threads updating the players in parallel at each step
+    pub workers: usize,
+    // Seed of the galaxy and of the market changes, random if not set
+    pub seed: Option<u64>,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        GameConfig {
+            syslog_history: SYSLOG_HISTORY_DEFAULT_SIZE,
+            admin_key: None,
+            require_invite: false,
+            rich_players: false,
+            snapshot_dir: None,
+            score_formula: ScoreFormula::default(),
+            session: SessionConfig::default(),
+            time_scale: 1.0,
+            workers: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            seed: None,
+        }
+    }
+}
+
+// What the simulation keeps from one step to the next
+struct StepState {
+    syslog: SyslogRecv,
+    rng: StdRng,
+    // Game time of the last change of the market prices
+    market_last_tick: f64,
+}
 
 // TODO (#23) Have a global "inflation" rate for all users, that increases over time
 //     Equipment becomes more and more expansive
 
 #[derive(Clone)]
 pub struct Game {
-    pub players: Arc<RwLock<BTreeMap<PlayerId, Arc<RwLock<Player>>>>>,
-    pub player_index: Arc<RwLock<HashMap<PlayerKey, PlayerId>>>,
+    pub players: Arc<OrderedLock<BTreeMap<PlayerId, Arc<PlayerLock>>, level::Players>>,
+    pub player_index: Arc<OrderedLock<HashMap<PlayerKey, PlayerId>, level::PlayerIndex>>,
     pub galaxy: Galaxy,
-    pub market: Arc<RwLock<Market>>,
+    pub market: Arc<OrderedLock<Market, level::Market>>,
     pub syslog: SyslogSend,
-    pub fifo_events: SyslogFifo,
+    pub syslog_history: SyslogHistories,
+    pub syslog_notify: SyslogNotify,
+    pub syslog_stats: Arc<SyslogStats>,
     pub tstart: f64,
-    send_stop: Sender<bool>,
+    pub clock: GameClock,
+    pub tick_stats: Arc<TickStats>,
+    pub score_formula: ScoreFormula,
+    pub score_history: Arc<OrderedLock<ScoreHistory, level::Scores>>,
+    pub sessions: Arc<OrderedLock<Sessions, level::Sessions>>,
+    pub snapshot_dir: Option<String>,
+    admin_key: Option<String>,
+    require_invite: bool,
+    rich_players: bool,
+    invites: Arc<OrderedLock<HashSet<String>, level::Invites>>,
+    next_player_id: Arc<AtomicU32>,
+    paused: Arc<AtomicBool>,
+    time_scale: Arc<AtomicU64>,
+    workers: usize,
+    pool: Arc<WorkerPool>,
+    // Locked during a whole step, before any other lock
+    step_state: Arc<OrderedMutex<StepState, level::Step>>,
+    stopped: Arc<AtomicBool>,
+}
+
+// A player to update during a step, with what its orders got since the last one
+type PlayerUpdate = (Arc<PlayerLock>, Vec<Settlement>);
+
+// Chunk of players updated by a worker during a step
+struct WorkerJob {
+    // Dropped once the job is done, so that the workers don't keep the game alive
+    game: Game,
+    players: Vec<PlayerUpdate>,
+    prices: Arc<MarketPrices>,
+    tdelta: f64,
+    index: usize,
+    reply: mpsc::Sender<(usize, SyslogBuffer)>,
+}
+
+// Threads started with the game, that exit once the game is dropped
+struct WorkerPool(Vec<mpsc::Sender<WorkerJob>>);
+
+impl WorkerPool {
+    fn start(nthreads: usize) -> WorkerPool {
+        let senders = (0..nthreads)
+            .map(|n| {
+                let (send, recv) = mpsc::channel::<WorkerJob>();
+                std::thread::Builder::new()
+                    .name(format!("game-worker-{n}"))
+                    .spawn(move || {
+                        for job in recv {
+                            let mut token = LockToken::root();
+                            let buffer = job.game.update_players(
+                                &mut token,
+                                job.players,
+                                &job.prices,
+                                job.tdelta,
+                            );
+                            let _ = job.reply.send((job.index, buffer));
+                        }
+                    })
+                    .expect("Unable to start a game worker");
+                send
+            })
+            .collect();
+        WorkerPool(senders)
+    }
+
+    fn send(&self, worker: usize, job: WorkerJob) {
+        self.0[worker % self.0.len()]
+            .send(job)
+            .expect("A player update worker died");
+    }
 }
 
 impl Game {
-    pub fn init() -> (JoinHandle<()>, Game) {
-        let (send_stop, recv_stop) = std::sync::mpsc::channel();
-        let (syssend, sysrecv) = SyslogSend::channel();
+    // Game simulated by a background thread, in real time
+    pub fn init(config: GameConfig) -> (JoinHandle<()>, Game) {
+        let game = Game::new(config);
+        let thread_game = game.clone();
+        let thread = std::thread::spawn(move || thread_game.run());
+        (thread, game)
+    }
+
+    // Game that only moves when `step` is called
+    pub fn new(config: GameConfig) -> Game {
+        let mut rng = StdRng::seed_from_u64(config.seed.unwrap_or_else(|| rand::rng().random()));
+        let clock = GameClock::default();
+        let (syssend, sysrecv) = SyslogSend::channel(config.syslog_history, clock.clone());
         let tstart = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .unwrap()
             .as_secs_f64();
-        let data = Game {
-            send_stop,
-            galaxy: Galaxy::init(),
-            market: Arc::new(RwLock::new(Market::init())),
-            players: Arc::new(RwLock::new(BTreeMap::new())),
-            player_index: Arc::new(RwLock::new(HashMap::new())),
+        Game {
+            galaxy: Galaxy::init(rng.random()),
+            market: Arc::new(OrderedLock::new(Market::init(clock.clone()))),
+            players: Arc::new(OrderedLock::new(BTreeMap::new())),
+            player_index: Arc::new(OrderedLock::new(HashMap::new())),
             syslog: syssend.clone(),
-            fifo_events: sysrecv.fifo.clone(),
+            syslog_history: sysrecv.history.clone(),
+            syslog_notify: sysrecv.notify.clone(),
+            syslog_stats: syssend.stats(),
             tstart,
-        };
-        let thread_data = data.clone();
-
-        let thread = std::thread::spawn(move || thread_data.start(recv_stop, sysrecv));
-        (thread, data)
+            clock,
+            tick_stats: Arc::new(TickStats::default()),
+            score_formula: config.score_formula,
+            score_history: Arc::new(OrderedLock::new(ScoreHistory::default())),
+            sessions: Arc::new(OrderedLock::new(Sessions::new(config.session))),
+            admin_key: config.admin_key,
+            require_invite: config.require_invite,
+            rich_players: config.rich_players,
+            snapshot_dir: config.snapshot_dir,
+            invites: Arc::new(OrderedLock::new(HashSet::new())),
+            next_player_id: Arc::new(AtomicU32::new(1)),
+            paused: Arc::new(AtomicBool::new(false)),
+            time_scale: Arc::new(AtomicU64::new(config.time_scale.to_bits())),
+            workers: config.workers.max(1),
+            pool: Arc::new(WorkerPool::start(config.workers.max(1) - 1)),
+            step_state: Arc::new(OrderedMutex::new(StepState {
+                syslog: sysrecv,
+                rng: StdRng::seed_from_u64(rng.random()),
+                market_last_tick: 0.0,
+            })),
+            stopped: Arc::new(AtomicBool::new(false)),
+        }
     }
 
-    pub fn start(&self, stop: Receiver<bool>, syslog: SyslogRecv) {
+    fn run(&self) {
         log::debug!("Started thread");
-        let sleepmin_iter = ITER_PERIOD;
+        let mut token = LockToken::root();
         let mut last_iter = Instant::now();
-        let mut market_last_tick = Instant::now();
-        let mut rng = rand::rng();
-        while stop.try_recv().is_err_and(|x| x == TryRecvError::Empty) {
-            self.threadloop(&mut rng, &mut market_last_tick, &syslog);
-            let took = Instant::now() - last_iter;
-            std::thread::sleep(sleepmin_iter.saturating_sub(took));
-            last_iter = Instant::now();
+        let mut last_log = Instant::now();
+        let (mut overruns, mut longest, mut dropped) =
+            (0, Duration::ZERO, self.tick_stats.dropped());
+        while !self.stopped.load(Ordering::Acquire) {
+            let tick = Instant::now();
+            let elapsed = tick - last_iter;
+            last_iter = tick;
+            if self.step_elapsed(&mut token, elapsed) > 0 {
+                let took = tick.elapsed();
+                self.tick_stats.record(took);
+                if took > ITER_PERIOD {
+                    overruns += 1;
+                    longest = longest.max(took);
+                }
+            }
+            if last_log.elapsed() >= OVERRUN_LOG_PERIOD {
+                let not_simulated = self.tick_stats.dropped() - dropped;
+                if overruns > 0 || not_simulated > 0.0 {
+                    log::warn!(
+                        "{overruns} game iterations took longer than {ITER_PERIOD:?} in the last {:?} (longest {longest:?}), {not_simulated:.3}s not simulated",
+                        last_log.elapsed()
+                    );
+                }
+                last_log = Instant::now();
+                (overruns, longest, dropped) = (0, Duration::ZERO, self.tick_stats.dropped());
+            }
+            std::thread::sleep(ITER_PERIOD.saturating_sub(tick.elapsed()));
         }
         log::info!("Exiting game thread");
     }
 
-    fn threadloop<R: Rng>(&self, rng: &mut R, mlt: &mut Instant, syslog: &SyslogRecv) {
-        let market_change_proba = (mlt.elapsed().as_secs_f64() / MARKET_CHANGE_SEC).min(1.0);
+    // Simulates a real time duration, in steps of about ITER_PERIOD so that
+    //     a late iteration doesn't make the ships jump too far at once
+    // Beyond MAX_ITER_GAP the game slows down, returns the number of steps simulated
+    fn step_elapsed(&self, token: &mut LockToken<level::Root>, mut elapsed: Duration) -> u32 {
+        if elapsed > MAX_ITER_GAP {
+            self.tick_stats.record_dropped(elapsed - MAX_ITER_GAP);
+            elapsed = MAX_ITER_GAP;
+        }
+        let nsteps = (elapsed.as_secs_f64() / ITER_PERIOD.as_secs_f64())
+            .round()
+            .max(1.0) as u32;
+        let tdelta = elapsed.as_secs_f64() * self.time_scale() / nsteps as f64;
+        for n in 0..nsteps {
+            if !self.step(token, tdelta) {
+                return n;
+            }
+        }
+        nsteps
+    }
+
+    // Game time simulated by an iteration of the game thread that is on time
+    pub fn tick_delta(&self) -> f64 {
+        ITER_PERIOD.as_secs_f64() * self.time_scale()
+    }
+
+    // Simulates `tdelta` seconds of game time, returns false if the game is paused or over
+    pub fn step(&self, token: &mut LockToken<level::Root>, tdelta: f64) -> bool {
+        let mut state = self.step_state.lock(token).unwrap();
+        let (state, token) = state.split();
+        let over = self.sessions.read(token).unwrap().current.phase == SessionPhase::Over;
+        if self.is_paused() || over {
+            // Nothing moves, but the events sent by the API still get delivered
+            state.syslog.update(token);
+            return false;
+        }
+        let StepState {
+            syslog,
+            rng,
+            market_last_tick,
+        } = state;
+        self.threadloop(token, tdelta, rng, market_last_tick, syslog);
+        self.update_session(token, syslog);
+        true
+    }
+
+    // Steps by ITER_PERIOD until the condition holds, or `timeout` seconds of game time passed
+    // Returns whether the condition holds
+    pub fn advance_until<F>(
+        &self,
+        token: &mut LockToken<level::Root>,
+        timeout: f64,
+        mut cond: F,
+    ) -> bool
+    where
+        F: FnMut(&mut LockToken<level::Root>, &Game) -> bool,
+    {
+        let tend = self.clock.now() + timeout;
+        while !cond(token, self) {
+            if self.clock.now() >= tend || !self.step(token, ITER_PERIOD.as_secs_f64()) {
+                return cond(token, self);
+            }
+        }
+        true
+    }
+
+    fn threadloop<R: Rng>(
+        &self,
+        token: &mut LockToken<level::Step>,
+        tdelta: f64,
+        rng: &mut R,
+        mlt: &mut f64,
+        syslog: &SyslogRecv,
+    ) {
+        // What was sent before this step comes first in the syslogs
+        syslog.update(token);
+        self.clock.advance(tdelta);
+        let market_change_proba = ((self.clock.now() - *mlt) / MARKET_CHANGE_SEC).min(1.0);
         if rng.random_bool(market_change_proba) {
-            self.market.write().unwrap().update_prices(rng);
-            *mlt = Instant::now();
-        }
-
-        for (player_id, player) in self.players.read().unwrap().iter() {
-            let mut player = player.write().unwrap();
-            player.update_money(syslog, ITER_PERIOD.as_secs_f64());
-
-            let mut deadship = vec![];
-            for (id, ship) in player.ships.iter_mut() {
-                match ship.state {
-                    ShipState::InFlight(..) => {
-                        let finished = ship.update_flight(ITER_PERIOD.as_secs_f64());
-                        if finished {
-                            ship.state = ShipState::Idle;
-                            if ship.hull_decay >= ship.hull_decay_capacity {
-                                deadship.push(*id);
-                            } else {
-                                syslog.event(*player_id, SyslogEvent::ShipFlightFinished(*id));
-                            }
-                        }
+            let spikes = self.market.write(token).unwrap().update_prices(rng);
+            *mlt = self.clock.now();
+            for spike in spikes {
+                if let Some(owner) = self.station_owner(token, &spike.station) {
+                    syslog.event(token, owner, SyslogEvent::PriceSpike(spike));
+                }
+            }
+        }
+        // The market is locked once per step, the players are then updated without it
+        let (expired, mut settlements, prices) = {
+            let mut market = self.market.write(token).unwrap();
+            let expired = market.orderbook.expire(self.clock.now());
+            let settlements = market.orderbook.take_all_settlements();
+            (expired, settlements, Arc::new(market.prices()))
+        };
+        for order in expired {
+            let evt = SyslogEvent::OrderExpired {
+                station: order.station,
+                order: order.id,
+            };
+            syslog.event(token, order.player, evt);
+        }
+
+        let mut guard = self.players.read(token).unwrap();
+        let (all_players, players_token) = guard.split();
+        let players = all_players
+            .iter()
+            .collect::<Vec<(&PlayerId, &Arc<PlayerLock>)>>();
+        let mut updates = |chunk: &[(&PlayerId, &Arc<PlayerLock>)]| {
+            chunk
+                .iter()
+                .map(|(id, p)| ((*p).clone(), settlements.remove(id).unwrap_or_default()))
+                .collect::<Vec<PlayerUpdate>>()
+        };
+        let chunk_size = players
+            .len()
+            .div_ceil(self.workers)
+            .max(PLAYERS_PER_WORKER_MIN);
+        let buffers = if players.len() <= chunk_size {
+            vec![self.update_players(players_token, updates(&players), &prices, tdelta)]
+        } else {
+            // The step thread updates the first chunk while the workers update the others
+            let mut chunks = players.chunks(chunk_size);
+            let first = updates(chunks.next().unwrap());
+            let (reply, results) = mpsc::channel();
+            let nsent = chunks
+                .enumerate()
+                .map(|(n, chunk)| {
+                    self.pool.send(
+                        n,
+                        WorkerJob {
+                            game: self.clone(),
+                            players: updates(chunk),
+                            prices: prices.clone(),
+                            tdelta,
+                            index: n + 1,
+                            reply: reply.clone(),
+                        },
+                    )
+                })
+                .count();
+            drop(reply);
+            let first = self.update_players(players_token, first, &prices, tdelta);
+            let mut buffers = vec![(0, first)];
+            buffers.extend(results.iter().take(nsent));
+            assert_eq!(buffers.len(), nsent + 1, "A player update worker died");
+            buffers.sort_by_key(|(n, _)| *n);
+            buffers.into_iter().map(|(_, b)| b).collect()
+        };
+        drop(players);
+        drop(guard);
+        // The players are split in chunks in their order, so are their events
+        for buffer in buffers {
+            syslog.flush(token, buffer);
+        }
+
+        let last_scores = self.score_history.read(token).unwrap().last_time();
+        if last_scores.is_none_or(|t| self.clock.now() - t >= SCORE_SNAPSHOT_PERIOD) {
+            self.record_scores(token);
+        }
+        syslog.update(token);
+    }
+
+    // Runs on the workers, the events are kept to be delivered in order once they are all done
+    fn update_players(
+        &self,
+        token: &mut LockToken<impl Before<level::Player>>,
+        players: Vec<PlayerUpdate>,
+        prices: &MarketPrices,
+        tdelta: f64,
+    ) -> SyslogBuffer {
+        let syslog = SyslogBuffer::default();
+        for (player, settlements) in players {
+            let mut player = player.write(token).unwrap();
+            let (player, token) = player.split();
+            self.update_player(token, player, settlements, prices, tdelta, &syslog);
+        }
+        syslog
+    }
+
+    fn update_player(
+        &self,
+        token: &mut LockToken<level::Player>,
+        player: &mut Player,
+        settlements: Vec<Settlement>,
+        prices: &MarketPrices,
+        tdelta: f64,
+        syslog: &SyslogBuffer,
+    ) {
+        let player_id = player.id;
+        player.update_money(syslog, tdelta);
+        self.deliver_settlements(token, player, settlements, syslog);
+
+        let mut deadship = vec![];
+        for (id, ship) in player.ships.iter_mut() {
+            match ship.state {
+                ShipState::InFlight(..) => {
+                    let fuel_before = ship.fuel_tank;
+                    let hull_before = ship.hull_decay_capacity - ship.hull_decay;
+                    let finished = ship.update_flight(tdelta);
+
+                    let fuel_low = ship.fuel_tank_capacity * LOW_FUEL_RATIO;
+                    if fuel_before > fuel_low && ship.fuel_tank <= fuel_low {
+                        let evt = SyslogEvent::LowFuel {
+                            ship: *id,
+                            left: ship.fuel_tank,
+                            capacity: ship.fuel_tank_capacity,
+                        };
+                        syslog.event(player_id, evt);
+                    }
+                    let hull_left = ship.hull_decay_capacity - ship.hull_decay;
+                    let hull_low = ship.hull_decay_capacity * LOW_HULL_RATIO;
+                    if hull_before > hull_low && hull_left <= hull_low && hull_left > 0.0 {
+                        let evt = SyslogEvent::LowHull {
+                            ship: *id,
+                            left: hull_left,
+                            capacity: ship.hull_decay_capacity,
+                        };
+                        syslog.event(player_id, evt);
                     }
 
-                    ShipState::Extracting(..) => {
-                        let finished = ship.update_extract(ITER_PERIOD.as_secs_f64());
-                        if finished {
-                            ship.state = ShipState::Idle;
-                            syslog.event(*player_id, SyslogEvent::ExtractionStopped(*id));
+                    if finished {
+                        ship.state = ShipState::Idle;
+                        if ship.hull_decay >= ship.hull_decay_capacity {
+                            deadship.push(*id);
+                        } else {
+                            syslog.event(player_id, SyslogEvent::ShipFlightFinished(*id));
                         }
                     }
-                    _ => {}
                 }
+
+                ShipState::Extracting(..) => {
+                    let finished = ship.update_extract(tdelta);
+                    if finished {
+                        ship.state = ShipState::Idle;
+                        syslog.event(player_id, SyslogEvent::ExtractionStopped(*id));
+                    }
+                }
+                _ => {}
             }
-            for id in deadship {
-                syslog.event(*player_id, SyslogEvent::ShipDestroyed(id));
-                player.ships.remove(&id);
+        }
+        for id in deadship {
+            syslog.event(player_id, SyslogEvent::ShipDestroyed(id));
+            player.ships.remove(&id);
+        }
+        self.check_alerts(token, player, prices, syslog);
+    }
+
+    fn check_alerts(
+        &self,
+        token: &mut LockToken<level::Player>,
+        player: &mut Player,
+        prices: &MarketPrices,
+        syslog: &SyslogBuffer,
+    ) {
+        if player.alerts.is_empty() {
+            return;
+        }
+        let mut alerts = std::mem::take(&mut player.alerts);
+        let fired = alerts.evaluate(|rule| self.alert_value(token, player, prices, rule));
+        player.alerts = alerts;
+        for (alert, value) in fired {
+            let evt = SyslogEvent::AlertTriggered {
+                alert: alert.id,
+                rule: alert.rule,
+                value,
+            };
+            syslog.event(player.id, evt);
+        }
+    }
+
+    // Current value of what the rule is watching, the worst one if it applies to several items
+    fn alert_value(
+        &self,
+        token: &mut LockToken<level::Player>,
+        player: &Player,
+        prices: &MarketPrices,
+        rule: &AlertRule,
+    ) -> Option<f64> {
+        let ships = player
+            .ships
+            .iter()
+            .filter(|(id, _)| rule.ship.is_none_or(|s| s == **id))
+            .map(|(_, ship)| ship);
+        match rule.kind {
+            AlertKind::MoneyBelow => Some(player.money),
+            AlertKind::FuelBelow => ships
+                .map(|ship| (ship.fuel_tank / ship.fuel_tank_capacity) * 100.0)
+                .reduce(f64::min),
+            AlertKind::HullDecayAbove => ships
+                .map(|ship| (ship.hull_decay / ship.hull_decay_capacity) * 100.0)
+                .reduce(f64::max),
+            AlertKind::PriceAbove | AlertKind::PriceBelow => {
+                let resource = rule.resource?;
+                match rule.station {
+                    Some(id) => prices.stations.get(&id)?.get(&resource).copied(),
+                    None => prices.average.get(&resource).copied(),
+                }
             }
+            AlertKind::CargoAbove => player
+                .stations
+                .iter()
+                .filter(|(id, _)| rule.station.is_none_or(|s| s == **id))
+                .filter_map(|(_, coord)| {
+                    let station = self.galaxy.get_station(token, coord)?;
+                    let cargo = &station.read(token).unwrap().cargo;
+                    Some((cargo.usage / cargo.capacity) * 100.0)
+                })
+                .reduce(f64::max),
         }
+    }
 
-        syslog.update();
+    // Players can only act while the match of the current session is running
+    pub fn check_session(
+        &self,
+        token: &mut LockToken<impl Before<level::Sessions>>,
+    ) -> Result<(), Errcode> {
+        let session = &self.sessions.read(token).unwrap().current;
+        match session.phase {
+            SessionPhase::Lobby => Err(Errcode::GameNotStarted(session.start)),
+            SessionPhase::Running => Ok(()),
+            SessionPhase::Over => Err(Errcode::GameOver(session.id)),
+        }
+    }
+
+    fn update_session(&self, token: &mut LockToken<level::Step>, syslog: &SyslogRecv) {
+        let now = self.clock.now();
+        let Some(phase) = self.sessions.write(token).unwrap().update(now) else {
+            return;
+        };
+        let players = self
+            .players
+            .read(token)
+            .unwrap()
+            .keys()
+            .copied()
+            .collect::<Vec<PlayerId>>();
+        match phase {
+            SessionPhase::Lobby => {}
+            SessionPhase::Running => {
+                let id = self.sessions.read(token).unwrap().current.id;
+                log::info!("Session {id} started");
+                for player in players {
+                    syslog.event(token, player, SyslogEvent::SessionStarted(id));
+                }
+            }
+            SessionPhase::Over => {
+                let ranking = self.leaderboard(token);
+                let session = self.sessions.write(token).unwrap().finish(ranking);
+                log::info!("Session {} is over", session.id);
+                for entry in session.ranking.iter().flatten() {
+                    let evt = SyslogEvent::SessionEnded {
+                        session: session.id,
+                        rank: entry.rank,
+                    };
+                    syslog.event(token, entry.player, evt);
+                }
+                self.export_session(token, &session);
+
+                if self.sessions.read(token).unwrap().config.rollover {
+                    for player in players {
+                        let _ = self.reset_player(token, &player);
+                    }
+                    *self.score_history.write(token).unwrap() = ScoreHistory::default();
+                    self.sessions.write(token).unwrap().rollover(now);
+                }
+            }
+        }
+    }
+
+    fn export_session(
+        &self,
+        token: &mut LockToken<impl Before<level::Sessions>>,
+        session: &Session,
+    ) {
+        let Some(dir) = self.sessions.read(token).unwrap().config.export_dir.clone() else {
+            return;
+        };
+        let path = std::path::Path::new(&dir).join(format!("session-{}.json", session.id));
+        let written = std::fs::create_dir_all(&dir)
+            .and_then(|_| std::fs::write(&path, serde_json::to_string(session).unwrap()));
+        if let Err(e) = written {
+            log::error!("Unable to export session to {}: {e}", path.display());
+        }
+    }
+
+    // Players ranked by their score, computed with the formula of the game
+    pub fn leaderboard(&self, token: &mut LockToken<impl Unlocked>) -> Vec<RankEntry> {
+        // Prices are copied first, the market lock comes after the players and the stations
+        //     in the lock order, so none of them can be locked while holding it
+        let prices = self.market.read(token).unwrap().prices();
+        let value = |prices: &BTreeMap<Resource, f64>, cargo: &BTreeMap<Resource, f64>| {
+            cargo
+                .iter()
+                .map(|(r, amnt)| amnt * prices.get(r).copied().unwrap_or(r.base_price()))
+                .sum::<f64>()
+        };
+
+        let mut entries = vec![];
+        let mut players = self.players.read(token).unwrap();
+        let (players, token) = players.split();
+        for player in players.values() {
+            let mut player = player.read(token).unwrap();
+            let (player, token) = player.split();
+            let mut score = Score {
+                money: player.money,
+                ..Default::default()
+            };
+            for ship in player.ships.values() {
+                score.ships += ship.compute_price();
+                score.cargo += value(&prices.average, &ship.cargo.resources);
+                score.crew += ship.crew.sum_ranks_value();
+            }
+            for (id, coord) in player.stations.iter() {
+                let station = self.galaxy.get_station(token, coord).unwrap();
+                let station = station.read(token).unwrap();
+                let station_prices = prices.stations.get(id).unwrap_or(&prices.average);
+                score.cargo += value(station_prices, &station.cargo.resources);
+                score.crew += station.crew.sum_ranks_value();
+                score.crew += station.idle_crew.sum_ranks_value();
+            }
+            score.compute_total(&self.score_formula);
+            entries.push(RankEntry {
+                rank: 0,
+                player: player.id,
+                name: player.name.clone(),
+                lost: player.lost,
+                score,
+            });
+        }
+        rank(entries)
+    }
+
+    fn record_scores(&self, token: &mut LockToken<impl Unlocked>) {
+        let scores = self
+            .leaderboard(token)
+            .into_iter()
+            .map(|entry| (entry.player, entry.score.total))
+            .collect();
+        let snapshot = ScoreSnapshot {
+            time: self.clock.now(),
+            scores,
+        };
+        self.score_history.write(token).unwrap().push(snapshot);
+    }
+
+    fn station_owner(
+        &self,
+        token: &mut LockToken<impl Before<level::Players>>,
+        station: &StationId,
+    ) -> Option<PlayerId> {
+        let mut players = self.players.read(token).unwrap();
+        let (players, token) = players.split();
+        players
+            .iter()
+            .find(|(_, p)| p.read(token).unwrap().stations.contains_key(station))
+            .map(|(id, _)| *id)
+    }
+
+    // Delivers to the player what its orders on the order book got
+    fn deliver_settlements(
+        &self,
+        token: &mut LockToken<level::Player>,
+        player: &mut Player,
+        settlements: Vec<Settlement>,
+        syslog: &SyslogBuffer,
+    ) {
+        for settlement in settlements {
+            let Some(coord) = player.stations.get(&settlement.station) else {
+                continue;
+            };
+            let station = self.galaxy.get_station(token, coord).unwrap();
+            let mut station = station.write(token).unwrap();
+            if settlement.fill.is_some() {
+                let evt = SyslogEvent::TradeExecuted {
+                    station: settlement.station,
+                    order: Some(settlement.order),
+                    tx: settlement.tx.clone(),
+                };
+                syslog.event(player.id, evt);
+            }
+            if station.apply_settlement(player, settlement) {
+                let evt = SyslogEvent::StationCargoFull {
+                    station: station.id,
+                    cargo: station.cargo.clone(),
+                };
+                syslog.event(player.id, evt);
+            }
+        }
+    }
+
+    pub fn cancel_order(
+        &self,
+        token: &mut LockToken<level::Player>,
+        player: &mut Player,
+        id: &OrderId,
+    ) -> Result<MarketTx, Errcode> {
+        let settlement = self
+            .market
+            .write(token)
+            .unwrap()
+            .orderbook
+            .cancel(&player.id, id)?;
+        let Some(coord) = player.stations.get(&settlement.station) else {
+            return Err(Errcode::NoSuchStation(settlement.station));
+        };
+        let station = self.galaxy.get_station(token, coord).unwrap();
+        let mut station = station.write(token).unwrap();
+        let refund = MarketTx {
+            added_money: settlement.tx.added_money,
+            added_cargo: settlement.tx.added_cargo,
+            ..Default::default()
+        };
+        if station.apply_settlement(player, settlement) {
+            let evt = SyslogEvent::StationCargoFull {
+                station: station.id,
+                cargo: station.cargo.clone(),
+            };
+            self.syslog.event(&player.id, evt);
+        }
+        Ok(refund)
     }
 
     pub fn stop(self, handle: JoinHandle<()>) {
         log::info!("Asking game thread to exit");
-        self.send_stop.send(true).unwrap();
+        self.stopped.store(true, Ordering::Release);
         let _ = handle.join();
         log::info!("Game stopped");
     }
 
-    pub fn new_player<T: ToString>(&self, name: T) -> Result<(PlayerId, String), Errcode> {
+    pub fn new_player<T: ToString>(
+        &self,
+        token: &mut LockToken<impl Unlocked>,
+        name: T,
+        invite: Option<&str>,
+    ) -> Result<(PlayerId, String), Errcode> {
         let name = name.to_string();
-        for (pid, player) in self.players.read().unwrap().iter() {
-            if name == player.read().unwrap().name {
+        if let Err(Errcode::GameOver(id)) = self.check_session(token) {
+            return Err(Errcode::GameOver(id));
+        }
+        // Both locks are held until the player is inserted, so the same name can't be taken twice
+        let mut index = self.player_index.write(token).unwrap();
+        let (index, token) = index.split();
+        let mut players = self.players.write(token).unwrap();
+        let (players, token) = players.split();
+        for (pid, player) in players.iter() {
+            if name == player.read(token).unwrap().name {
                 return Err(Errcode::PlayerAlreadyExists(*pid, name));
             }
         }
+        if self.require_invite {
+            let valid = invite.is_some_and(|i| self.invites.write(token).unwrap().remove(i));
+            if !valid {
+                return Err(Errcode::InvalidInvite);
+            }
+        }
 
-        let station = self.galaxy.init_new_station();
-        let player = Player::new(station, name);
-        let pid = player.id;
-        let key = BASE64_STANDARD.encode(player.key);
-        self.player_index
-            .write()
-            .unwrap()
-            .insert(player.key, player.id);
-        self.players
-            .write()
+        let pid = loop {
+            let id = self.next_player_id.fetch_add(1, Ordering::Relaxed);
+            if !players.contains_key(&id) {
+                break id;
+            }
+            log::error!("Player ID {id} is already allocated, skipping it");
+        };
+        let station = self.galaxy.init_new_station(token);
+        self.market
+            .write(token)
             .unwrap()
-            .insert(player.id, Arc::new(RwLock::new(player)));
+            .add_station(station.0, station.1)?;
+        let player = Player::new(pid, station, name, self.rich_players, self.clock.clone());
+        let key = BASE64_STANDARD.encode(player.key);
+        index.insert(player.key, pid);
+        players.insert(pid, Arc::new(OrderedLock::new(player)));
         self.syslog.event(&pid, SyslogEvent::GameStarted);
         Ok((pid, key))
     }
 
+    // Replaces the key of a player, the previous one stops working immediately
+    pub fn rotate_player_key(
+        &self,
+        token: &mut LockToken<impl Unlocked>,
+        id: &PlayerId,
+    ) -> Result<String, Errcode> {
+        let mut index = self.player_index.write(token).unwrap();
+        let (index, token) = index.split();
+        let mut players = self.players.read(token).unwrap();
+        let (players, token) = players.split();
+        let Some(player) = players.get(id) else {
+            return Err(Errcode::PlayerNotFound(*id));
+        };
+        let mut player = player.write(token).unwrap();
+        let mut key = [0; 128];
+        rand::rng().fill_bytes(&mut key);
+        index.remove(&player.key);
+        index.insert(key, player.id);
+        player.key = key;
+        Ok(BASE64_STANDARD.encode(key))
+    }
+
+    // Compared in constant time, the time taken doesn't tell how much of the key is right
+    pub fn is_admin_key(&self, key: &str) -> bool {
+        let Some(admin_key) = self.admin_key.as_ref() else {
+            return false;
+        };
+        let diff = admin_key
+            .bytes()
+            .zip(key.bytes())
+            .fold(0, |acc, (a, b)| acc | (a ^ b));
+        std::hint::black_box(diff) == 0 && admin_key.len() == key.len()
+    }
+
+    // Single-use token allowing to create a player when invites are required
+    pub fn new_invite(&self, token: &mut LockToken<impl Before<level::Invites>>) -> String {
+        let mut invite = [0; 32];
+        rand::rng().fill_bytes(&mut invite);
+        let invite = BASE64_STANDARD.encode(invite);
+        self.invites.write(token).unwrap().insert(invite.clone());
+        invite
+    }
+
+    // While paused, the game clock stops and nothing is simulated
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::Release);
+    }
+
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::Release);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Acquire)
+    }
+
+    pub fn time_scale(&self) -> f64 {
+        f64::from_bits(self.time_scale.load(Ordering::Acquire))
+    }
+
+    // Applies to everything that depends on time: flights, extraction, wages,
+    //     market changes and the syslog timestamps
+    pub fn set_time_scale(&self, scale: f64) -> Result<(), Errcode> {
+        if !scale.is_finite() || scale <= 0.0 {
+            return Err(Errcode::InvalidArgument("scale"));
+        }
+        self.time_scale.store(scale.to_bits(), Ordering::Release);
+        Ok(())
+    }
+
+    // Removes the player from the game, its orders are dropped without refund
+    pub fn kick_player(
+        &self,
+        token: &mut LockToken<impl Unlocked>,
+        id: &PlayerId,
+    ) -> Result<(), Errcode> {
+        {
+            let mut index = self.player_index.write(token).unwrap();
+            let (index, token) = index.split();
+            let mut players = self.players.write(token).unwrap();
+            let (players, token) = players.split();
+            let Some(player) = players.remove(id) else {
+                return Err(Errcode::PlayerNotFound(*id));
+            };
+            index.remove(&player.read(token).unwrap().key);
+            self.drop_orders(token, id);
+        }
+        self.syslog_history.write(token).unwrap().remove(id);
+        Ok(())
+    }
+
+    // Starts the player over with its initial money and empty stations,
+    //     it keeps its id, name and key
+    pub fn reset_player(
+        &self,
+        token: &mut LockToken<impl Unlocked>,
+        id: &PlayerId,
+    ) -> Result<(), Errcode> {
+        let mut players = self.players.read(token).unwrap();
+        let (players, token) = players.split();
+        let Some(player) = players.get(id) else {
+            return Err(Errcode::PlayerNotFound(*id));
+        };
+        let mut player = player.write(token).unwrap();
+        let (player, token) = player.split();
+        self.drop_orders(token, id);
+        for coord in player.stations.values() {
+            let station = self.galaxy.get_station(token, coord).unwrap();
+            let mut station = station.write(token).unwrap();
+            *station = Station::init(station.id, station.position);
+        }
+        player.reset();
+        self.syslog.event(id, SyslogEvent::GameStarted);
+        Ok(())
+    }
+
+    fn drop_orders(&self, token: &mut LockToken<impl Before<level::Market>>, id: &PlayerId) {
+        let mut market = self.market.write(token).unwrap();
+        let orders = market
+            .orderbook
+            .player_orders(id)
+            .iter()
+            .map(|o| o.id)
+            .collect::<Vec<OrderId>>();
+        for order in orders {
+            let _ = market.orderbook.cancel(id, &order);
+        }
+        market.orderbook.take_settlements(id);
+    }
+
+    // A negative amount takes money back, returns the new balance of the player
+    pub fn grant_money(
+        &self,
+        token: &mut LockToken<impl Unlocked>,
+        id: &PlayerId,
+        amount: f64,
+    ) -> Result<f64, Errcode> {
+        if !amount.is_finite() {
+            return Err(Errcode::InvalidArgument("amount"));
+        }
+        let mut players = self.players.read(token).unwrap();
+        let (players, token) = players.split();
+        let Some(player) = players.get(id) else {
+            return Err(Errcode::PlayerNotFound(*id));
+        };
+        let mut player = player.write(token).unwrap();
+        player.credit(amount, LedgerCategory::Grant, None);
+        Ok(player.money)
+    }
+
+    pub fn force_price(
+        &self,
+        token: &mut LockToken<impl Unlocked>,
+        station: Option<&StationId>,
+        resource: &Resource,
+        price: f64,
+    ) -> Result<(), Errcode> {
+        self.market
+            .write(token)
+            .unwrap()
+            .force_price(station, resource, price)
+    }
+
+    // Sends a message to the syslog of every player, returns the number of players reached
+    pub fn broadcast(&self, token: &mut LockToken<impl Unlocked>, message: &str) -> usize {
+        let players = self.players.read(token).unwrap();
+        for id in players.keys() {
+            self.syslog
+                .event(id, SyslogEvent::AdminMessage(message.to_string()));
+        }
+        players.len()
+    }
+
+    // State of the whole game, players first and market last, to follow the lock order
+    pub fn snapshot(&self, token: &mut LockToken<impl Unlocked>) -> serde_json::Value {
+        let mut players = self.players.read(token).unwrap();
+        let (players, token) = players.split();
+        let mut snap_players = vec![];
+        for player in players.values() {
+            let mut player = player.read(token).unwrap();
+            let (player, token) = player.split();
+            let mut stations = vec![];
+            for coord in player.stations.values() {
+                let station = self.galaxy.get_station(token, coord).unwrap();
+                let station = station.read(token).unwrap();
+                stations.push(json!({
+                    "id": station.id,
+                    "position": station.position,
+                    "crew": station.crew,
+                    "idle_crew": station.idle_crew,
+                    "cargo": station.cargo,
+                    "trader": station.trader,
+                }));
+            }
+            snap_players.push(json!({
+                "id": player.id,
+                "name": player.name,
+                "lost": player.lost,
+                "money": player.money,
+                "costs": player.costs,
+                "stations": stations,
+                "ships": player.ships.values().collect::<Vec<_>>(),
+            }));
+        }
+        let market = self.market.read(token).unwrap();
+        let orders = players
+            .keys()
+            .map(|id| (*id, market.orderbook.player_orders(id)))
+            .collect::<BTreeMap<_, _>>();
+        json!({
+            "time": self.clock.now(),
+            "paused": self.is_paused(),
+            "players": snap_players,
+            "market": *market,
+            "orders": orders,
+        })
+    }
+}
+
+#[test]
+fn test_player_ids_unique() {
+    let game = Game::new(GameConfig::default());
+    let mut token = LockToken::root();
+    let mut ids = std::collections::BTreeSet::new();
+    for n in 0..500 {
+        let (id, _) = game
+            .new_player(&mut token, format!("player-{n}"), None)
+            .unwrap();
+        assert!(ids.insert(id));
+    }
+    assert!(matches!(
+        game.new_player(&mut token, "player-0", None),
+        Err(Errcode::PlayerAlreadyExists(..))
+    ));
+    assert_eq!(game.players.read(&mut token).unwrap().len(), 500);
+    // Every player got its own station, none of the markets were replaced
+    assert_eq!(game.market.read(&mut token).unwrap().stations.len(), 500);
+}
+
+#[test]
+fn test_rich_players() {
+    let money = |game: &Game, id: &PlayerId| {
+        let mut token = LockToken::root();
+        let player = game.players.read(&mut token).unwrap()[id].clone();
+        let money = player.read(&mut token).unwrap().money;
+        money
+    };
+    let mut token = LockToken::root();
+    let game = Game::new(GameConfig::default());
+    let (poor, _) = game.new_player(&mut token, "test-rich-0", None).unwrap();
+
+    let game_rich = Game::new(GameConfig {
+        rich_players: true,
+        ..Default::default()
+    });
+    let (rich, _) = game_rich
+        .new_player(&mut token, "test-rich-0", None)
+        .unwrap();
+    let (other, _) = game_rich.new_player(&mut token, "other", None).unwrap();
+    assert_eq!(money(&game_rich, &rich), money(&game, &poor) * 10000.0);
+    assert_eq!(money(&game_rich, &other), money(&game, &poor));
+    // A reset gives the player its starting money back
+    game_rich.grant_money(&mut token, &rich, 500.0).unwrap();
+    game_rich.reset_player(&mut token, &rich).unwrap();
+    assert_eq!(money(&game_rich, &rich), money(&game, &poor) * 10000.0);
+}
+
+#[test]
+fn test_undelivered_cargo() {
+    use crate::market::orderbook::Settlement;
+    let game = Game::new(GameConfig::default());
+    let mut token = LockToken::root();
+    let (id, _) = game.new_player(&mut token, "cargo-full", None).unwrap();
+    let player = game.players.read(&mut token).unwrap()[&id].clone();
+    let mut player = player.write(&mut token).unwrap();
+    let (player, token) = player.split();
+    let coord = *player.stations.values().next().unwrap();
+    let station = game.galaxy.get_station(token, &coord).unwrap();
+    let mut station = station.write(token).unwrap();
+
+    let space = station.cargo.space_for(&Resource::Fuel);
+    station.cargo.add_resource(&Resource::Fuel, space);
+    let settlement = Settlement {
+        order: 0,
+        station: station.id,
+        tx: MarketTx {
+            added_cargo: Some((Resource::Fuel, 10.0)),
+            ..Default::default()
+        },
+        fill: None,
+    };
+    // The cargo waits in the station, until some space is freed
+    assert!(station.apply_settlement(player, settlement));
+    assert_eq!(station.undelivered, vec![(Resource::Fuel, 10.0)]);
+    station.buy_cargo(player, &4).unwrap();
+    assert_eq!(station.undelivered, vec![(Resource::Fuel, 8.0)]);
+    station.buy_cargo(player, &20).unwrap();
+    assert!(station.undelivered.is_empty());
+    assert_eq!(station.cargo.resources[&Resource::Fuel], space + 10.0);
+}
+
+#[test]
+fn test_admin_player_ops() {
+    let game = Game::new(GameConfig::default());
+    let mut token = LockToken::root();
+    let (id, _) = game.new_player(&mut token, "admin-test", None).unwrap();
+    let player = game.players.read(&mut token).unwrap()[&id].clone();
+    let start = player.read(&mut token).unwrap().money;
+
+    assert_eq!(
+        game.grant_money(&mut token, &id, 500.0).unwrap(),
+        start + 500.0
+    );
+    assert!(game.grant_money(&mut token, &id, f64::NAN).is_err());
+    game.reset_player(&mut token, &id).unwrap();
+    assert_eq!(player.read(&mut token).unwrap().money, start);
+    assert!(player
+        .read(&mut token)
+        .unwrap()
+        .ledger
+        .entries()
+        .next()
+        .is_none());
+
+    game.kick_player(&mut token, &id).unwrap();
+    assert!(game.players.read(&mut token).unwrap().is_empty());
+    assert!(game.player_index.read(&mut token).unwrap().is_empty());
+    assert!(matches!(
+        game.kick_player(&mut token, &id),
+        Err(Errcode::PlayerNotFound(_))
+    ));
+}
+
+#[test]
+fn test_admin_key() {
+    assert!(!Game::new(GameConfig::default()).is_admin_key(""));
+    let game = Game::new(GameConfig {
+        admin_key: Some("secret".to_string()),
+        ..Default::default()
+    });
+    assert!(game.is_admin_key("secret"));
+    for key in ["", "secre", "secrets", "Secret"] {
+        assert!(!game.is_admin_key(key));
+    }
+}
+
+#[test]
+fn test_time_scale() {
+    let game = Game::new(GameConfig {
+        time_scale: 100.0,
+        ..Default::default()
+    });
+    assert_eq!(game.tick_delta(), ITER_PERIOD.as_secs_f64() * 100.0);
+    assert!(game.set_time_scale(0.0).is_err());
+    assert!(game.set_time_scale(f64::INFINITY).is_err());
+    game.set_time_scale(2.0).unwrap();
+    assert_eq!(game.time_scale(), 2.0);
+
+    // A late iteration simulates all the time that passed, in several steps
+    game.step_elapsed(&mut LockToken::root(), ITER_PERIOD * 10);
+    assert!((game.clock.now() - ITER_PERIOD.as_secs_f64() * 20.0).abs() < 1e-9);
+}
+
+#[test]
+fn test_manual_steps() {
+    let game = Game::new(GameConfig {
+        session: SessionConfig {
+            lobby: 10.0,
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+    let mut token = LockToken::root();
+    let (id, _) = game.new_player(&mut token, "stepper", None).unwrap();
+    assert!(game.step(&mut token, 4.0));
+    assert_eq!(game.clock.now(), 4.0);
+
+    let running = |token: &mut LockToken<level::Root>, g: &Game| {
+        g.sessions.read(token).unwrap().current.phase == SessionPhase::Running
+    };
+    assert!(!game.advance_until(&mut token, 1.0, running));
+    assert!(game.advance_until(&mut token, 10.0, running));
+    assert!(game.clock.now() >= 10.0 && game.clock.now() < 10.1);
+    {
+        let mut histories = game.syslog_history.read(&mut token).unwrap();
+        let (histories, token) = histories.split();
+        let history = histories[&id].read(token).unwrap();
+        assert!(history
+            .since(0)
+            .any(|e| matches!(e.event, SyslogEvent::SessionStarted(1))));
+    }
+
+    game.pause();
+    assert!(!game.step(&mut token, 1.0));
+    assert!(!game.advance_until(&mut token, 100.0, |_, _| false));
+    assert!(game.clock.now() < 10.1);
+}
+
+#[test]
+fn test_step_elapsed() {
+    let game = Game::new(GameConfig::default());
+    let mut token = LockToken::root();
+    let start = game.clock.now();
+    assert_eq!(game.step_elapsed(&mut token, ITER_PERIOD * 3), 3);
+    let simulated = (ITER_PERIOD * 3).as_secs_f64() * game.time_scale();
+    assert!((game.clock.now() - start - simulated).abs() < 1e-9);
+    assert_eq!(game.tick_stats.dropped(), 0.0);
+
+    // A long gap is capped, the rest is counted as not simulated
+    let start = game.clock.now();
+    let nsteps = (MAX_ITER_GAP.as_secs_f64() / ITER_PERIOD.as_secs_f64()).round() as u32;
+    assert_eq!(
+        game.step_elapsed(&mut token, MAX_ITER_GAP + Duration::from_secs(2)),
+        nsteps
+    );
+    let simulated = MAX_ITER_GAP.as_secs_f64() * game.time_scale();
+    assert!((game.clock.now() - start - simulated).abs() < 1e-9);
+    assert!((game.tick_stats.dropped() - 2.0).abs() < 1e-9);
+
+    game.pause();
+    assert_eq!(game.step_elapsed(&mut token, ITER_PERIOD), 0);
+}
+
+#[test]
+fn test_parallel_player_updates() {
+    let game = Game::new(GameConfig {
+        workers: 4,
+        ..Default::default()
+    });
+    let mut token = LockToken::root();
+    let mut ids = vec![];
+    for n in 0..300 {
+        let (id, _) = game
+            .new_player(&mut token, format!("parallel-{n}"), None)
+            .unwrap();
+        let player = game.players.read(&mut token).unwrap()[&id].clone();
+        let mut player = player.write(&mut token).unwrap();
+        player.money = 100.0;
+        player.costs = 1.0;
+        ids.push(id);
+    }
+    // The players are warned, then all lose during the same step, spread over the workers
+    assert!(game.step(&mut token, 50.0));
+    assert!(game.step(&mut token, 100.0));
+
+    let mut histories = game.syslog_history.read(&mut token).unwrap();
+    let (histories, token) = histories.split();
+    for id in ids {
+        let history = histories[&id].read(token).unwrap();
+        let events = history
+            .since(0)
+            .map(|e| (&e.event).into())
+            .filter(|e| *e != "PriceSpike")
+            .collect::<Vec<&'static str>>();
+        assert_eq!(events, vec!["GameStarted", "LowFunds", "GameLost"]);
+    }
+}
+
+// Run with `cargo test --release -p simeis-data bench_tick -- --ignored --nocapture`
+#[test]
+#[ignore]
+fn bench_tick() {
+    use crate::crew::{CrewMember, CrewMemberType};
+    use crate::ship::Ship;
+
+    if cfg!(debug_assertions) {
+        panic!("The timings are only meaningful in a release build");
+    }
+    let game = Game::new(GameConfig {
+        workers: 4,
+        ..Default::default()
+    });
+    assert!(game.workers > 1);
+    let mut token = LockToken::root();
+    for n in 0..1000 {
+        let (id, _) = game
+            .new_player(&mut token, format!("bench-{n}"), None)
+            .unwrap();
+        let player = game.players.read(&mut token).unwrap()[&id].clone();
+        let mut player = player.write(&mut token).unwrap();
+        let (player, token) = player.split();
+        player.money = 1e12;
+        let position = *player.stations.values().next().unwrap();
+        for _ in 0..10 {
+            let mut ship = Ship::random(position);
+            ship.crew
+                .0
+                .insert(1, CrewMember::from(CrewMemberType::Pilot));
+            ship.pilot = Some(1);
+            ship.update_perf_stats();
+            ship.fuel_tank = ship.fuel_tank_capacity;
+            let destination = (position.0.wrapping_add(100_000), position.1, position.2);
+            let _ = ship.set_travel(destination);
+            player.ships.insert(ship.id, ship);
+        }
+        player.update_wages(token, &game.galaxy);
+    }
+
+    // The first steps record the scores and fill the histories
+    for _ in 0..10 {
+        game.step(&mut token, ITER_PERIOD.as_secs_f64());
+    }
+    let mut times = vec![];
+    for _ in 0..100 {
+        let tick = Instant::now();
+        game.step(&mut token, ITER_PERIOD.as_secs_f64());
+        times.push(tick.elapsed());
+    }
+    let mut players = game.players.read(&mut token).unwrap();
+    let (players, token) = players.split();
+    let flying = players
+        .values()
+        .map(|p| {
+            let p = p.read(token).unwrap();
+            p.ships
+                .values()
+                .filter(|s| matches!(s.state, ShipState::InFlight(_)))
+                .count()
+        })
+        .sum::<usize>();
+    let mean = times.iter().sum::<Duration>() / times.len() as u32;
+    let max = times.iter().max().unwrap();
+    println!(
+        "1000 players, 10000 ships ({flying} in flight), {} workers: mean {mean:?}, max {max:?}",
+        game.workers
+    );
+    assert!(mean < ITER_PERIOD);
+    assert!(*max < ITER_PERIOD);
+}
+
+#[test]
+fn test_seeded_galaxy() {
+    let stations = |seed| {
+        let game = Game::new(GameConfig {
+            seed: Some(seed),
+            ..Default::default()
+        });
+        let mut token = LockToken::root();
+        let (id, _) = game.new_player(&mut token, "seeded", None).unwrap();
+        let player = game.players.read(&mut token).unwrap()[&id].clone();
+        let stations = player.read(&mut token).unwrap().stations.clone();
+        stations
+    };
+    assert_eq!(stations(42), stations(42));
+    assert_ne!(stations(42), stations(43));
 }
@@ -4,7 +4,10 @@ pub mod crew;
 pub mod errors;
 pub mod galaxy;
 pub mod game;
+pub mod lock;
 pub mod market;
 pub mod player;
+pub mod score;
+pub mod session;
 pub mod ship;
 pub mod syslog;
@@ -0,0 +1,330 @@
+// Locks of the shared state of the game, tagged with their level in the lock order
+// A thread holding a lock may only take locks of a strictly higher level:
+//     game step < player index < players < a player < a station < the galaxy < its generator
+//     < the market < sessions < scores < invites < syslog histories < the history of a player
+// Two locks of the same level are never held together. As long as every thread follows this
+//     order, no deadlock can happen.
+// The order is checked by the compiler: taking a lock needs a token of a lower level, that stays
+//     borrowed by the guard. The guard gives a token of its own level, for the locks taken under it.
+// A thread starts with the root token, taken once where it starts using the game. In debug builds,
+//     a runtime check also catches the locks taken out of order with a second root token.
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+use std::sync::{
+    LockResult, Mutex, MutexGuard, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard,
+};
+
+use level::{Before, Level};
+
+pub mod level {
+    // Position of a level in the lock order, for the runtime check
+    pub trait Level {
+        const LEVEL: u8;
+    }
+
+    // Implemented by a level for all the levels that can be locked while holding it
+    pub trait Before<L> {}
+
+    // Nothing is held but the game step, any lock of the state can be taken
+    pub trait Unlocked:
+        Before<PlayerIndex>
+        + Before<Players>
+        + Before<Player>
+        + Before<Station>
+        + Before<Galaxy>
+        + Before<GalaxyRng>
+        + Before<Market>
+        + Before<Sessions>
+        + Before<Scores>
+        + Before<Invites>
+        + Before<SyslogHistories>
+        + Before<SyslogHistory>
+    {
+    }
+
+    macro_rules! levels {
+        ($($name:ident = $n:literal),*) => {
+            $(
+                pub struct $name;
+                impl Level for $name {
+                    const LEVEL: u8 = $n;
+                }
+            )*
+        };
+    }
+
+    macro_rules! lock_order {
+        ($first:ident $(, $rest:ident)*) => {
+            $(impl Before<$rest> for $first {})*
+            lock_order!($($rest),*);
+        };
+        () => {};
+    }
+
+    levels!(
+        Root = 0,
+        Step = 1,
+        PlayerIndex = 2,
+        Players = 3,
+        Player = 4,
+        Station = 5,
+        Galaxy = 6,
+        GalaxyRng = 7,
+        Market = 8,
+        // Held briefly, without taking any other lock of the game
+        Sessions = 9,
+        Scores = 10,
+        Invites = 11,
+        SyslogHistories = 12,
+        SyslogHistory = 13
+    );
+    lock_order!(
+        Root,
+        Step,
+        PlayerIndex,
+        Players,
+        Player,
+        Station,
+        Galaxy,
+        GalaxyRng,
+        Market,
+        Sessions,
+        Scores,
+        Invites,
+        SyslogHistories,
+        SyslogHistory
+    );
+    impl Unlocked for Root {}
+    impl Unlocked for Step {}
+}
+
+// Proof that the locks held by the thread are all of level L or below
+// Can't be sent to another thread, each one starts from its own root
+pub struct LockToken<L>(PhantomData<(L, *const ())>);
+
+impl LockToken<level::Root> {
+    pub fn root() -> Self {
+        LockToken(PhantomData)
+    }
+}
+
+#[cfg(debug_assertions)]
+thread_local! {
+    static HELD: std::cell::RefCell<Vec<u8>> = const { std::cell::RefCell::new(vec![]) };
+}
+
+fn acquire(level: u8) {
+    #[cfg(debug_assertions)]
+    HELD.with_borrow_mut(|held| {
+        if let Some(max) = held.iter().max() {
+            assert!(
+                *max < level,
+                "Lock of level {level} taken while holding locks of levels {held:?}"
+            );
+        }
+        held.push(level);
+    });
+    #[cfg(not(debug_assertions))]
+    let _ = level;
+}
+
+fn release(level: u8) {
+    #[cfg(debug_assertions)]
+    HELD.with_borrow_mut(|held| {
+        if let Some(n) = held.iter().rposition(|l| *l == level) {
+            held.remove(n);
+        }
+    });
+    #[cfg(not(debug_assertions))]
+    let _ = level;
+}
+
+pub struct OrderedLock<T, L>(RwLock<T>, PhantomData<fn() -> L>);
+
+impl<T: Default, L> Default for OrderedLock<T, L> {
+    fn default() -> Self {
+        OrderedLock(RwLock::default(), PhantomData)
+    }
+}
+
+impl<T, L: Level> OrderedLock<T, L> {
+    pub fn new(data: T) -> Self {
+        OrderedLock(RwLock::new(data), PhantomData)
+    }
+
+    pub fn read<'a>(
+        &'a self,
+        _held: &'a mut LockToken<impl Before<L>>,
+    ) -> LockResult<ReadGuard<'a, T, L>> {
+        acquire(L::LEVEL);
+        let token = LockToken(PhantomData);
+        match self.0.read() {
+            Ok(guard) => Ok(ReadGuard(guard, token)),
+            Err(e) => Err(PoisonError::new(ReadGuard(e.into_inner(), token))),
+        }
+    }
+
+    pub fn write<'a>(
+        &'a self,
+        _held: &'a mut LockToken<impl Before<L>>,
+    ) -> LockResult<WriteGuard<'a, T, L>> {
+        acquire(L::LEVEL);
+        let token = LockToken(PhantomData);
+        match self.0.write() {
+            Ok(guard) => Ok(WriteGuard(guard, token)),
+            Err(e) => Err(PoisonError::new(WriteGuard(e.into_inner(), token))),
+        }
+    }
+}
+
+pub struct ReadGuard<'a, T, L: Level>(RwLockReadGuard<'a, T>, LockToken<L>);
+
+impl<T, L: Level> ReadGuard<'_, T, L> {
+    // Token to take the locks of a higher level while holding this one
+    pub fn token(&mut self) -> &mut LockToken<L> {
+        &mut self.1
+    }
+
+    pub fn split(&mut self) -> (&T, &mut LockToken<L>) {
+        (&self.0, &mut self.1)
+    }
+}
+
+impl<T, L: Level> Deref for ReadGuard<'_, T, L> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T, L: Level> Drop for ReadGuard<'_, T, L> {
+    fn drop(&mut self) {
+        release(L::LEVEL);
+    }
+}
+
+pub struct WriteGuard<'a, T, L: Level>(RwLockWriteGuard<'a, T>, LockToken<L>);
+
+impl<T, L: Level> WriteGuard<'_, T, L> {
+    pub fn token(&mut self) -> &mut LockToken<L> {
+        &mut self.1
+    }
+
+    pub fn split(&mut self) -> (&mut T, &mut LockToken<L>) {
+        (&mut self.0, &mut self.1)
+    }
+}
+
+impl<T, L: Level> Deref for WriteGuard<'_, T, L> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T, L: Level> DerefMut for WriteGuard<'_, T, L> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T, L: Level> Drop for WriteGuard<'_, T, L> {
+    fn drop(&mut self) {
+        release(L::LEVEL);
+    }
+}
+
+// For the data that can't be shared between threads, only sent from one to the other
+pub struct OrderedMutex<T, L>(Mutex<T>, PhantomData<fn() -> L>);
+
+impl<T: Default, L> Default for OrderedMutex<T, L> {
+    fn default() -> Self {
+        OrderedMutex(Mutex::default(), PhantomData)
+    }
+}
+
+impl<T, L: Level> OrderedMutex<T, L> {
+    pub fn new(data: T) -> Self {
+        OrderedMutex(Mutex::new(data), PhantomData)
+    }
+
+    pub fn lock<'a>(
+        &'a self,
+        _held: &'a mut LockToken<impl Before<L>>,
+    ) -> LockResult<MutexLockGuard<'a, T, L>> {
+        acquire(L::LEVEL);
+        let token = LockToken(PhantomData);
+        match self.0.lock() {
+            Ok(guard) => Ok(MutexLockGuard(guard, token)),
+            Err(e) => Err(PoisonError::new(MutexLockGuard(e.into_inner(), token))),
+        }
+    }
+}
+
+pub struct MutexLockGuard<'a, T, L: Level>(MutexGuard<'a, T>, LockToken<L>);
+
+impl<T, L: Level> MutexLockGuard<'_, T, L> {
+    pub fn token(&mut self) -> &mut LockToken<L> {
+        &mut self.1
+    }
+
+    pub fn split(&mut self) -> (&mut T, &mut LockToken<L>) {
+        (&mut self.0, &mut self.1)
+    }
+}
+
+impl<T, L: Level> Deref for MutexLockGuard<'_, T, L> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T, L: Level> DerefMut for MutexLockGuard<'_, T, L> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T, L: Level> Drop for MutexLockGuard<'_, T, L> {
+    fn drop(&mut self) {
+        release(L::LEVEL);
+    }
+}
+
+#[test]
+#[cfg(debug_assertions)]
+fn test_lock_order() {
+    let players: OrderedLock<u32, level::Players> = OrderedLock::new(0);
+    let station: OrderedLock<u32, level::Station> = OrderedLock::new(0);
+    let other: OrderedLock<u32, level::Station> = OrderedLock::new(0);
+    let step: OrderedMutex<u32, level::Step> = OrderedMutex::new(0);
+    let mut token = LockToken::root();
+    {
+        let mut st = step.lock(&mut token).unwrap();
+        let mut p = players.read(st.token()).unwrap();
+        let _s = station.write(p.token()).unwrap();
+    }
+    // Released locks can be taken again in any order
+    drop(station.read(&mut token).unwrap());
+    drop(players.write(&mut token).unwrap());
+
+    // Out of order, the compiler refuses the token: only a second root token gets there
+    let res = std::thread::spawn(move || {
+        let (mut token, mut second) = (LockToken::root(), LockToken::root());
+        let _s = station.write(&mut token).unwrap();
+        let _p = players.read(&mut second).unwrap();
+    })
+    .join();
+    assert!(res.is_err());
+    let res = std::thread::spawn(move || {
+        let (mut token, mut second) = (LockToken::root(), LockToken::root());
+        let _s = other.read(&mut token).unwrap();
+        let _o = other.read(&mut second).unwrap();
+    })
+    .join();
+    assert!(res.is_err());
+}
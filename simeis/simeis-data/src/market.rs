@@ -1,11 +1,20 @@
+use history::PriceHistory;
+use orderbook::OrderBook;
 use rand::Rng;
 use rand_distr::{Distribution, Normal};
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, VecDeque};
 use strum::IntoEnumIterator;
 
+use crate::errors::Errcode;
+use crate::galaxy::station::StationId;
+use crate::galaxy::{get_distance, SpaceCoord};
+use crate::game::GameClock;
 use crate::{crew::CrewMember, ship::resources::Resource};
 
+pub mod history;
+pub mod orderbook;
+
 const MAX_AVG_AMPL: f64 = 2.0 / 100.0;
 pub const MARKET_CHANGE_SEC: f64 = 20.0;
 const BASE_FEE_RATE: f64 = 20.0 / 100.0;
@@ -17,28 +26,74 @@ const PRICE_INC_DIV: f64 = 10000.0;
 const PRICE_INC_RANGE_MAX: f64 = 20.0 / 100.0;
 const PRICE_INC_MIN_RATIO: f64 = 75.0 / 100.0;
 
+// Stock a station needs to accumulate for its prices to be divided by e
+const SUPPLY_ELASTICITY: f64 = 50000.0;
+// Part of the stock surplus / shortage that is resorbed at each price update
+const SUPPLY_RESORB_RATE: f64 = 5.0 / 100.0;
+
+// Stations closer than this distance pull their prices toward each other
+const CONVERGENCE_RANGE: f64 = 5000.0;
+const CONVERGENCE_RATE: f64 = 10.0 / 100.0;
+
+// Change of price over a single update above which the owner of the station is warned
+const PRICE_SPIKE_RATIO: f64 = 5.0 / 100.0;
+
 #[inline]
 pub fn fee_rate(rank: u8) -> f64 {
     BASE_FEE_RATE / (rank as f64).powf(FEE_RATE_DEC_POWF)
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PriceSpike {
+    pub station: StationId,
+    pub resource: Resource,
+    pub old_price: f64,
+    pub new_price: f64,
+}
+
+// Copy of the prices, for what reads many of them without keeping the market locked
+#[derive(Default)]
+pub struct MarketPrices {
+    pub average: BTreeMap<Resource, f64>,
+    pub stations: BTreeMap<StationId, BTreeMap<Resource, f64>>,
+}
+
 #[derive(Serialize)]
-pub struct Market {
+pub struct StationMarket {
+    pub position: SpaceCoord,
     pub prices: BTreeMap<Resource, f64>,
+    // Units sold to this market minus units bought from it, resorbed over time
+    pub supply: BTreeMap<Resource, f64>,
+    #[serde(skip)]
+    pub history: BTreeMap<Resource, PriceHistory>,
 }
 
-impl Market {
-    pub fn init() -> Market {
+impl StationMarket {
+    pub fn init(position: SpaceCoord) -> StationMarket {
         let mut prices = BTreeMap::new();
+        let mut supply = BTreeMap::new();
+        let mut history = BTreeMap::new();
         for r in Resource::iter() {
             prices.insert(r, r.base_price());
+            supply.insert(r, 0.0);
+            history.insert(r, PriceHistory::default());
         }
-        Market { prices }
+        StationMarket {
+            position,
+            prices,
+            supply,
+            history,
+        }
+    }
+
+    // Price toward which the market drifts, given the local stock
+    fn target_price(&self, r: &Resource) -> f64 {
+        let supply = self.supply.get(r).copied().unwrap_or(0.0);
+        r.base_price() * (-supply / SUPPLY_ELASTICITY).exp()
     }
 
     fn rand_distrib(&self, r: &Resource, now_price: f64) -> Normal<f64> {
-        let base_price = r.base_price();
-        let pratio = now_price / base_price;
+        let pratio = now_price / self.target_price(r);
         let avg = (1.0 - pratio) * MAX_AVG_AMPL;
         let std = avg.abs() + MAX_AVG_AMPL;
 
@@ -51,6 +106,12 @@ impl Market {
         old * (1.0 + change)
     }
 
+    fn record(&mut self, r: &Resource, volume: f64, now: f64) {
+        let price = *self.prices.get(r).unwrap();
+        self.history.get_mut(r).unwrap().record(now, price, volume);
+    }
+
+    // The new prices are recorded by the market, once they converged with the neighbours
     pub fn update_prices<R: Rng>(&mut self, rng: &mut R) {
         let mut new_prices = vec![];
         for (res, price) in self.prices.iter() {
@@ -66,9 +127,13 @@ impl Market {
             log::debug!("{r:?} {price} ({:?}%)", (price / r.base_price()) * 100.0);
             *p = price;
         }
+
+        for stock in self.supply.values_mut() {
+            *stock *= 1.0 - SUPPLY_RESORB_RATE;
+        }
     }
 
-    pub fn buy(&mut self, trader: &CrewMember, r: &Resource, amnt: f64) -> MarketTx {
+    pub fn buy(&mut self, trader: &CrewMember, r: &Resource, amnt: f64, now: f64) -> MarketTx {
         assert!(amnt > 0.0);
         let fee_rate = fee_rate(trader.rank);
         let amnt_wfee = amnt * (1.0 - fee_rate);
@@ -80,6 +145,8 @@ impl Market {
         let mut rng = rand::rng();
         let inc = rng.random_range(price_inc_min..=price_inc_max);
         *self.prices.get_mut(r).unwrap() *= 1.0 + inc;
+        *self.supply.get_mut(r).unwrap() -= amnt;
+        self.record(r, amnt, now);
 
         let fees = (amnt * fee_rate) * price;
         MarketTx {
@@ -90,7 +157,7 @@ impl Market {
         }
     }
 
-    pub fn sell(&mut self, trader: &CrewMember, r: &Resource, amnt: f64) -> MarketTx {
+    pub fn sell(&mut self, trader: &CrewMember, r: &Resource, amnt: f64, now: f64) -> MarketTx {
         assert!(amnt > 0.0);
         let fee_rate = fee_rate(trader.rank);
 
@@ -105,6 +172,8 @@ impl Market {
         let mut rng = rand::rng();
         let dec = rng.random_range(price_dec_min..=price_dec_max);
         *self.prices.get_mut(r).unwrap() *= 1.0 - dec;
+        *self.supply.get_mut(r).unwrap() += amnt;
+        self.record(r, amnt, now);
 
         MarketTx {
             removed_cargo: Some((*r, amnt)),
@@ -115,7 +184,270 @@ impl Market {
     }
 }
 
-#[derive(Serialize, Deserialize, Default, Debug)]
+#[derive(Serialize, Default)]
+pub struct Market {
+    pub stations: BTreeMap<StationId, StationMarket>,
+    #[serde(skip)]
+    pub orderbook: OrderBook,
+    // History of the average price over all the stations
+    #[serde(skip)]
+    pub history: BTreeMap<Resource, PriceHistory>,
+    // Number of trades since the start, and the ones of the last minute with their game time
+    #[serde(skip)]
+    pub trades: u64,
+    #[serde(skip)]
+    recent_trades: VecDeque<(f64, u64)>,
+    #[serde(skip)]
+    clock: GameClock,
+}
+
+impl Market {
+    pub fn init(clock: GameClock) -> Market {
+        let mut history = BTreeMap::new();
+        for r in Resource::iter() {
+            history.insert(r, PriceHistory::default());
+        }
+        Market {
+            clock,
+            history,
+            ..Default::default()
+        }
+    }
+
+    pub fn add_station(&mut self, id: StationId, position: SpaceCoord) -> Result<(), Errcode> {
+        if self.stations.contains_key(&id) {
+            return Err(Errcode::StationAlreadyExists(id));
+        }
+        let mut smarket = StationMarket::init(position);
+        for r in Resource::iter() {
+            smarket.record(&r, 0.0, self.clock.now());
+        }
+        self.stations.insert(id, smarket);
+        Ok(())
+    }
+
+    pub fn get_station(&self, id: &StationId) -> Option<&StationMarket> {
+        self.stations.get(id)
+    }
+
+    pub fn get_station_mut(&mut self, id: &StationId) -> Option<&mut StationMarket> {
+        self.stations.get_mut(id)
+    }
+
+    pub fn average_price(&self, r: &Resource) -> f64 {
+        if self.stations.is_empty() {
+            return r.base_price();
+        }
+        let tot = self
+            .stations
+            .values()
+            .map(|m| m.prices.get(r).unwrap())
+            .sum::<f64>();
+        tot / (self.stations.len() as f64)
+    }
+
+    // Average of the prices over all the stations of the galaxy
+    pub fn average_prices(&self) -> BTreeMap<Resource, f64> {
+        let mut prices = BTreeMap::new();
+        for r in Resource::iter() {
+            prices.insert(r, self.average_price(&r));
+        }
+        prices
+    }
+
+    pub fn prices(&self) -> MarketPrices {
+        MarketPrices {
+            average: self.average_prices(),
+            stations: self
+                .stations
+                .iter()
+                .map(|(id, smarket)| (*id, smarket.prices.clone()))
+                .collect(),
+        }
+    }
+
+    // Overrides the price of a resource on a station, or on all of them
+    //     The price then drifts again like any other
+    pub fn force_price(
+        &mut self,
+        station: Option<&StationId>,
+        r: &Resource,
+        price: f64,
+    ) -> Result<(), Errcode> {
+        if !price.is_finite() || price <= 0.0 {
+            return Err(Errcode::InvalidArgument("price"));
+        }
+        let now = self.clock.now();
+        let smarkets = match station {
+            Some(id) => match self.stations.get_mut(id) {
+                Some(smarket) => vec![smarket],
+                None => return Err(Errcode::NoSuchStation(*id)),
+            },
+            None => self.stations.values_mut().collect(),
+        };
+        for smarket in smarkets {
+            smarket.prices.insert(*r, price);
+            smarket.record(r, 0.0, now);
+        }
+        self.record_average(r, 0.0);
+        Ok(())
+    }
+
+    fn record_average(&mut self, r: &Resource, volume: f64) {
+        let price = self.average_price(r);
+        let now = self.clock.now();
+        self.history.get_mut(r).unwrap().record(now, price, volume);
+    }
+
+    // Returns the prices that moved more than PRICE_SPIKE_RATIO
+    pub fn update_prices<R: Rng>(&mut self, rng: &mut R) -> Vec<PriceSpike> {
+        let now = self.clock.now();
+        let old_prices = self
+            .stations
+            .iter()
+            .map(|(id, smarket)| (*id, smarket.prices.clone()))
+            .collect::<Vec<(StationId, BTreeMap<Resource, f64>)>>();
+        for smarket in self.stations.values_mut() {
+            smarket.update_prices(rng);
+        }
+        self.converge_prices();
+        for r in Resource::iter() {
+            self.record_average(&r, 0.0);
+        }
+
+        let mut spikes = vec![];
+        for (id, old) in old_prices {
+            let smarket = self.stations.get_mut(&id).unwrap();
+            for (r, old_price) in old {
+                let new_price = *smarket.prices.get(&r).unwrap();
+                if new_price != old_price {
+                    smarket.record(&r, 0.0, now);
+                }
+                if ((new_price / old_price) - 1.0).abs() >= PRICE_SPIKE_RATIO {
+                    spikes.push(PriceSpike {
+                        station: id,
+                        resource: r,
+                        old_price,
+                        new_price,
+                    });
+                }
+            }
+        }
+        spikes
+    }
+
+    pub fn buy(
+        &mut self,
+        station: &StationId,
+        trader: &CrewMember,
+        r: &Resource,
+        amnt: f64,
+    ) -> Result<MarketTx, Errcode> {
+        let now = self.clock.now();
+        let Some(smarket) = self.stations.get_mut(station) else {
+            return Err(Errcode::NoSuchStation(*station));
+        };
+        let tx = smarket.buy(trader, r, amnt, now);
+        self.record_average(r, amnt);
+        self.record_trades(1);
+        Ok(tx)
+    }
+
+    pub fn sell(
+        &mut self,
+        station: &StationId,
+        trader: &CrewMember,
+        r: &Resource,
+        amnt: f64,
+    ) -> Result<MarketTx, Errcode> {
+        let now = self.clock.now();
+        let Some(smarket) = self.stations.get_mut(station) else {
+            return Err(Errcode::NoSuchStation(*station));
+        };
+        let tx = smarket.sell(trader, r, amnt, now);
+        self.record_average(r, amnt);
+        self.record_trades(1);
+        Ok(tx)
+    }
+
+    pub fn record_trades(&mut self, nb: u64) {
+        if nb == 0 {
+            return;
+        }
+        let now = self.clock.now();
+        self.trades += nb;
+        self.recent_trades.push_back((now, nb));
+        while self
+            .recent_trades
+            .front()
+            .is_some_and(|(t, _)| *t < now - 60.0)
+        {
+            self.recent_trades.pop_front();
+        }
+    }
+
+    // Trades between players happen at their own price, not at the one of the station
+    pub fn record_fill(&mut self, station: &StationId, r: &Resource, price: f64, volume: f64) {
+        let now = self.clock.now();
+        if let Some(smarket) = self.stations.get_mut(station) {
+            smarket
+                .history
+                .get_mut(r)
+                .unwrap()
+                .record(now, price, volume);
+        }
+        self.history.get_mut(r).unwrap().record(now, price, volume);
+    }
+
+    // Trades made during the last minute of game time
+    pub fn trades_per_minute(&self) -> u64 {
+        let since = self.clock.now() - 60.0;
+        self.recent_trades
+            .iter()
+            .filter(|(t, _)| *t >= since)
+            .map(|(_, nb)| nb)
+            .sum()
+    }
+
+    // Nearby stations trade between themselves, which pulls their prices together
+    fn converge_prices(&mut self) {
+        let mut deltas: Vec<(StationId, Resource, f64)> = vec![];
+        for (id, smarket) in self.stations.iter() {
+            let neighbours = self
+                .stations
+                .iter()
+                .filter(|(oid, _)| *oid != id)
+                .map(|(_, other)| (other, get_distance(&smarket.position, &other.position)))
+                .filter(|(_, dist)| *dist < CONVERGENCE_RANGE)
+                .collect::<Vec<(&StationMarket, f64)>>();
+            if neighbours.is_empty() {
+                continue;
+            }
+
+            for (r, price) in smarket.prices.iter() {
+                let mut delta = 0.0;
+                for (other, dist) in neighbours.iter() {
+                    let strength = CONVERGENCE_RATE * (1.0 - (dist / CONVERGENCE_RANGE));
+                    delta += (other.prices.get(r).unwrap() - price) * strength;
+                }
+                deltas.push((*id, *r, delta / (neighbours.len() as f64)));
+            }
+        }
+
+        for (id, r, delta) in deltas {
+            let price = self
+                .stations
+                .get_mut(&id)
+                .unwrap()
+                .prices
+                .get_mut(&r)
+                .unwrap();
+            *price += delta;
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
 pub struct MarketTx {
     pub added_cargo: Option<(Resource, f64)>,
     pub removed_cargo: Option<(Resource, f64)>,
@@ -124,3 +456,89 @@ pub struct MarketTx {
     pub removed_money: Option<f64>,
     pub fees: f64,
 }
+
+#[test]
+fn test_market_convergence() {
+    let mut market = Market::init(GameClock::default());
+    market.add_station(0, (0, 0, 0)).unwrap();
+    market.add_station(1, (1000, 0, 0)).unwrap();
+    market.add_station(2, (1_000_000, 0, 0)).unwrap();
+    for id in [0, 2] {
+        *market
+            .get_station_mut(&id)
+            .unwrap()
+            .prices
+            .get_mut(&Resource::Iron)
+            .unwrap() *= 2.0;
+    }
+
+    let price = |m: &Market, id: StationId| {
+        *m.get_station(&id)
+            .unwrap()
+            .prices
+            .get(&Resource::Iron)
+            .unwrap()
+    };
+    let gap_before = price(&market, 0) - price(&market, 1);
+    let far_before = price(&market, 2);
+    market.converge_prices();
+    let gap_after = price(&market, 0) - price(&market, 1);
+    assert!(gap_after > 0.0 && gap_after < gap_before);
+    assert_eq!(price(&market, 2), far_before);
+}
+
+#[test]
+fn test_price_spikes() {
+    use rand::SeedableRng;
+    let mut rng = rand::rngs::SmallRng::seed_from_u64(0);
+    let mut market = Market::init(GameClock::default());
+    market.add_station(0, (0, 0, 0)).unwrap();
+    market.add_station(1, (0, 0, 0)).unwrap();
+    *market
+        .get_station_mut(&0)
+        .unwrap()
+        .prices
+        .get_mut(&Resource::Iron)
+        .unwrap() *= 10.0;
+
+    // Converging toward the neighbour makes the price of station 0 drop sharply
+    let spikes = market.update_prices(&mut rng);
+    let spike = spikes
+        .iter()
+        .find(|s| s.station == 0 && s.resource == Resource::Iron)
+        .unwrap();
+    assert!(spike.new_price < spike.old_price);
+    // The history got the price the station ended up with
+    let history = &market.get_station(&0).unwrap().history[&Resource::Iron];
+    assert_eq!(history.samples().last().unwrap().price, spike.new_price);
+}
+
+#[test]
+fn test_fill_history() {
+    let mut market = Market::init(GameClock::default());
+    market.add_station(0, (0, 0, 0)).unwrap();
+    market.record_fill(&0, &Resource::Iron, 123.0, 4.0);
+
+    let candles = market.get_station(&0).unwrap().history[&Resource::Iron].candles(10.0, 1, 5.0);
+    assert_eq!(candles[0].close, 123.0);
+    assert_eq!(candles[0].volume, 4.0);
+    let candles = market.history[&Resource::Iron].candles(10.0, 1, 5.0);
+    assert_eq!(candles[0].close, 123.0);
+    assert_eq!(candles[0].volume, 4.0);
+}
+
+#[test]
+fn test_add_station_twice() {
+    let mut market = Market::init(GameClock::default());
+    market.add_station(3, (0, 0, 0)).unwrap();
+    market
+        .force_price(Some(&3), &Resource::Iron, 123.0)
+        .unwrap();
+    let err = market.add_station(3, (1000, 0, 0)).unwrap_err();
+    assert!(matches!(err, Errcode::StationAlreadyExists(3)));
+    assert_eq!(market.get_station(&3).unwrap().position, (0, 0, 0));
+    assert_eq!(
+        market.get_station(&3).unwrap().prices[&Resource::Iron],
+        123.0
+    );
+}
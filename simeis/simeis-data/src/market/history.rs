@@ -0,0 +1,123 @@
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+const HISTORY_MAX_SAMPLES: usize = 4096;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct PriceSample {
+    pub t: f64,
+    pub price: f64,
+    pub volume: f64,
+}
+
+// Open / High / Low / Close of the price over a time interval
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Candle {
+    pub start: f64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: f64,
+}
+
+impl Candle {
+    fn flat(start: f64, price: f64) -> Candle {
+        Candle {
+            start,
+            open: price,
+            high: price,
+            low: price,
+            close: price,
+            volume: 0.0,
+        }
+    }
+
+    fn add(&mut self, sample: &PriceSample) {
+        self.high = self.high.max(sample.price);
+        self.low = self.low.min(sample.price);
+        self.close = sample.price;
+        self.volume += sample.volume;
+    }
+}
+
+// Bounded time series of the price of a resource, oldest samples are dropped first
+#[derive(Default, Debug)]
+pub struct PriceHistory(VecDeque<PriceSample>);
+
+impl PriceHistory {
+    pub fn record(&mut self, t: f64, price: f64, volume: f64) {
+        if self.0.len() == HISTORY_MAX_SAMPLES {
+            self.0.pop_front();
+        }
+        self.0.push_back(PriceSample { t, price, volume });
+    }
+
+    pub fn samples(&self) -> impl Iterator<Item = &PriceSample> {
+        self.0.iter()
+    }
+
+    // Aggregates the samples in the `count` last intervals before `now`
+    // Intervals without any sample are filled with the last known price
+    pub fn candles(&self, interval: f64, count: usize, now: f64) -> Vec<Candle> {
+        let Some(first) = self.0.front().filter(|_| count > 0) else {
+            return vec![];
+        };
+        let last_start = (now / interval).floor() * interval;
+        let first_start = (last_start - (interval * (count.saturating_sub(1) as f64)))
+            .max((first.t / interval).floor() * interval);
+
+        let mut price = first.price;
+        let mut samples = self.0.iter().peekable();
+        while let Some(s) = samples.next_if(|s| s.t < first_start) {
+            price = s.price;
+        }
+
+        let mut candles = vec![];
+        let mut start = first_start;
+        while start <= last_start {
+            let mut candle = Candle::flat(start, price);
+            while let Some(s) = samples.next_if(|s| s.t < start + interval) {
+                candle.add(s);
+            }
+            price = candle.close;
+            candles.push(candle);
+            start += interval;
+        }
+        candles
+    }
+}
+
+#[test]
+fn test_price_candles() {
+    let mut history = PriceHistory::default();
+    history.record(1.0, 10.0, 0.0);
+    history.record(5.0, 12.0, 3.0);
+    history.record(7.0, 9.0, 2.0);
+    history.record(25.0, 11.0, 1.0);
+
+    let candles = history.candles(10.0, 10, 35.0);
+    assert_eq!(candles.len(), 4);
+    assert_eq!(
+        candles[0],
+        Candle {
+            start: 0.0,
+            open: 10.0,
+            high: 12.0,
+            low: 9.0,
+            close: 9.0,
+            volume: 5.0,
+        }
+    );
+    assert_eq!(candles[1], Candle::flat(10.0, 9.0));
+    assert_eq!(candles[2].close, 11.0);
+    assert_eq!(candles[3], Candle::flat(30.0, 11.0));
+
+    assert!(history.candles(10.0, 0, 35.0).is_empty());
+
+    let candles = history.candles(10.0, 2, 35.0);
+    assert_eq!(candles.len(), 2);
+    assert_eq!(candles[0].start, 20.0);
+    assert_eq!(candles[0].open, 9.0);
+}
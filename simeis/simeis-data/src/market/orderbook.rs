@@ -0,0 +1,384 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use strum::{EnumString, IntoStaticStr};
+
+use super::MarketTx;
+use crate::errors::Errcode;
+use crate::galaxy::station::StationId;
+use crate::player::PlayerId;
+use crate::ship::resources::Resource;
+use crate::ship::ShipId;
+
+pub const ORDER_DEFAULT_TTL: f64 = 60.0 * 60.0;
+pub const ORDER_MAX_TTL: f64 = 24.0 * 60.0 * 60.0;
+
+pub type OrderId = u64;
+
+#[derive(EnumString, IntoStaticStr, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[strum(ascii_case_insensitive)]
+pub enum OrderSide {
+    Buy,
+    Sell,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Order {
+    pub id: OrderId,
+    pub player: PlayerId,
+    pub station: StationId,
+    pub side: OrderSide,
+    pub resource: Resource,
+    pub price: f64,
+    pub amount: f64,
+    pub remaining: f64,
+    pub created: f64,
+    pub expires: f64,
+}
+
+impl Order {
+    // What the owner gets back if the order leaves the book unfilled
+    fn refund(&self) -> MarketTx {
+        match self.side {
+            OrderSide::Buy => MarketTx {
+                added_money: Some(self.remaining * self.price),
+                ..Default::default()
+            },
+            OrderSide::Sell => MarketTx {
+                added_cargo: Some((self.resource, self.remaining)),
+                ..Default::default()
+            },
+        }
+    }
+
+    // Order entries shown to other players on the book
+    pub fn public_data(&self) -> serde_json::Value {
+        serde_json::json!({
+            "id": self.id,
+            "station": self.station,
+            "price": self.price,
+            "remaining": self.remaining,
+            "expires": self.expires,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct OrderRequest {
+    pub side: OrderSide,
+    pub resource: Resource,
+    pub price: f64,
+    pub amount: f64,
+    pub ttl: f64,
+    // Ship docked at the station trading from its cargo, instead of the cargo of the station
+    pub ship: Option<ShipId>,
+}
+
+impl OrderRequest {
+    pub fn check(&self) -> Result<(), Errcode> {
+        // An infinite price would go into the escrow and the matching
+        if !self.price.is_finite() || self.price <= 0.0 {
+            return Err(Errcode::InvalidArgument("price"));
+        }
+        if self.ttl <= 0.0 || self.ttl.is_nan() {
+            return Err(Errcode::InvalidArgument("ttl"));
+        }
+        // Capped below by the free space or the cargo, a NaN would take all of it
+        if self.amount <= 0.0 || !self.amount.is_finite() {
+            return Err(Errcode::InvalidArgument("amount"));
+        }
+        Ok(())
+    }
+}
+
+// Part of a taker order that got matched against a resting order
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Fill {
+    pub maker: OrderId,
+    pub price: f64,
+    pub amount: f64,
+}
+
+// Money or cargo owed to a player, delivered to its station by the game thread
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Settlement {
+    pub order: OrderId,
+    pub station: StationId,
+    pub tx: MarketTx,
+    // Set when the settlement comes from a trade, not from a refund
+    pub fill: Option<Fill>,
+}
+
+// Orders only match others placed at the same station, the goods don't travel
+type BookKey = (StationId, Resource);
+
+#[derive(Default)]
+pub struct OrderBook {
+    next_id: OrderId,
+    // Sorted from the best price to the worst, then by creation time
+    bids: BTreeMap<BookKey, Vec<Order>>,
+    asks: BTreeMap<BookKey, Vec<Order>>,
+    pending: BTreeMap<PlayerId, Vec<Settlement>>,
+}
+
+// Orders of a resource at one station, or at all of them
+fn book_side<'a>(
+    book: &'a BTreeMap<BookKey, Vec<Order>>,
+    station: Option<&StationId>,
+    resource: &Resource,
+) -> Vec<&'a Order> {
+    book.iter()
+        .filter(|((s, r), _)| r == resource && station.is_none_or(|station| s == station))
+        .flat_map(|(_, orders)| orders.iter())
+        .collect()
+}
+
+impl OrderBook {
+    pub fn bids(&self, station: Option<&StationId>, resource: &Resource) -> Vec<&Order> {
+        let mut bids = book_side(&self.bids, station, resource);
+        bids.sort_by(|a, b| b.price.total_cmp(&a.price).then(a.id.cmp(&b.id)));
+        bids
+    }
+
+    pub fn asks(&self, station: Option<&StationId>, resource: &Resource) -> Vec<&Order> {
+        let mut asks = book_side(&self.asks, station, resource);
+        asks.sort_by(|a, b| a.price.total_cmp(&b.price).then(a.id.cmp(&b.id)));
+        asks
+    }
+
+    pub fn player_orders(&self, player: &PlayerId) -> Vec<&Order> {
+        self.bids
+            .values()
+            .chain(self.asks.values())
+            .flatten()
+            .filter(|o| &o.player == player)
+            .collect()
+    }
+
+    // Places an order, matches it against the opposite side of the book,
+    //     and rests the remaining amount on the book.
+    // Money / cargo must already be escrowed by the caller.
+    #[allow(clippy::too_many_arguments)]
+    pub fn place(
+        &mut self,
+        player: PlayerId,
+        station: StationId,
+        side: OrderSide,
+        resource: Resource,
+        price: f64,
+        amount: f64,
+        ttl: f64,
+        now: f64,
+    ) -> (Order, Vec<Fill>) {
+        let (mut order, fills) = self.take(player, station, side, resource, price, amount, now);
+        order.expires = now + ttl.min(ORDER_MAX_TTL);
+        if order.remaining > 0.0 {
+            let key = (station, resource);
+            let (book, better): (_, fn(f64, f64) -> bool) = match side {
+                OrderSide::Buy => (self.bids.entry(key).or_default(), |a, b| a > b),
+                OrderSide::Sell => (self.asks.entry(key).or_default(), |a, b| a < b),
+            };
+            let pos = book
+                .iter()
+                .position(|o| better(price, o.price))
+                .unwrap_or(book.len());
+            book.insert(pos, order.clone());
+        }
+        (order, fills)
+    }
+
+    // Matches an order against the ones resting at its station, the remaining amount is not kept
+    #[allow(clippy::too_many_arguments)]
+    pub fn take(
+        &mut self,
+        player: PlayerId,
+        station: StationId,
+        side: OrderSide,
+        resource: Resource,
+        price: f64,
+        amount: f64,
+        now: f64,
+    ) -> (Order, Vec<Fill>) {
+        self.next_id += 1;
+        let mut order = Order {
+            id: self.next_id,
+            player,
+            station,
+            side,
+            resource,
+            price,
+            amount,
+            remaining: amount,
+            created: now,
+            expires: now,
+        };
+        let fills = self.match_order(&mut order);
+        (order, fills)
+    }
+
+    fn match_order(&mut self, order: &mut Order) -> Vec<Fill> {
+        let key = (order.station, order.resource);
+        let book = match order.side {
+            OrderSide::Buy => self.asks.entry(key).or_default(),
+            OrderSide::Sell => self.bids.entry(key).or_default(),
+        };
+
+        let mut fills = vec![];
+        let mut settlements = vec![];
+        for maker in book.iter_mut() {
+            if order.remaining <= 0.0 {
+                break;
+            }
+            let crosses = match order.side {
+                OrderSide::Buy => maker.price <= order.price,
+                OrderSide::Sell => maker.price >= order.price,
+            };
+            if !crosses {
+                break;
+            }
+            if maker.player == order.player {
+                continue;
+            }
+
+            let amount = maker.remaining.min(order.remaining);
+            maker.remaining -= amount;
+            order.remaining -= amount;
+            let fill = Fill {
+                maker: maker.id,
+                price: maker.price,
+                amount,
+            };
+            fills.push(fill.clone());
+
+            let tx = match maker.side {
+                OrderSide::Buy => MarketTx {
+                    added_cargo: Some((maker.resource, amount)),
+                    ..Default::default()
+                },
+                OrderSide::Sell => MarketTx {
+                    added_money: Some(amount * maker.price),
+                    ..Default::default()
+                },
+            };
+            settlements.push((
+                maker.player,
+                Settlement {
+                    order: maker.id,
+                    station: maker.station,
+                    tx,
+                    fill: Some(fill),
+                },
+            ));
+        }
+        book.retain(|o| o.remaining > 0.0);
+
+        for (player, settlement) in settlements {
+            self.settle(player, settlement);
+        }
+        fills
+    }
+
+    pub fn cancel(&mut self, player: &PlayerId, id: &OrderId) -> Result<Settlement, Errcode> {
+        for book in self.bids.values_mut().chain(self.asks.values_mut()) {
+            let Some(pos) = book.iter().position(|o| &o.id == id && &o.player == player) else {
+                continue;
+            };
+            let order = book.remove(pos);
+            return Ok(Settlement {
+                order: order.id,
+                station: order.station,
+                tx: order.refund(),
+                fill: None,
+            });
+        }
+        Err(Errcode::NoSuchOrder(*id))
+    }
+
+    // Removes the expired orders, and schedule the refund of their escrow
+    pub fn expire(&mut self, now: f64) -> Vec<Order> {
+        let mut expired = vec![];
+        for book in self.bids.values_mut().chain(self.asks.values_mut()) {
+            book.retain(|o| {
+                if o.expires <= now {
+                    expired.push(o.clone());
+                    false
+                } else {
+                    true
+                }
+            });
+        }
+
+        for order in expired.iter() {
+            let settlement = Settlement {
+                order: order.id,
+                station: order.station,
+                tx: order.refund(),
+                fill: None,
+            };
+            self.settle(order.player, settlement);
+        }
+        expired
+    }
+
+    fn settle(&mut self, player: PlayerId, settlement: Settlement) {
+        self.pending.entry(player).or_default().push(settlement);
+    }
+
+    pub fn take_settlements(&mut self, player: &PlayerId) -> Vec<Settlement> {
+        self.pending.remove(player).unwrap_or_default()
+    }
+
+    pub fn take_all_settlements(&mut self) -> BTreeMap<PlayerId, Vec<Settlement>> {
+        std::mem::take(&mut self.pending)
+    }
+}
+
+#[test]
+fn test_orderbook_matching() {
+    let mut book = OrderBook::default();
+    let (ask_cheap, fills) =
+        book.place(1, 1, OrderSide::Sell, Resource::Iron, 5.0, 10.0, 60.0, 0.0);
+    assert!(fills.is_empty());
+    let (ask_exp, _) = book.place(1, 1, OrderSide::Sell, Resource::Iron, 8.0, 10.0, 60.0, 0.0);
+    let _ = book.place(1, 1, OrderSide::Sell, Resource::Iron, 6.0, 10.0, 60.0, 0.0);
+
+    // Own orders are never matched
+    let (_, fills) = book.place(1, 1, OrderSide::Buy, Resource::Iron, 10.0, 1.0, 60.0, 0.0);
+    assert!(fills.is_empty());
+    let _ = book.cancel(&1, &4).unwrap();
+
+    // Orders at another station are never matched
+    let (other, fills) = book.place(2, 2, OrderSide::Buy, Resource::Iron, 9.0, 5.0, 60.0, 0.0);
+    assert!(fills.is_empty());
+    assert_eq!(book.bids(None, &Resource::Iron).len(), 1);
+    let _ = book.cancel(&2, &other.id).unwrap();
+
+    // Taking from the book leaves nothing on it
+    let (taken, fills) = book.take(3, 2, OrderSide::Buy, Resource::Iron, 9.0, 5.0, 0.0);
+    assert!(fills.is_empty());
+    assert_eq!(taken.remaining, 5.0);
+    assert!(book.bids(None, &Resource::Iron).is_empty());
+
+    let (order, fills) = book.place(2, 1, OrderSide::Buy, Resource::Iron, 7.0, 15.0, 60.0, 0.0);
+    assert_eq!(fills.len(), 2);
+    assert_eq!(fills[0].maker, ask_cheap.id);
+    assert_eq!(fills[0].amount, 10.0);
+    assert_eq!(fills[1].price, 6.0);
+    assert_eq!(fills[1].amount, 5.0);
+    assert_eq!(order.remaining, 0.0);
+    assert_eq!(book.asks(Some(&1), &Resource::Iron).len(), 2);
+    assert!(book.bids(None, &Resource::Iron).is_empty());
+
+    let settled = book.take_settlements(&1);
+    assert_eq!(settled.len(), 2);
+    assert_eq!(settled[0].station, 1);
+    assert_eq!(settled[0].tx.added_money, Some(50.0));
+
+    let expired = book.expire(61.0);
+    assert_eq!(expired.len(), 2);
+    assert!(expired.iter().any(|o| o.id == ask_exp.id));
+    assert!(book.asks(None, &Resource::Iron).is_empty());
+    let refunds = book.take_settlements(&1);
+    assert_eq!(refunds.len(), 2);
+    assert!(refunds.iter().all(|s| s.tx.added_cargo.is_some()));
+}
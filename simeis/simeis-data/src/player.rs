@@ -1,21 +1,33 @@
 use rand::RngCore;
-use std::collections::hash_map::DefaultHasher;
 use std::collections::BTreeMap;
-use std::hash::Hasher;
 
 use crate::crew::CrewId;
 use crate::errors::Errcode;
 use crate::galaxy::station::{Station, StationId};
 use crate::galaxy::{Galaxy, SpaceCoord};
+use crate::game::GameClock;
+use crate::lock::{level, LockToken, OrderedLock};
+use crate::market::orderbook::{Order, OrderRequest, OrderSide};
+use crate::market::{Market, MarketTx};
 use crate::ship::module::{ShipModuleId, ShipModuleType};
 use crate::ship::upgrade::ShipUpgrade;
 use crate::ship::{Ship, ShipId};
-use crate::syslog::{SyslogEvent, SyslogRecv};
+use crate::syslog::{SyslogBuffer, SyslogEvent};
+use alert::{AlertId, AlertRule, Alerts};
+use ledger::{Ledger, LedgerCategory, LedgerEntity};
+
+pub mod alert;
+pub mod ledger;
 
 const INIT_MONEY: f64 = 30000.0;
+// Players named with this prefix start with a lot more money, when the game allows it
+const RICH_PLAYER_PREFIX: &str = "test-rich";
+const RICH_PLAYER_FACTOR: f64 = 10000.0;
 
-pub type PlayerId = u16;
+// Allocated in sequence by the game, never reused
+pub type PlayerId = u32;
 pub type PlayerKey = [u8; 128];
+pub type PlayerLock = OrderedLock<Player, level::Player>;
 
 // Game state for a single player
 #[allow(dead_code)] // DEV
@@ -27,48 +39,96 @@ pub struct Player {
     pub name: String,
     pub money: f64,
     pub costs: f64,
+    // Money the player starts with, and gets back when reset
+    init_money: f64,
 
     pub stations: BTreeMap<StationId, SpaceCoord>,
-    pub ships: BTreeMap<ShipId, Ship>
+    pub ships: BTreeMap<ShipId, Ship>,
+    pub ledger: Ledger,
+    pub alerts: Alerts,
+    clock: GameClock,
 }
 
 impl Player {
-    pub fn new(station: (StationId, SpaceCoord), name: String) -> Player {
-        let mut hasher = DefaultHasher::new();
-        hasher.write(name.as_bytes());
+    pub fn new(
+        id: PlayerId,
+        station: (StationId, SpaceCoord),
+        name: String,
+        rich_players: bool,
+        clock: GameClock,
+    ) -> Player {
         let mut rng = rand::rng();
         let mut randbytes = [0; 128];
         rng.fill_bytes(&mut randbytes);
 
-        #[allow(unused_mut)]
         let mut money = INIT_MONEY;
-
-        #[cfg(feature = "testing")]
-        if name.starts_with("test-rich") {
-            money *= 10000.0;
+        if rich_players && name.starts_with(RICH_PLAYER_PREFIX) {
+            money *= RICH_PLAYER_FACTOR;
         }
         let mut stations = BTreeMap::new();
         stations.insert(station.0, station.1);
         Player {
             key: randbytes,
-            id: (hasher.finish() % (PlayerId::MAX as u64)) as PlayerId,
+            id,
             lost: false,
 
             money,
             costs: 0.0,
+            init_money: money,
 
             name,
             stations,
             ships: BTreeMap::new(),
+            ledger: Ledger::default(),
+            alerts: Alerts::default(),
+            clock,
         }
     }
 
-    // SAFETY NOTE Only use this function when a &mut Station is NOT present, or deadlock
-    pub fn update_wages(&mut self, galaxy: &Galaxy) {
+    // Back to the state of a new player, keeping its id, name, key and stations
+    pub fn reset(&mut self) {
+        self.lost = false;
+        self.money = self.init_money;
+        self.costs = 0.0;
+        self.ships.clear();
+        self.ledger = Ledger::default();
+        self.alerts = Alerts::default();
+    }
+
+    pub fn debit(&mut self, amount: f64, category: LedgerCategory, entity: Option<LedgerEntity>) {
+        self.money -= amount;
+        let now = self.clock.now();
+        self.ledger
+            .record(now, category, -amount, self.money, entity);
+    }
+
+    pub fn credit(&mut self, amount: f64, category: LedgerCategory, entity: Option<LedgerEntity>) {
+        self.money += amount;
+        let now = self.clock.now();
+        self.ledger
+            .record(now, category, amount, self.money, entity);
+    }
+
+    pub fn add_alert(&mut self, rule: AlertRule) -> Result<AlertId, Errcode> {
+        if let Some(id) = rule.ship {
+            if !self.ships.contains_key(&id) {
+                return Err(Errcode::ShipNotFound(id));
+            }
+        }
+        if let Some(id) = rule.station {
+            if !self.stations.contains_key(&id) {
+                return Err(Errcode::NoSuchStation(id));
+            }
+        }
+        self.alerts.add(rule)
+    }
+
+    // Reads the stations, the token of the player proves none of them is held
+    pub fn update_wages(&mut self, token: &mut LockToken<level::Player>, galaxy: &Galaxy) {
         self.costs = 0.0;
         for coord in self.stations.values() {
-            let station = galaxy.get_station(coord).unwrap();
-            let station = station.read().unwrap();
+            let station = galaxy.get_station(token, coord).unwrap();
+            let station = station.read(token).unwrap();
             self.costs += station.crew.sum_wages();
             self.costs += station.idle_crew.sum_wages();
         }
@@ -79,12 +139,15 @@ impl Player {
             .sum::<f64>();
     }
 
-    pub fn update_money(&mut self, syslog: &SyslogRecv, tdelta: f64) {
+    pub fn update_money(&mut self, syslog: &SyslogBuffer, tdelta: f64) {
         let before = self.money < (self.costs * 60.0);
-        self.money -= self.costs * tdelta;
+        if self.costs > 0.0 {
+            self.debit(self.costs * tdelta, LedgerCategory::Wages, None);
+        }
         let after = self.money < (self.costs * 60.0);
         if after && !before {
-            let tleft = std::time::Duration::from_secs_f64(self.money / self.costs);
+            // Large steps can take the money below 0 at once
+            let tleft = std::time::Duration::from_secs_f64(self.money.max(0.0) / self.costs);
             syslog.event(self.id, SyslogEvent::LowFunds(tleft));
         }
         if self.money < 0.0 && !self.lost {
@@ -118,7 +181,11 @@ impl Player {
         let ship_id = ship.id;
         ship.update_perf_stats();
         ship.fuel_tank = ship.fuel_tank_capacity;
-        self.money -= price;
+        self.debit(
+            price,
+            LedgerCategory::Equipment,
+            Some(LedgerEntity::Ship(ship_id)),
+        );
         self.ships.insert(id, ship);
 
         let pos = station.position;
@@ -148,10 +215,11 @@ impl Player {
         if self.money < price {
             return Err(Errcode::NotEnoughMoney(self.money, price));
         }
-        self.money -= price;
         let id = (ship.modules.len() + 1) as ShipModuleId;
         log::warn!("id: {id:?}");
         ship.modules.insert(id, modtype.new_module());
+        let entity = LedgerEntity::ShipModule(*ship_id, id);
+        self.debit(price, LedgerCategory::Equipment, Some(entity));
         Ok(id)
     }
 
@@ -170,8 +238,12 @@ impl Player {
             return Err(Errcode::NotEnoughMoney(self.money, price));
         }
 
-        self.money -= price;
         upgrade.install(ship);
+        self.debit(
+            price,
+            LedgerCategory::Equipment,
+            Some(LedgerEntity::Ship(*ship_id)),
+        );
         Ok(price)
     }
 
@@ -195,10 +267,12 @@ impl Player {
             return Err(Errcode::NotEnoughMoney(self.money, price));
         }
 
-        self.money -= price;
         module.rank += 1;
+        let rank = module.rank;
+        let entity = LedgerEntity::ShipModule(*ship_id, *mod_id);
+        self.debit(price, LedgerCategory::Equipment, Some(entity));
 
-        Ok((price, module.rank))
+        Ok((price, rank))
     }
 
     pub fn upgrade_crew_rank(
@@ -223,14 +297,95 @@ impl Player {
                 return Err(Errcode::NotEnoughMoney(self.money, price));
             }
 
-            self.money -= price;
             cm.rank += 1;
             (price, cm.rank)
         };
         ship.update_perf_stats();
+        let entity = LedgerEntity::CrewMember(*crew_id);
+        self.debit(res.0, LedgerCategory::Crew, Some(entity));
         Ok(res)
     }
 
+    // Trades from the cargo of a ship docked at a station, owned by this player or another one
+    // The order only takes the ones resting at the station and never rests itself,
+    //     so nothing is escrowed: the cargo and the money only change by what got filled
+    pub fn place_ship_order(
+        &mut self,
+        ship_id: &ShipId,
+        station: (StationId, SpaceCoord),
+        req: &OrderRequest,
+        market: &mut Market,
+        now: f64,
+    ) -> Result<(Order, Vec<MarketTx>), Errcode> {
+        req.check()?;
+        let Some(ship) = self.ships.get_mut(ship_id) else {
+            return Err(Errcode::ShipNotFound(*ship_id));
+        };
+        if ship.position != station.1 {
+            return Err(Errcode::ShipNotInStation);
+        }
+
+        let resource = req.resource;
+        let amnt = match req.side {
+            OrderSide::Buy => {
+                let amnt = req.amount.min(ship.cargo.space_for(&resource));
+                if amnt <= 0.0 {
+                    return Err(Errcode::BuyNothing);
+                }
+                let cost = amnt * req.price;
+                if cost > self.money {
+                    return Err(Errcode::NotEnoughMoney(self.money, cost));
+                }
+                amnt
+            }
+            OrderSide::Sell => {
+                let amnt = req
+                    .amount
+                    .min(ship.cargo.resources.get(&resource).copied().unwrap_or(0.0));
+                if amnt <= 0.0 {
+                    return Err(Errcode::SellNothing);
+                }
+                amnt
+            }
+        };
+
+        let (order, fills) = market
+            .orderbook
+            .take(self.id, station.0, req.side, resource, req.price, amnt, now);
+        market.record_trades(fills.len() as u64);
+
+        let mut txs = vec![];
+        let entity = Some(LedgerEntity::Order(order.id));
+        for fill in fills {
+            market.record_fill(&station.0, &resource, fill.price, fill.amount);
+            let cost = fill.amount * fill.price;
+            let ship = self.ships.get_mut(ship_id).unwrap();
+            let tx = match req.side {
+                OrderSide::Buy => {
+                    // Fits in the cargo, the amount was capped by its free space
+                    ship.cargo.add_resource(&resource, fill.amount);
+                    self.debit(cost, LedgerCategory::Trading, entity);
+                    MarketTx {
+                        added_cargo: Some((resource, fill.amount)),
+                        removed_money: Some(cost),
+                        ..Default::default()
+                    }
+                }
+                OrderSide::Sell => {
+                    ship.cargo.unload(&resource, fill.amount);
+                    self.credit(cost, LedgerCategory::Trading, entity);
+                    MarketTx {
+                        removed_cargo: Some((resource, fill.amount)),
+                        added_money: Some(cost),
+                        ..Default::default()
+                    }
+                }
+            };
+            txs.push(tx);
+        }
+        Ok((order, txs))
+    }
+
     pub fn upgrade_station_trader(&mut self, station: &mut Station) -> Result<(f64, u8), Errcode> {
         let Some(trader_id) = station.trader else {
             return Err(Errcode::NoTraderAssigned);
@@ -240,8 +395,10 @@ impl Player {
         if price > self.money {
             return Err(Errcode::NotEnoughMoney(self.money, price));
         }
-        self.money -= price;
         cm.rank += 1;
-        Ok((price, cm.rank))
+        let rank = cm.rank;
+        let entity = LedgerEntity::CrewMember(trader_id);
+        self.debit(price, LedgerCategory::Crew, Some(entity));
+        Ok((price, rank))
     }
 }
@@ -0,0 +1,187 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use strum::{EnumString, IntoStaticStr};
+
+use crate::errors::Errcode;
+use crate::galaxy::station::StationId;
+use crate::ship::resources::Resource;
+use crate::ship::ShipId;
+
+const MAX_ALERTS: usize = 50;
+
+pub type AlertId = u32;
+
+#[derive(EnumString, IntoStaticStr, Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[strum(ascii_case_insensitive)]
+pub enum AlertKind {
+    MoneyBelow,
+    // In percent of the fuel tank capacity
+    FuelBelow,
+    // In percent of the hull decay capacity
+    HullDecayAbove,
+    PriceAbove,
+    PriceBelow,
+    // In percent of the station cargo capacity
+    CargoAbove,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AlertRule {
+    pub kind: AlertKind,
+    pub threshold: f64,
+    // Restrict the rule to a single ship / station, if not set the rule applies to all of them
+    pub ship: Option<ShipId>,
+    pub station: Option<StationId>,
+    // Required for the rules on prices
+    pub resource: Option<Resource>,
+}
+
+impl AlertRule {
+    pub fn validate(&self) -> Result<(), Errcode> {
+        if !self.threshold.is_finite() || self.threshold < 0.0 {
+            return Err(Errcode::InvalidArgument("threshold"));
+        }
+        match self.kind {
+            AlertKind::FuelBelow | AlertKind::HullDecayAbove | AlertKind::CargoAbove => {
+                if self.threshold > 100.0 {
+                    return Err(Errcode::InvalidArgument("threshold"));
+                }
+            }
+            AlertKind::PriceAbove | AlertKind::PriceBelow => {
+                if self.resource.is_none() {
+                    return Err(Errcode::InvalidArgument("resource"));
+                }
+            }
+            AlertKind::MoneyBelow => {}
+        }
+        Ok(())
+    }
+
+    pub fn check(&self, value: f64) -> bool {
+        match self.kind {
+            AlertKind::MoneyBelow | AlertKind::FuelBelow | AlertKind::PriceBelow => {
+                value < self.threshold
+            }
+            AlertKind::HullDecayAbove | AlertKind::PriceAbove | AlertKind::CargoAbove => {
+                value > self.threshold
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Alert {
+    pub id: AlertId,
+    pub rule: AlertRule,
+    // An alert fires once when its condition becomes true, and is armed again once it's false
+    pub triggered: bool,
+}
+
+#[derive(Default)]
+pub struct Alerts {
+    next_id: AlertId,
+    alerts: BTreeMap<AlertId, Alert>,
+}
+
+impl Alerts {
+    pub fn add(&mut self, rule: AlertRule) -> Result<AlertId, Errcode> {
+        rule.validate()?;
+        if self.alerts.len() >= MAX_ALERTS {
+            return Err(Errcode::TooManyAlerts(MAX_ALERTS));
+        }
+        self.next_id += 1;
+        let id = self.next_id;
+        let alert = Alert {
+            id,
+            rule,
+            triggered: false,
+        };
+        self.alerts.insert(id, alert);
+        Ok(id)
+    }
+
+    pub fn get(&self, id: &AlertId) -> Result<&Alert, Errcode> {
+        self.alerts.get(id).ok_or(Errcode::NoSuchAlert(*id))
+    }
+
+    pub fn set_threshold(&mut self, id: &AlertId, threshold: f64) -> Result<&Alert, Errcode> {
+        let alert = self.alerts.get_mut(id).ok_or(Errcode::NoSuchAlert(*id))?;
+        let rule = AlertRule {
+            threshold,
+            ..alert.rule.clone()
+        };
+        rule.validate()?;
+        alert.rule = rule;
+        alert.triggered = false;
+        Ok(alert)
+    }
+
+    pub fn remove(&mut self, id: &AlertId) -> Result<Alert, Errcode> {
+        self.alerts.remove(id).ok_or(Errcode::NoSuchAlert(*id))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Alert> {
+        self.alerts.values()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.alerts.is_empty()
+    }
+
+    // Returns the alerts that just fired, along with the value that triggered them
+    // The observer gives None when the value can't be known (ship destroyed, etc...)
+    pub fn evaluate<F>(&mut self, mut observe: F) -> Vec<(Alert, f64)>
+    where
+        F: FnMut(&AlertRule) -> Option<f64>,
+    {
+        let mut fired = vec![];
+        for alert in self.alerts.values_mut() {
+            let Some(value) = observe(&alert.rule) else {
+                continue;
+            };
+            let active = alert.rule.check(value);
+            if active && !alert.triggered {
+                fired.push((alert.clone(), value));
+            }
+            alert.triggered = active;
+        }
+        fired
+    }
+}
+
+#[test]
+fn test_alerts_trigger_once() {
+    let mut alerts = Alerts::default();
+    let rule = AlertRule {
+        kind: AlertKind::MoneyBelow,
+        threshold: 1000.0,
+        ship: None,
+        station: None,
+        resource: None,
+    };
+    let id = alerts.add(rule.clone()).unwrap();
+
+    let price_rule = AlertRule {
+        kind: AlertKind::PriceAbove,
+        ..rule
+    };
+    assert!(alerts.add(price_rule).is_err());
+
+    assert!(alerts.evaluate(|_| Some(2000.0)).is_empty());
+    let fired = alerts.evaluate(|_| Some(500.0));
+    assert_eq!(fired.len(), 1);
+    assert_eq!(fired[0].0.id, id);
+    assert_eq!(fired[0].1, 500.0);
+
+    // Stays silent while the condition holds, fires again after going back above
+    assert!(alerts.evaluate(|_| Some(400.0)).is_empty());
+    assert!(alerts.evaluate(|_| Some(1500.0)).is_empty());
+    assert_eq!(alerts.evaluate(|_| Some(100.0)).len(), 1);
+
+    alerts.set_threshold(&id, 50.0).unwrap();
+    assert!(alerts.evaluate(|_| Some(100.0)).is_empty());
+    alerts.remove(&id).unwrap();
+    assert!(alerts.is_empty());
+    assert!(alerts.get(&id).is_err());
+}
@@ -0,0 +1,192 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use serde::{Deserialize, Serialize};
+use strum::{EnumIter, EnumString, IntoEnumIterator, IntoStaticStr};
+
+use crate::crew::CrewId;
+use crate::galaxy::station::StationId;
+use crate::market::orderbook::OrderId;
+use crate::ship::module::ShipModuleId;
+use crate::ship::resources::Resource;
+use crate::ship::ShipId;
+
+const LEDGER_MAX_ENTRIES: usize = 10000;
+// Wages are paid at every tick, they are grouped in a single entry per period
+const LEDGER_WAGES_PERIOD: f64 = 60.0;
+
+#[derive(
+    EnumIter,
+    EnumString,
+    IntoStaticStr,
+    Serialize,
+    Deserialize,
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+)]
+#[strum(ascii_case_insensitive)]
+pub enum LedgerCategory {
+    Wages,
+    Trading,
+    Fees,
+    Equipment,
+    Crew,
+    // Money granted, or taken back, by an admin
+    Grant,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum LedgerEntity {
+    Ship(ShipId),
+    ShipModule(ShipId, ShipModuleId),
+    CrewMember(CrewId),
+    Station(StationId),
+    Resource(Resource),
+    Order(OrderId),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LedgerEntry {
+    pub id: u64,
+    pub timestamp: f64,
+    pub category: LedgerCategory,
+    // Positive for a credit, negative for a debit
+    pub amount: f64,
+    pub balance: f64,
+    pub entity: Option<LedgerEntity>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct CategoryTotal {
+    pub credit: f64,
+    pub debit: f64,
+    pub net: f64,
+}
+
+#[derive(Default)]
+pub struct Ledger {
+    next_id: u64,
+    entries: VecDeque<LedgerEntry>,
+    // Entry of the wages for the current period, not yet in the ledger
+    wages: Option<LedgerEntry>,
+    totals: BTreeMap<LedgerCategory, CategoryTotal>,
+}
+
+impl Ledger {
+    pub fn record(
+        &mut self,
+        timestamp: f64,
+        category: LedgerCategory,
+        amount: f64,
+        balance: f64,
+        entity: Option<LedgerEntity>,
+    ) {
+        let total = self.totals.entry(category).or_default();
+        if amount >= 0.0 {
+            total.credit += amount;
+        } else {
+            total.debit -= amount;
+        }
+        total.net += amount;
+
+        if category == LedgerCategory::Wages {
+            if let Some(ref mut wages) = self.wages {
+                if timestamp - wages.timestamp < LEDGER_WAGES_PERIOD {
+                    wages.amount += amount;
+                    wages.balance = balance;
+                    return;
+                }
+            }
+            let entry = self.new_entry(timestamp, category, amount, balance, entity);
+            if let Some(wages) = self.wages.replace(entry) {
+                self.push(wages);
+            }
+            return;
+        }
+
+        let entry = self.new_entry(timestamp, category, amount, balance, entity);
+        self.push(entry);
+    }
+
+    fn new_entry(
+        &mut self,
+        timestamp: f64,
+        category: LedgerCategory,
+        amount: f64,
+        balance: f64,
+        entity: Option<LedgerEntity>,
+    ) -> LedgerEntry {
+        self.next_id += 1;
+        LedgerEntry {
+            id: self.next_id,
+            timestamp,
+            category,
+            amount,
+            balance,
+            entity,
+        }
+    }
+
+    // Keeps the entries sorted by ID, the wages entry being inserted once its period is over
+    fn push(&mut self, entry: LedgerEntry) {
+        if self.entries.len() == LEDGER_MAX_ENTRIES {
+            self.entries.pop_front();
+        }
+        let pos = self.entries.partition_point(|e| e.id < entry.id);
+        self.entries.insert(pos, entry);
+    }
+
+    // Entries from the most recent to the oldest, including the wages being accumulated
+    pub fn entries(&self) -> impl Iterator<Item = &LedgerEntry> {
+        let mut all = self.entries.iter().collect::<Vec<&LedgerEntry>>();
+        if let Some(ref wages) = self.wages {
+            let pos = all.partition_point(|e| e.id < wages.id);
+            all.insert(pos, wages);
+        }
+        all.into_iter().rev()
+    }
+
+    // Profit & loss aggregated by category, since the start of the game
+    pub fn profit_and_loss(&self) -> BTreeMap<LedgerCategory, CategoryTotal> {
+        LedgerCategory::iter()
+            .map(|c| (c, self.totals.get(&c).cloned().unwrap_or_default()))
+            .collect()
+    }
+}
+
+#[test]
+fn test_ledger_wages_grouping() {
+    let mut ledger = Ledger::default();
+    ledger.record(0.0, LedgerCategory::Wages, -1.0, 99.0, None);
+    ledger.record(
+        1.0,
+        LedgerCategory::Equipment,
+        -10.0,
+        89.0,
+        Some(LedgerEntity::Ship(1)),
+    );
+    ledger.record(30.0, LedgerCategory::Wages, -1.0, 88.0, None);
+    ledger.record(
+        LEDGER_WAGES_PERIOD + 1.0,
+        LedgerCategory::Wages,
+        -1.0,
+        87.0,
+        None,
+    );
+
+    let entries = ledger.entries().collect::<Vec<&LedgerEntry>>();
+    assert_eq!(entries.len(), 3);
+    assert_eq!(entries[0].amount, -1.0);
+    assert_eq!(entries[1].category, LedgerCategory::Equipment);
+    assert_eq!(entries[2].amount, -2.0);
+    assert_eq!(entries[2].balance, 88.0);
+
+    let pnl = ledger.profit_and_loss();
+    assert_eq!(pnl.get(&LedgerCategory::Wages).unwrap().debit, 3.0);
+    assert_eq!(pnl.get(&LedgerCategory::Equipment).unwrap().net, -10.0);
+    assert_eq!(pnl.get(&LedgerCategory::Trading).unwrap().net, 0.0);
+}
@@ -0,0 +1,170 @@
+// Scores of the players, used to rank them on the leaderboard
+use std::collections::{BTreeMap, VecDeque};
+use std::str::FromStr;
+
+use serde::Serialize;
+
+use crate::errors::Errcode;
+use crate::player::PlayerId;
+
+// Game time between two snapshots of the scores, and number of snapshots kept
+pub const SCORE_SNAPSHOT_PERIOD: f64 = 60.0;
+const SCORE_HISTORY_MAX: usize = 24 * 60;
+
+// Weight of each part of the net worth in the score
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+pub struct ScoreFormula {
+    pub money: f64,
+    pub ships: f64,
+    pub cargo: f64,
+    pub crew: f64,
+}
+
+impl Default for ScoreFormula {
+    fn default() -> Self {
+        ScoreFormula {
+            money: 1.0,
+            ships: 1.0,
+            cargo: 1.0,
+            crew: 1.0,
+        }
+    }
+}
+
+impl FromStr for ScoreFormula {
+    type Err = Errcode;
+
+    // Either the name of a preset, or weights as "money=1,ships=0.5", missing parts weigh 0
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let zero = ScoreFormula {
+            money: 0.0,
+            ships: 0.0,
+            cargo: 0.0,
+            crew: 0.0,
+        };
+        match s {
+            "networth" => return Ok(ScoreFormula::default()),
+            "money" => return Ok(ScoreFormula { money: 1.0, ..zero }),
+            "assets" => {
+                return Ok(ScoreFormula {
+                    money: 0.0,
+                    ..Default::default()
+                })
+            }
+            _ => {}
+        }
+
+        let mut formula = zero;
+        for part in s.split(',') {
+            let Some((name, weight)) = part.split_once('=') else {
+                return Err(Errcode::InvalidArgument("formula"));
+            };
+            let Ok(weight) = weight.trim().parse::<f64>() else {
+                return Err(Errcode::InvalidArgument("formula"));
+            };
+            match name.trim() {
+                "money" => formula.money = weight,
+                "ships" => formula.ships = weight,
+                "cargo" => formula.cargo = weight,
+                "crew" => formula.crew = weight,
+                _ => return Err(Errcode::InvalidArgument("formula")),
+            }
+        }
+        Ok(formula)
+    }
+}
+
+// Parts of the net worth of a player, in money
+#[derive(Serialize, Clone, Default, Debug)]
+pub struct Score {
+    pub money: f64,
+    // Price of the ships, modules included
+    pub ships: f64,
+    // Resources in the stations and the ships, at the market prices
+    pub cargo: f64,
+    // Money spent on the ranks of the crew members
+    pub crew: f64,
+    pub total: f64,
+}
+
+impl Score {
+    pub fn compute_total(&mut self, formula: &ScoreFormula) {
+        self.total = self.money * formula.money
+            + self.ships * formula.ships
+            + self.cargo * formula.cargo
+            + self.crew * formula.crew;
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct RankEntry {
+    pub rank: usize,
+    pub player: PlayerId,
+    pub name: String,
+    pub lost: bool,
+    pub score: Score,
+}
+
+// Players still in the game first, then from the best score to the worst
+pub fn rank(mut entries: Vec<RankEntry>) -> Vec<RankEntry> {
+    entries.sort_by(|a, b| {
+        a.lost
+            .cmp(&b.lost)
+            .then(b.score.total.total_cmp(&a.score.total))
+    });
+    for (n, entry) in entries.iter_mut().enumerate() {
+        entry.rank = n + 1;
+    }
+    entries
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ScoreSnapshot {
+    pub time: f64,
+    pub scores: BTreeMap<PlayerId, f64>,
+}
+
+#[derive(Default)]
+pub struct ScoreHistory {
+    snapshots: VecDeque<ScoreSnapshot>,
+}
+
+impl ScoreHistory {
+    pub fn last_time(&self) -> Option<f64> {
+        self.snapshots.back().map(|s| s.time)
+    }
+
+    pub fn push(&mut self, snapshot: ScoreSnapshot) {
+        if self.snapshots.len() >= SCORE_HISTORY_MAX {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(snapshot);
+    }
+
+    pub fn since(&self, time: f64) -> impl Iterator<Item = &ScoreSnapshot> {
+        self.snapshots.iter().filter(move |s| s.time >= time)
+    }
+}
+
+#[test]
+fn test_score_formula() {
+    assert_eq!(
+        ScoreFormula::from_str("networth").unwrap(),
+        ScoreFormula::default()
+    );
+    let formula = ScoreFormula::from_str("money=1, cargo=0.5").unwrap();
+    assert_eq!(formula.cargo, 0.5);
+    assert_eq!(formula.ships, 0.0);
+    assert!(ScoreFormula::from_str("money=a").is_err());
+    assert!(ScoreFormula::from_str("fame=1").is_err());
+
+    let mut score = Score {
+        money: 100.0,
+        ships: 50.0,
+        cargo: 10.0,
+        crew: 1000.0,
+        total: 0.0,
+    };
+    score.compute_total(&formula);
+    assert_eq!(score.total, 105.0);
+}
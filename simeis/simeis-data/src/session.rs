@@ -0,0 +1,164 @@
+// Sessions split the game in matches of a fixed length, with a lobby phase before each one
+// Times are in game time, seconds since the start of the server
+use serde::Serialize;
+
+use crate::score::RankEntry;
+
+pub type SessionId = u32;
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionPhase {
+    // Players can register, but not play yet
+    Lobby,
+    Running,
+    // Every action fails with a GameOver error, the ranking is frozen
+    Over,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SessionConfig {
+    // Duration of the lobby phase, the match starts right away if 0
+    pub lobby: f64,
+    // Duration of the match, it never ends if not set
+    pub duration: Option<f64>,
+    // Start a new session when the match is over, with all the players starting over
+    pub rollover: bool,
+    // Directory where the final ranking of each session is written
+    pub export_dir: Option<String>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Session {
+    pub id: SessionId,
+    pub phase: SessionPhase,
+    pub created: f64,
+    // End of the lobby phase
+    pub start: f64,
+    pub end: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ranking: Option<Vec<RankEntry>>,
+}
+
+impl Session {
+    fn new(id: SessionId, config: &SessionConfig, now: f64) -> Session {
+        let start = now + config.lobby;
+        Session {
+            id,
+            phase: if config.lobby > 0.0 {
+                SessionPhase::Lobby
+            } else {
+                SessionPhase::Running
+            },
+            created: now,
+            start,
+            end: config.duration.map(|d| start + d),
+            ranking: None,
+        }
+    }
+}
+
+pub struct Sessions {
+    pub config: SessionConfig,
+    pub current: Session,
+    // Sessions that are over, with their final ranking
+    pub finished: Vec<Session>,
+}
+
+impl Sessions {
+    pub fn new(config: SessionConfig) -> Sessions {
+        let current = Session::new(1, &config, 0.0);
+        Sessions {
+            config,
+            current,
+            finished: vec![],
+        }
+    }
+
+    // Moves the current session to its next phase if it is time, and returns the new phase
+    pub fn update(&mut self, now: f64) -> Option<SessionPhase> {
+        let session = &mut self.current;
+        match session.phase {
+            SessionPhase::Lobby if now >= session.start => session.phase = SessionPhase::Running,
+            SessionPhase::Running if session.end.is_some_and(|end| now >= end) => {
+                session.phase = SessionPhase::Over
+            }
+            _ => return None,
+        }
+        Some(session.phase)
+    }
+
+    pub fn finish(&mut self, ranking: Vec<RankEntry>) -> Session {
+        self.current.ranking = Some(ranking);
+        self.finished.push(self.current.clone());
+        self.current.clone()
+    }
+
+    pub fn rollover(&mut self, now: f64) {
+        self.current = Session::new(self.current.id + 1, &self.config, now);
+    }
+
+    // Every session, oldest first
+    // Once over, the current session is also in the finished ones until the next rollover,
+    //     it is only listed once, with its ranking
+    pub fn list(&self) -> Vec<&Session> {
+        let current = Some(&self.current).filter(|c| !self.finished.iter().any(|s| s.id == c.id));
+        self.finished.iter().chain(current).collect()
+    }
+
+    pub fn get(&self, id: SessionId) -> Option<&Session> {
+        if id == self.current.id {
+            return Some(&self.current);
+        }
+        self.finished.iter().find(|s| s.id == id)
+    }
+}
+
+#[test]
+fn test_session_phases() {
+    let config = SessionConfig {
+        lobby: 10.0,
+        duration: Some(100.0),
+        ..Default::default()
+    };
+    let mut sessions = Sessions::new(config);
+    assert_eq!(sessions.current.phase, SessionPhase::Lobby);
+    assert_eq!(sessions.update(5.0), None);
+    assert_eq!(sessions.update(10.0), Some(SessionPhase::Running));
+    assert_eq!(sessions.update(50.0), None);
+    assert_eq!(sessions.update(110.0), Some(SessionPhase::Over));
+    assert_eq!(sessions.update(200.0), None);
+
+    sessions.finish(vec![]);
+    sessions.rollover(200.0);
+    assert_eq!(sessions.current.id, 2);
+    assert_eq!(sessions.current.end, Some(310.0));
+    assert!(sessions.get(1).is_some_and(|s| s.ranking.is_some()));
+
+    // Without any limit, the match starts immediately and never ends
+    let mut sessions = Sessions::new(SessionConfig::default());
+    assert_eq!(sessions.current.phase, SessionPhase::Running);
+    assert_eq!(sessions.update(1e9), None);
+}
+
+#[test]
+fn test_session_list() {
+    let config = SessionConfig {
+        duration: Some(100.0),
+        ..Default::default()
+    };
+    let mut sessions = Sessions::new(config);
+    assert_eq!(sessions.list().len(), 1);
+
+    // Without any rollover, the session stays listed once over
+    assert_eq!(sessions.update(100.0), Some(SessionPhase::Over));
+    sessions.finish(vec![]);
+    let list = sessions.list();
+    assert_eq!(list.len(), 1);
+    assert_eq!(list[0].id, 1);
+    assert!(list[0].ranking.is_some());
+
+    sessions.rollover(100.0);
+    let ids = sessions.list().iter().map(|s| s.id).collect::<Vec<_>>();
+    assert_eq!(ids, vec![1, 2]);
+}
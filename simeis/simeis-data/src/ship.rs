@@ -7,11 +7,14 @@ use rand::Rng;
 use resources::{ExtractionInfo, Resource};
 use serde::{Deserialize, Serialize};
 use shipstats::ShipStats;
+use strum::IntoStaticStr;
 
 use crate::crew::{Crew, CrewId, CrewMemberType};
 use crate::errors::Errcode;
 use crate::galaxy::station::Station;
 use crate::galaxy::{translation, Galaxy, SpaceCoord};
+use crate::lock::level::{self, Before};
+use crate::lock::LockToken;
 
 pub mod cargo;
 pub mod module;
@@ -32,7 +35,7 @@ const REACTOR_SPEED_PER_POWER: f64 = 50.0;
 
 pub type ShipId = u64;
 
-#[derive(Debug, Deserialize, Serialize, Default)]
+#[derive(Debug, Deserialize, Serialize, Default, IntoStaticStr)]
 pub enum ShipState {
     #[default]
     Idle,
@@ -169,11 +172,6 @@ impl Ship {
             self.stats.speed = 0.0;
         };
         self.stats.speed *= 1.0 - self.cargo.slowing_ratio();
-
-        #[cfg(feature = "testing")]
-        {
-            self.stats.speed *= 100.0
-        };
     }
 
     pub fn compute_travel_costs(&self, destination: SpaceCoord) -> Result<TravelCost, Errcode> {
@@ -237,11 +235,15 @@ impl Ship {
         finished
     }
 
-    pub fn start_extraction(&mut self, galaxy: &Galaxy) -> Result<ExtractionInfo, Errcode> {
+    pub fn start_extraction(
+        &mut self,
+        token: &mut LockToken<impl Before<level::Galaxy>>,
+        galaxy: &Galaxy,
+    ) -> Result<ExtractionInfo, Errcode> {
         let ShipState::Idle = self.state else {
             return Err(Errcode::ShipNotIdle);
         };
-        let Some(planet) = galaxy.get_planet(&self.position) else {
+        let Some(planet) = galaxy.get_planet(token, &self.position) else {
             return Err(Errcode::CannotExtractWithoutPlanet);
         };
         log::debug!(
@@ -22,6 +22,8 @@ pub type ShipModuleId = u16;
     Eq,
     PartialOrd,
     Ord,
+    Clone,
+    Copy,
 )]
 #[strum(ascii_case_insensitive)]
 pub enum ShipModuleType {
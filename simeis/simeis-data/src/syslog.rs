@@ -1,68 +1,159 @@
 #![allow(clippy::type_complexity)]
-use std::collections::BTreeMap;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::mpsc::{Receiver, Sender, TryRecvError};
-use std::sync::{Arc, RwLock};
+use std::sync::Arc;
 
 use serde::{Deserialize, Serialize};
 use strum::IntoStaticStr;
+use tokio::sync::broadcast;
 
+use crate::galaxy::station::StationId;
+use crate::game::GameClock;
+use crate::lock::level::{self, Before};
+use crate::lock::{LockToken, OrderedLock};
 use crate::player::PlayerId;
 
-const SYSLOG_FIFO_MAX_SIZE: usize = 10;
+pub const SYSLOG_HISTORY_DEFAULT_SIZE: usize = 1000;
+// Clients of a player keeping a cursor, the one unused for the longest time is dropped above it
+pub const SYSLOG_MAX_CLIENTS: usize = 16;
+// Notifications a slow subscriber can miss before it is told it lagged behind
+const SYSLOG_NOTIFY_CAPACITY: usize = 1024;
 
-type SyslogData = (PlayerId, f64, SyslogEvent);
-pub struct Fifo<T> {
-    list: [Option<T>; SYSLOG_FIFO_MAX_SIZE],
-    push_ind: usize,
-    pop_ind: usize,
-    len: usize,
+pub type SyslogSeq = u64;
+
+// Player, real time when sent (for the latency), and game time of the event
+type SyslogData = (PlayerId, f64, f64, SyslogEvent);
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SyslogEntry {
+    pub seq: SyslogSeq,
+    pub timestamp: f64,
+    pub event: SyslogEvent,
 }
 
-impl<T: Copy> Default for Fifo<T> {
-    fn default() -> Self {
-        Self::new()
-    }
+// Last events of a player, numbered so that clients can resume from where they stopped
+// Reading the events doesn't remove them, the oldest ones are dropped when the history is full
+pub struct SyslogHistory {
+    max_size: usize,
+    next_seq: SyslogSeq,
+    // Last sequence number each client of the player told us it has processed,
+    //     the client used the most recently is last
+    acked: VecDeque<(String, SyslogSeq)>,
+    entries: VecDeque<SyslogEntry>,
 }
 
-impl<T> Fifo<T> {
-    pub fn new() -> Fifo<T> {
-        Fifo {
-            len: 0,
-            push_ind: 0,
-            pop_ind: 0,
-            list: [const { None }; SYSLOG_FIFO_MAX_SIZE],
+impl SyslogHistory {
+    pub fn with_capacity(max_size: usize) -> SyslogHistory {
+        SyslogHistory {
+            max_size: max_size.max(1),
+            next_seq: 0,
+            acked: VecDeque::new(),
+            entries: VecDeque::new(),
         }
     }
 
-    pub fn push(&mut self, data: T) {
-        if (self.len > 0) && (self.push_ind == self.pop_ind) {
-            self.pop_ind = (self.pop_ind + 1) % SYSLOG_FIFO_MAX_SIZE;
+    pub fn push(&mut self, timestamp: f64, event: SyslogEvent) -> SyslogSeq {
+        if self.entries.len() == self.max_size {
+            self.entries.pop_front();
         }
-        *self.list.get_mut(self.push_ind).unwrap() = Some(data);
-        self.push_ind = (self.push_ind + 1) % SYSLOG_FIFO_MAX_SIZE;
-        self.len = (self.len + 1).min(SYSLOG_FIFO_MAX_SIZE);
+        self.next_seq += 1;
+        self.entries.push_back(SyslogEntry {
+            seq: self.next_seq,
+            timestamp,
+            event,
+        });
+        self.next_seq
+    }
+
+    // Sequence number of the last event produced
+    pub fn last_seq(&self) -> SyslogSeq {
+        self.next_seq
+    }
+
+    // Sequence number of the oldest event still available
+    pub fn first_seq(&self) -> SyslogSeq {
+        self.entries
+            .front()
+            .map(|e| e.seq)
+            .unwrap_or(self.next_seq + 1)
+    }
+
+    pub fn acked(&self, client: &str) -> SyslogSeq {
+        self.acked
+            .iter()
+            .find(|(c, _)| c == client)
+            .map(|(_, seq)| *seq)
+            .unwrap_or(0)
     }
 
-    fn pop(&mut self) -> Option<T> {
-        if self.len == 0 {
-            return None;
+    // Moves the cursor of this client only, the other clients of the player still get the events
+    pub fn ack(&mut self, client: &str, seq: SyslogSeq) -> SyslogSeq {
+        let prev = match self.acked.iter().position(|(c, _)| c == client) {
+            Some(n) => self.acked.remove(n).unwrap().1,
+            None => 0,
+        };
+        if self.acked.len() >= SYSLOG_MAX_CLIENTS {
+            self.acked.pop_front();
         }
+        let acked = prev.max(seq.min(self.next_seq));
+        self.acked.push_back((client.to_string(), acked));
+        acked
+    }
+
+    pub fn since(&self, seq: SyslogSeq) -> impl DoubleEndedIterator<Item = &SyslogEntry> {
+        let start = self.entries.partition_point(|e| e.seq <= seq);
+        self.entries.range(start..)
+    }
+}
+
+pub type SyslogHistoryLock = OrderedLock<SyslogHistory, level::SyslogHistory>;
+pub type SyslogHistories =
+    Arc<OrderedLock<BTreeMap<PlayerId, SyslogHistoryLock>, level::SyslogHistories>>;
+// Tells the subscribers which player got a new event in its history
+pub type SyslogNotify = broadcast::Sender<PlayerId>;
+
+// Health of the pipeline between the senders and the game thread
+// Latencies are the time (in seconds) between an event being sent and it reaching the history
+#[derive(Default)]
+pub struct SyslogStats {
+    pending: AtomicU64,
+    delivered: AtomicU64,
+    last_latency: AtomicU64,
+    max_latency: AtomicU64,
+}
+
+impl SyslogStats {
+    // Number of events sent but not yet in the history
+    pub fn pending(&self) -> u64 {
+        self.pending.load(Ordering::Relaxed)
+    }
 
-        let data = std::mem::take(self.list.get_mut(self.pop_ind).unwrap());
-        self.pop_ind = (self.pop_ind + 1) % SYSLOG_FIFO_MAX_SIZE;
-        self.len -= 1;
-        data
+    pub fn delivered(&self) -> u64 {
+        self.delivered.load(Ordering::Relaxed)
     }
 
-    pub fn remove_all(&mut self) -> Vec<T> {
-        let mut data = vec![];
-        while self.len > 0 {
-            let Some(got) = self.pop() else {
-                continue;
-            };
-            data.push(got);
+    pub fn last_latency(&self) -> f64 {
+        f64::from_bits(self.last_latency.load(Ordering::Relaxed))
+    }
+
+    pub fn max_latency(&self) -> f64 {
+        f64::from_bits(self.max_latency.load(Ordering::Relaxed))
+    }
+
+    fn sent(&self) {
+        self.pending.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn received(&self, latency: f64) {
+        self.pending.fetch_sub(1, Ordering::Relaxed);
+        self.delivered.fetch_add(1, Ordering::Relaxed);
+        self.last_latency
+            .store(latency.to_bits(), Ordering::Relaxed);
+        if latency > self.max_latency() {
+            self.max_latency.store(latency.to_bits(), Ordering::Relaxed);
         }
-        data
     }
 }
 
@@ -70,71 +161,153 @@ impl<T> Fifo<T> {
 pub struct SyslogSend {
     sender: Sender<SyslogData>,
     tstart: std::time::Instant,
+    clock: GameClock,
+    stats: Arc<SyslogStats>,
 }
 
 impl SyslogSend {
-    pub fn channel() -> (SyslogSend, SyslogRecv) {
+    pub fn channel(history_size: usize, clock: GameClock) -> (SyslogSend, SyslogRecv) {
         let (sender, recv) = std::sync::mpsc::channel();
         let tstart = std::time::Instant::now();
-        let syslogsend = SyslogSend { sender, tstart };
-        (syslogsend, SyslogRecv::init(recv, tstart))
+        let stats = Arc::new(SyslogStats::default());
+        let syslogsend = SyslogSend {
+            sender,
+            tstart,
+            clock: clock.clone(),
+            stats: stats.clone(),
+        };
+        (
+            syslogsend,
+            SyslogRecv::init(recv, tstart, clock, history_size, stats),
+        )
     }
 
     pub fn event(&self, player: &PlayerId, evt: SyslogEvent) {
         let ns = self.tstart.elapsed().as_secs_f64();
-        self.sender.send((*player, ns, evt)).unwrap();
+        self.stats.sent();
+        self.sender
+            .send((*player, ns, self.clock.now(), evt))
+            .unwrap();
     }
-}
 
-pub type SyslogFifo = Arc<RwLock<BTreeMap<PlayerId, RwLock<Fifo<(f64, SyslogEvent)>>>>>;
+    pub fn stats(&self) -> Arc<SyslogStats> {
+        self.stats.clone()
+    }
+}
 
 pub struct SyslogRecv {
     recv: Receiver<SyslogData>,
-    pub(crate) fifo: SyslogFifo,
+    pub(crate) history: SyslogHistories,
+    pub(crate) notify: SyslogNotify,
+    history_size: usize,
     tstart: std::time::Instant,
+    clock: GameClock,
+    stats: Arc<SyslogStats>,
 }
 
 impl SyslogRecv {
-    pub fn init(recv: Receiver<SyslogData>, tstart: std::time::Instant) -> SyslogRecv {
+    pub fn init(
+        recv: Receiver<SyslogData>,
+        tstart: std::time::Instant,
+        clock: GameClock,
+        history_size: usize,
+        stats: Arc<SyslogStats>,
+    ) -> SyslogRecv {
         SyslogRecv {
             recv,
             tstart,
-            fifo: Arc::new(RwLock::new(BTreeMap::new())),
+            clock,
+            history_size,
+            stats,
+            history: Arc::new(OrderedLock::new(BTreeMap::new())),
+            notify: broadcast::channel(SYSLOG_NOTIFY_CAPACITY).0,
         }
     }
 
-    pub fn update(&self) {
-        match self.recv.try_recv() {
-            Ok((id, ns, evt)) => self.add_to_fifo(id, ns, evt),
-            Err(TryRecvError::Empty) => {}
-            Err(e) => {
-                let msg = format!("Error while receiving syslog: {e:?}");
-                log::error!("{}", msg);
-                panic!("{}", msg);
+    // Deliver what was sent before this call, what comes in meanwhile waits for the next one
+    pub fn update(&self, token: &mut LockToken<impl Before<level::SyslogHistories>>) {
+        for _ in 0..self.stats.pending() {
+            match self.recv.try_recv() {
+                Ok((id, ns, timestamp, evt)) => {
+                    self.stats
+                        .received(self.tstart.elapsed().as_secs_f64() - ns);
+                    self.add_to_history(token, id, timestamp, evt);
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(e) => {
+                    let msg = format!("Error while receiving syslog: {e:?}");
+                    log::error!("{}", msg);
+                    panic!("{}", msg);
+                }
             }
         }
     }
 
-    pub fn event(&self, player: PlayerId, evt: SyslogEvent) {
-        self.add_to_fifo(player, self.tstart.elapsed().as_secs_f64(), evt);
+    pub fn event(
+        &self,
+        token: &mut LockToken<impl Before<level::SyslogHistories>>,
+        player: PlayerId,
+        evt: SyslogEvent,
+    ) {
+        self.add_to_history(token, player, self.clock.now(), evt);
+    }
+
+    pub fn flush(
+        &self,
+        token: &mut LockToken<impl Before<level::SyslogHistories>>,
+        buffer: SyslogBuffer,
+    ) {
+        let now = self.clock.now();
+        for (player, evt) in buffer.0.into_inner() {
+            self.add_to_history(token, player, now, evt);
+        }
     }
 
-    fn add_to_fifo(&self, id: PlayerId, ns: f64, evt: SyslogEvent) {
+    fn add_to_history(
+        &self,
+        token: &mut LockToken<impl Before<level::SyslogHistories>>,
+        id: PlayerId,
+        ns: f64,
+        evt: SyslogEvent,
+    ) {
         log::debug!("Player {id} got event {evt:?}");
-        let ok = {
-            if let Some(fifo) = self.fifo.read().unwrap().get(&id) {
-                fifo.write().unwrap().push((ns, evt.clone()));
-                true
-            } else {
-                false
-            }
-        };
+        self.push_to_history(token, id, ns, evt);
+        // Only fails when nobody is subscribed
+        let _ = self.notify.send(id);
+    }
 
-        if !ok {
-            let mut fifo = Fifo::new();
-            fifo.push((ns, evt));
-            self.fifo.write().unwrap().insert(id, RwLock::new(fifo));
+    fn push_to_history(
+        &self,
+        token: &mut LockToken<impl Before<level::SyslogHistories>>,
+        id: PlayerId,
+        ns: f64,
+        evt: SyslogEvent,
+    ) {
+        {
+            let mut histories = self.history.read(token).unwrap();
+            let (histories, token) = histories.split();
+            if let Some(history) = histories.get(&id) {
+                history.write(token).unwrap().push(ns, evt);
+                return;
+            }
         }
+
+        let mut history = SyslogHistory::with_capacity(self.history_size);
+        history.push(ns, evt);
+        self.history
+            .write(token)
+            .unwrap()
+            .insert(id, OrderedLock::new(history));
+    }
+}
+
+// Events produced away from the game thread, delivered later in the order they were produced
+#[derive(Default)]
+pub struct SyslogBuffer(RefCell<Vec<(PlayerId, SyslogEvent)>>);
+
+impl SyslogBuffer {
+    pub fn event(&self, player: PlayerId, evt: SyslogEvent) {
+        self.0.borrow_mut().push((player, evt));
     }
 }
 
@@ -146,11 +319,77 @@ pub enum SyslogEvent {
     // General game events
     GameStarted,
     GameLost,
+    // Message sent by an admin to all the players
+    AdminMessage(String),
+    SessionStarted(crate::session::SessionId),
+    SessionEnded {
+        session: crate::session::SessionId,
+        rank: usize,
+    },
 
     // Ship
     ShipDestroyed(crate::ship::ShipId),
     ShipFlightFinished(crate::ship::ShipId),
     ExtractionStopped(crate::ship::ShipId),
+    ShipBought {
+        station: StationId,
+        ship: crate::ship::ShipId,
+        price: f64,
+    },
+    UpgradeInstalled {
+        ship: crate::ship::ShipId,
+        upgrade: crate::ship::upgrade::ShipUpgrade,
+        cost: f64,
+    },
+    ModuleInstalled {
+        ship: crate::ship::ShipId,
+        module: crate::ship::module::ShipModuleId,
+        modtype: crate::ship::module::ShipModuleType,
+        cost: f64,
+    },
+    ModuleUpgraded {
+        ship: crate::ship::ShipId,
+        module: crate::ship::module::ShipModuleId,
+        rank: u8,
+        cost: f64,
+    },
+
+    // Crew
+    CrewHired {
+        station: StationId,
+        crew: crate::crew::CrewId,
+        member_type: crate::crew::CrewMemberType,
+    },
+    // The ship is None for the trader of a station
+    CrewPromoted {
+        crew: crate::crew::CrewId,
+        ship: Option<crate::ship::ShipId>,
+        rank: u8,
+        cost: f64,
+    },
+
+    // Station
+    ShipyardRestocked {
+        station: StationId,
+        ship: crate::ship::ShipId,
+    },
+    StationCargoFull {
+        station: StationId,
+        cargo: crate::ship::cargo::ShipCargo,
+    },
+
+    // Market
+    // The order is set when the trade comes from the order book
+    TradeExecuted {
+        station: StationId,
+        order: Option<crate::market::orderbook::OrderId>,
+        tx: crate::market::MarketTx,
+    },
+    OrderExpired {
+        station: StationId,
+        order: crate::market::orderbook::OrderId,
+    },
+    PriceSpike(crate::market::PriceSpike),
 
     // Warnings
     UnloadedNothing {
@@ -158,56 +397,99 @@ pub enum SyslogEvent {
         ship_cargo: crate::ship::cargo::ShipCargo,
     },
     LowFunds(std::time::Duration),
+    AlertTriggered {
+        alert: crate::player::alert::AlertId,
+        rule: crate::player::alert::AlertRule,
+        value: f64,
+    },
+    LowHull {
+        ship: crate::ship::ShipId,
+        left: f64,
+        capacity: f64,
+    },
+    LowFuel {
+        ship: crate::ship::ShipId,
+        left: f64,
+        capacity: f64,
+    },
 }
 
 #[test]
-fn test_syslog_fifo() {
-    let mut fifo = Fifo::<usize>::new();
-
-    fifo.push(0);
-    assert_eq!(fifo.remove_all(), vec![0]);
+fn test_syslog_history() {
+    let size = 10;
+    let mut history = SyslogHistory::with_capacity(size);
+    assert_eq!(history.first_seq(), 1);
+    assert_eq!(history.since(0).count(), 0);
 
-    let ntest = SYSLOG_FIFO_MAX_SIZE + 5;
+    let ntest = size + 5;
     for n in 0..ntest {
-        fifo.push(n);
-        println!("{} {} {:?}", fifo.len, fifo.push_ind, fifo.list);
-        assert_eq!(fifo.len, (n + 1).min(SYSLOG_FIFO_MAX_SIZE), "iter {}", n);
+        let seq = history.push(n as f64, SyslogEvent::GameStarted);
+        assert_eq!(seq, (n + 1) as SyslogSeq);
     }
-
-    println!();
-    println!("{} {} {}", fifo.len, fifo.push_ind, fifo.pop_ind);
-
-    for n in 0..ntest {
-        let got = fifo.pop();
-        println!("{} {} {:?}", fifo.len, fifo.pop_ind, fifo.list);
-        assert_eq!(
-            fifo.len,
-            SYSLOG_FIFO_MAX_SIZE.saturating_sub(n + 1),
-            "iter {}",
-            n
-        );
-
-        if n < SYSLOG_FIFO_MAX_SIZE {
-            assert_eq!(got, Some((ntest + n) - SYSLOG_FIFO_MAX_SIZE), "iter {}", n);
-        } else {
-            assert_eq!(got, None, "iter {}", n);
-        }
+    assert_eq!(history.last_seq(), ntest as SyslogSeq);
+    assert_eq!(history.first_seq(), (ntest - size + 1) as SyslogSeq);
+
+    // Reading doesn't consume anything
+    let all = history.since(0).map(|e| e.seq).collect::<Vec<SyslogSeq>>();
+    assert_eq!(all.len(), size);
+    assert_eq!(all.first(), Some(&history.first_seq()));
+    assert_eq!(history.since(0).count(), size);
+
+    let last = history.since(12).map(|e| e.seq).collect::<Vec<SyslogSeq>>();
+    assert_eq!(last, vec![13, 14, 15]);
+    assert_eq!(history.since(ntest as SyslogSeq).count(), 0);
+
+    history.ack("a", 12);
+    history.ack("a", 3);
+    assert_eq!(history.acked("a"), 12);
+    // Each client has its own cursor
+    assert_eq!(history.acked("b"), 0);
+    history.ack("b", 1000);
+    assert_eq!(history.acked("b"), history.last_seq());
+    assert_eq!(history.acked("a"), 12);
+    for n in 0..SYSLOG_MAX_CLIENTS {
+        history.ack(&n.to_string(), 1);
     }
+    assert_eq!(history.acked("a"), 0);
+}
 
-    for n in 0..(2 * ntest) {
-        fifo.push(n);
-        println!("{} {} {:?}", fifo.len, fifo.push_ind, fifo.list);
-        assert_eq!(fifo.len, (n + 1).min(SYSLOG_FIFO_MAX_SIZE), "iter {}", n);
+#[test]
+fn test_syslog_burst_delivery() {
+    let nevents = 1000;
+    let (send, recv) = SyslogSend::channel(nevents, GameClock::default());
+    for n in 0..nevents {
+        send.event(&((n % 4) as PlayerId), SyslogEvent::GameStarted);
     }
+    let stats = send.stats();
+    assert_eq!(stats.pending(), nevents as u64);
+
+    // A single tick is enough to get everything into the history
+    let mut token = LockToken::root();
+    recv.update(&mut token);
+    assert_eq!(stats.pending(), 0);
+    assert_eq!(stats.delivered(), nevents as u64);
+    assert!(stats.max_latency() >= stats.last_latency());
+    let mut histories = recv.history.read(&mut token).unwrap();
+    let (histories, token) = histories.split();
+    let total: usize = histories
+        .values()
+        .map(|h| h.read(token).unwrap().since(0).count())
+        .sum();
+    assert_eq!(total, nevents);
+}
 
-    println!();
-    fifo.push(usize::MAX);
-    let all = fifo.remove_all();
-    println!("{all:?}");
-    assert_eq!(all.len(), SYSLOG_FIFO_MAX_SIZE);
-    assert_eq!(
-        all.first(),
-        Some(&(((2 * ntest) + 1) - SYSLOG_FIFO_MAX_SIZE))
-    );
-    assert_eq!(all.last(), Some(&usize::MAX));
+#[test]
+fn test_syslog_notify() {
+    let (send, recv) = SyslogSend::channel(10, GameClock::default());
+    let mut notified = recv.notify.subscribe();
+    send.event(&3, SyslogEvent::GameStarted);
+    assert!(notified.try_recv().is_err());
+
+    // Subscribers learn about an event once it reached the history
+    let mut token = LockToken::root();
+    recv.update(&mut token);
+    assert_eq!(notified.try_recv().unwrap(), 3);
+    recv.event(&mut token, 4, SyslogEvent::GameLost);
+    assert_eq!(notified.try_recv().unwrap(), 4);
+    assert!(notified.try_recv().is_err());
 }
@@ -0,0 +1,119 @@
+use serde::Deserialize;
+use serde_json::json;
+
+use simeis_data::errors::Errcode;
+use simeis_data::galaxy::station::StationId;
+use simeis_data::lock::LockToken;
+use simeis_data::player::PlayerId;
+use simeis_data::ship::resources::Resource;
+
+use crate::api::ApiResult;
+use crate::GameState;
+
+#[derive(Deserialize)]
+pub struct ForcePriceQuery {
+    pub station: Option<StationId>,
+}
+
+#[derive(Deserialize)]
+pub struct BroadcastQuery {
+    pub message: Option<String>,
+}
+
+pub fn list_players(srv: &GameState) -> ApiResult {
+    let mut token = LockToken::root();
+    let mut players = srv.players.read(&mut token).unwrap();
+    let (players, token) = players.split();
+    let players = players
+        .values()
+        .map(|player| {
+            let player = player.read(token).unwrap();
+            json!({
+                "id": player.id,
+                "name": player.name,
+                "money": player.money,
+                "costs": player.costs,
+                "lost": player.lost,
+                "stations": player.stations.keys().collect::<Vec<_>>(),
+                "ships": player.ships.len(),
+            })
+        })
+        .collect::<Vec<_>>();
+    Ok(json!({
+        "paused": srv.is_paused(),
+        "time_scale": srv.time_scale(),
+        "players": players,
+    }))
+}
+
+pub fn kick_player(srv: &GameState, id: PlayerId) -> ApiResult {
+    srv.kick_player(&mut LockToken::root(), &id)?;
+    log::warn!("Player {id} was kicked by an admin");
+    Ok(json!({}))
+}
+
+pub fn reset_player(srv: &GameState, id: PlayerId) -> ApiResult {
+    srv.reset_player(&mut LockToken::root(), &id)?;
+    log::warn!("Player {id} was reset by an admin");
+    Ok(json!({}))
+}
+
+pub fn grant_money(srv: &GameState, id: PlayerId, amount: f64) -> ApiResult {
+    srv.grant_money(&mut LockToken::root(), &id, amount)
+        .map(|money| json!({ "money": money }))
+}
+
+pub fn force_price(
+    srv: &GameState,
+    station: Option<StationId>,
+    resource: Resource,
+    price: f64,
+) -> ApiResult {
+    srv.force_price(&mut LockToken::root(), station.as_ref(), &resource, price)?;
+    Ok(json!({}))
+}
+
+pub fn snapshot(srv: &GameState) -> ApiResult {
+    let snapshot = srv.snapshot(&mut LockToken::root());
+    let mut path = None;
+    if let Some(dir) = srv.snapshot_dir.as_ref() {
+        let fname = format!("snapshot-{}-{:.3}.json", srv.world, srv.clock.now());
+        let fpath = std::path::Path::new(&dir).join(fname);
+        let written =
+            std::fs::create_dir_all(dir).and_then(|_| std::fs::write(&fpath, snapshot.to_string()));
+        match written {
+            Ok(()) => path = Some(fpath.display().to_string()),
+            Err(e) => log::error!("Unable to write snapshot {}: {e}", fpath.display()),
+        }
+    }
+    Ok(json!({
+        "path": path,
+        "snapshot": snapshot,
+    }))
+}
+
+pub fn set_paused(srv: &GameState, paused: bool) -> ApiResult {
+    if paused {
+        srv.pause();
+    } else {
+        srv.resume();
+    }
+    log::warn!(
+        "Game {} by an admin",
+        if paused { "paused" } else { "resumed" }
+    );
+    Ok(json!({ "paused": srv.is_paused() }))
+}
+
+pub fn set_time_scale(srv: &GameState, scale: f64) -> ApiResult {
+    srv.set_time_scale(scale)?;
+    log::warn!("Time scale set to {scale} by an admin");
+    Ok(json!({ "time_scale": srv.time_scale() }))
+}
+
+pub fn broadcast(srv: &GameState, message: &str) -> ApiResult {
+    if message.trim().is_empty() {
+        return Err(Errcode::InvalidArgument("message"));
+    }
+    Ok(json!({ "players": srv.broadcast(&mut LockToken::root(), message) }))
+}
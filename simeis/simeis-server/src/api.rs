@@ -1,72 +1,125 @@
-use std::collections::BTreeMap;
-use std::ops::{Deref, DerefMut};
 use std::str::FromStr;
+use std::sync::Arc;
 
 use base64::{prelude::BASE64_STANDARD, Engine};
-use ntex::web::types::Path;
+use ntex::http::HeaderMap;
+use ntex::web::types::{Path, Query, State};
 use ntex::web::{self, HttpRequest, HttpResponse, ServiceConfig};
-use serde_json::{json, Value};
+use serde::Deserialize;
+use serde_json::Value;
 use simeis_data::crew::{CrewId, CrewMemberType};
-use simeis_data::galaxy::station::StationId;
+use simeis_data::galaxy::station::{StationId, StationLock};
 use simeis_data::galaxy::SpaceUnit;
-use simeis_data::market::fee_rate;
-use simeis_data::player::{PlayerId, PlayerKey};
+use simeis_data::lock::level::{self, Before};
+use simeis_data::lock::LockToken;
+use simeis_data::market::orderbook::{OrderId, OrderRequest, OrderSide, ORDER_DEFAULT_TTL};
+use simeis_data::player::alert::{AlertId, AlertKind, AlertRule};
+use simeis_data::player::{PlayerId, PlayerKey, PlayerLock};
+use simeis_data::session::SessionId;
 use simeis_data::ship::module::{ShipModuleId, ShipModuleType};
 use simeis_data::ship::resources::Resource;
 use simeis_data::ship::upgrade::ShipUpgrade;
 use simeis_data::ship::ShipId;
-use simeis_data::syslog::SyslogEvent;
-use strum::IntoEnumIterator;
+use simeis_data::syslog::SyslogSeq;
 
 pub type ApiResult = Result<serde_json::Value, Errcode>;
+pub type PlayerRef = Arc<PlayerLock>;
 
 use simeis_data::errors::Errcode;
 
+use crate::admin::{BroadcastQuery, ForcePriceQuery};
+use crate::leaderboard::{LeaderboardQuery, ScoreHistoryQuery};
+use crate::market::{MarketHistoryQuery, MarketPricesQuery, OrderBookQuery};
+use crate::metrics::{HttpMetrics, ResponseError};
+use crate::player::LedgerQuery;
+use crate::ratelimit::RateLimiter;
+use crate::syslog::{SyslogAckQuery, SyslogQuery, SyslogStreamQuery};
+use crate::world::Worlds;
 use crate::GameState;
 
-// TODO (#35) Use query parameters (with ntex::web::types::Query) instead of plain URLs
-
-macro_rules! get_player {
-    ($srv:ident, $req:ident) => {{
-        let Some(key) = get_player_key(&$req) else {
-            return build_response(Err(Errcode::NoPlayerKey));
-        };
-        let index = $srv.player_index.read().unwrap();
-        let Some(id) = index.get(&key) else {
-            return build_response(Err(Errcode::NoPlayerWithKey));
-        };
-        let players = $srv.players.read().unwrap();
-        let player = players.get(id).unwrap();
-        if player.read().unwrap().lost {
-            return build_response(Err(Errcode::PlayerLost));
+// Token sent with the request, either as an `Authorization: Bearer` header,
+//     or in the `key` query parameter kept for older clients
+pub fn token_from_parts(headers: &HeaderMap, query: &str) -> Option<String> {
+    if let Some(auth) = headers.get("authorization") {
+        let token = auth.to_str().ok()?.strip_prefix("Bearer ")?;
+        return Some(token.trim().to_string());
+    }
+    for q in query.split("&") {
+        if q.starts_with("key=") {
+            let key = q.split("=").nth(1)?;
+            return Some(urlencoding::decode(key).ok()?.into_owned());
         }
-        player.clone()
-    }};
+    }
+    None
 }
 
-macro_rules! get_station {
-    ($srv:ident, $player:ident, $id:expr) => {{
-        let player = $player.read().unwrap();
-        let Some(station_coord) = player.stations.get($id) else {
-            return build_response(Err(Errcode::NoSuchStation(*$id)));
-        };
-        $srv.galaxy.get_station(station_coord).unwrap()
-    }};
+pub fn get_request_token(req: &HttpRequest) -> Option<String> {
+    token_from_parts(req.headers(), req.query_string())
 }
 
-fn get_player_key(req: &HttpRequest) -> Option<PlayerKey> {
-    for q in req.query_string().split("&") {
-        if q.starts_with("key=") {
-            let key = q.split("=").nth(1)?;
-            let deckey = urlencoding::decode(key).ok()?;
-            let mut key = [0; 128];
-            BASE64_STANDARD
-                .decode_slice(deckey.as_ref(), &mut key)
-                .ok()?;
-            return Some(key);
-        }
+pub fn get_player_key(req: &HttpRequest) -> Option<PlayerKey> {
+    player_key_from_token(&get_request_token(req)?)
+}
+
+pub fn player_key_from_token(token: &str) -> Option<PlayerKey> {
+    let mut key = [0; 128];
+    BASE64_STANDARD
+        .decode_slice(token.as_bytes(), &mut key)
+        .ok()?;
+    Some(key)
+}
+
+// Finds the player making the request from its key
+pub fn auth_player(srv: &GameState, req: &HttpRequest) -> Result<PlayerRef, Errcode> {
+    let player = auth_player_any_phase(srv, req)?;
+    srv.check_session(&mut LockToken::root())?;
+    Ok(player)
+}
+
+// Also works outside of the match of the session,
+//     for the syslogs through which the players learn that it started or ended
+pub fn auth_player_any_phase(srv: &GameState, req: &HttpRequest) -> Result<PlayerRef, Errcode> {
+    let Some(key) = get_player_key(req) else {
+        return Err(Errcode::NoPlayerKey);
+    };
+    let mut token = LockToken::root();
+    let mut index = srv.player_index.read(&mut token).unwrap();
+    let (index, token) = index.split();
+    let Some(id) = index.get(&key) else {
+        return Err(Errcode::NoPlayerWithKey);
+    };
+    let mut players = srv.players.read(token).unwrap();
+    let (players, token) = players.split();
+    let player = players.get(id).unwrap();
+    if player.read(token).unwrap().lost {
+        return Err(Errcode::PlayerLost);
     }
-    None
+    Ok(player.clone())
+}
+
+pub fn auth_admin(srv: &GameState, req: &HttpRequest) -> Result<(), Errcode> {
+    match get_request_token(req) {
+        Some(token) if srv.is_admin_key(&token) => Ok(()),
+        _ => Err(Errcode::NotAdmin),
+    }
+}
+
+pub fn get_station(
+    srv: &GameState,
+    token: &mut LockToken<impl Before<level::Player>>,
+    player: &PlayerRef,
+    id: &StationId,
+) -> Result<Arc<StationLock>, Errcode> {
+    let mut player = player.read(token).unwrap();
+    let (player, token) = player.split();
+    let Some(station_coord) = player.stations.get(id) else {
+        return Err(Errcode::NoSuchStation(*id));
+    };
+    Ok(srv.galaxy.get_station(token, station_coord).unwrap())
+}
+
+pub fn parse_arg<T: FromStr>(arg: &str, name: &'static str) -> Result<T, Errcode> {
+    T::from_str(arg).map_err(|_| Errcode::InvalidArgument(name))
 }
 
 pub fn jsonmerge(a: &mut Value, b: &Value) {
@@ -80,67 +133,287 @@ pub fn jsonmerge(a: &mut Value, b: &Value) {
     }
 }
 
-fn build_response(res: ApiResult) -> HttpResponse {
+pub fn build_response(res: ApiResult) -> HttpResponse {
+    let mut error = None;
     let body = match res {
         Ok(mut data) => {
             jsonmerge(&mut data, &serde_json::json!({"error": "ok"}));
             data
         }
         Err(e) => {
-            serde_json::json!({"error": e.errmsg(), "type": format!("{e:?}")})
+            error = Some(ResponseError((&e).into()));
+            serde_json::to_value(&e).unwrap()
         }
     };
 
-    HttpResponse::Ok()
+    let res = HttpResponse::Ok()
         .content_type("application/json")
-        .json(&body)
+        .json(&body);
+    if let Some(error) = error {
+        res.extensions_mut().insert(error);
+    }
+    res
 }
 
 #[web::get("/ping")]
 async fn ping() -> impl web::Responder {
-    build_response(Ok(json!({"ping": "pong"})))
+    build_response(Ok(serde_json::json!({"ping": "pong"})))
+}
+
+#[web::get("/worlds")]
+async fn list_worlds(worlds: State<Arc<Worlds>>) -> impl web::Responder {
+    build_response(worlds.list())
+}
+
+#[web::get("/openapi.json")]
+async fn openapi() -> impl web::Responder {
+    HttpResponse::Ok().json(&crate::openapi::spec())
+}
+
+#[web::get("/metrics")]
+async fn metrics(srv: GameState, http: State<Arc<HttpMetrics>>) -> impl web::Responder {
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(crate::metrics::render(&srv, &http))
 }
 
 #[web::get("/syslogs")]
-async fn get_syslogs(srv: GameState, req: HttpRequest) -> impl web::Responder {
-    let player = get_player!(srv, req);
-    let player = player.read().unwrap();
-    let allfifo = srv.fifo_events.read().unwrap();
-    let Some(fifo) = allfifo.get(&player.id) else {
-        return build_response(Ok(json!({"nb": 0, "events": []})));
+async fn get_syslogs(
+    srv: GameState,
+    qry: Query<SyslogQuery>,
+    req: HttpRequest,
+) -> impl web::Responder {
+    build_response(
+        auth_player_any_phase(&srv, &req).and_then(|p| crate::syslog::get_events(&srv, p, &qry)),
+    )
+}
+
+#[web::get("/syslogs/ack/{seq}")]
+async fn ack_syslogs(
+    srv: GameState,
+    seq: Path<SyslogSeq>,
+    qry: Query<SyslogAckQuery>,
+    req: HttpRequest,
+) -> impl web::Responder {
+    build_response(
+        auth_player_any_phase(&srv, &req)
+            .and_then(|p| crate::syslog::ack_events(&srv, p, qry.client.as_ref(), *seq)),
+    )
+}
+
+#[web::get("/syslogs/stats")]
+async fn syslog_stats(srv: GameState) -> impl web::Responder {
+    build_response(crate::syslog::get_stats(&srv))
+}
+
+#[web::get("/syslogs/stream")]
+async fn stream_syslogs(
+    srv: GameState,
+    qry: Query<SyslogStreamQuery>,
+    req: HttpRequest,
+) -> Result<HttpResponse, web::Error> {
+    let player = match auth_player_any_phase(&srv, &req) {
+        Ok(player) => player,
+        Err(e) => return Ok(build_response(Err(e))),
     };
-    let mut fifo = fifo.write().unwrap();
-    let all_ev = fifo.remove_all();
-    let res = all_ev
-        .into_iter()
-        .map(|(t, ev)| {
-            let s: &'static str = ev.clone().into();
-            json!({
-                "timestamp": srv.tstart + t,
-                "type": s,
-                "event": ev,
-            })
-        })
-        .collect::<Vec<serde_json::Value>>();
-    build_response(Ok(json!({ "nb": res.len(), "events": res, })))
+    crate::syslog::stream_events(&srv, req, player, qry.since).await
+}
+
+#[derive(Deserialize)]
+struct NewPlayerQuery {
+    invite: Option<String>,
 }
 
 #[web::get("/player/new/{name}")]
-async fn new_player(srv: GameState, name: Path<String>) -> impl web::Responder {
-    build_response(srv.new_player(&name).map(|(id, key)| {
-        serde_json::json!({
-            "playerId": id,
-            "key": key,
-        })
+async fn new_player(
+    srv: GameState,
+    name: Path<String>,
+    qry: Query<NewPlayerQuery>,
+) -> impl web::Responder {
+    build_response(crate::player::new_player(
+        &srv,
+        &name,
+        qry.invite.as_deref(),
+    ))
+}
+
+#[web::get("/player/key/rotate")]
+async fn rotate_key(srv: GameState, req: HttpRequest) -> impl web::Responder {
+    build_response(auth_player(&srv, &req).and_then(|p| crate::player::rotate_key(&srv, p)))
+}
+
+#[web::get("/admin/ratelimit")]
+async fn ratelimit_stats(
+    srv: GameState,
+    limiter: State<Arc<RateLimiter>>,
+    req: HttpRequest,
+) -> impl web::Responder {
+    build_response(auth_admin(&srv, &req).and_then(|_| limiter.stats()))
+}
+
+#[web::get("/admin/invite")]
+async fn new_invite(srv: GameState, req: HttpRequest) -> impl web::Responder {
+    build_response(auth_admin(&srv, &req).and_then(|_| crate::player::new_invite(&srv)))
+}
+
+#[web::get("/admin/players")]
+async fn admin_list_players(srv: GameState, req: HttpRequest) -> impl web::Responder {
+    build_response(auth_admin(&srv, &req).and_then(|_| crate::admin::list_players(&srv)))
+}
+
+#[web::get("/admin/player/{id}/kick")]
+async fn admin_kick_player(
+    srv: GameState,
+    id: Path<PlayerId>,
+    req: HttpRequest,
+) -> impl web::Responder {
+    build_response(auth_admin(&srv, &req).and_then(|_| crate::admin::kick_player(&srv, *id)))
+}
+
+#[web::get("/admin/player/{id}/reset")]
+async fn admin_reset_player(
+    srv: GameState,
+    id: Path<PlayerId>,
+    req: HttpRequest,
+) -> impl web::Responder {
+    build_response(auth_admin(&srv, &req).and_then(|_| crate::admin::reset_player(&srv, *id)))
+}
+
+#[web::get("/admin/player/{id}/grant/{amount}")]
+async fn admin_grant_money(
+    srv: GameState,
+    args: Path<(PlayerId, f64)>,
+    req: HttpRequest,
+) -> impl web::Responder {
+    let (id, amount) = args.as_ref();
+    build_response(
+        auth_admin(&srv, &req).and_then(|_| crate::admin::grant_money(&srv, *id, *amount)),
+    )
+}
+
+#[web::get("/admin/market/price/{resource}/{price}")]
+async fn admin_force_price(
+    srv: GameState,
+    args: Path<(String, f64)>,
+    qry: Query<ForcePriceQuery>,
+    req: HttpRequest,
+) -> impl web::Responder {
+    let (resource, price) = args.as_ref();
+    build_response(auth_admin(&srv, &req).and_then(|_| {
+        let resource = parse_arg::<Resource>(resource, "resource")?;
+        crate::admin::force_price(&srv, qry.station, resource, *price)
     }))
 }
 
+#[web::get("/admin/snapshot")]
+async fn admin_snapshot(srv: GameState, req: HttpRequest) -> impl web::Responder {
+    build_response(auth_admin(&srv, &req).and_then(|_| crate::admin::snapshot(&srv)))
+}
+
+#[web::get("/admin/pause")]
+async fn admin_pause(srv: GameState, req: HttpRequest) -> impl web::Responder {
+    build_response(auth_admin(&srv, &req).and_then(|_| crate::admin::set_paused(&srv, true)))
+}
+
+#[web::get("/admin/resume")]
+async fn admin_resume(srv: GameState, req: HttpRequest) -> impl web::Responder {
+    build_response(auth_admin(&srv, &req).and_then(|_| crate::admin::set_paused(&srv, false)))
+}
+
+#[web::get("/admin/timescale/{scale}")]
+async fn admin_time_scale(
+    srv: GameState,
+    scale: Path<f64>,
+    req: HttpRequest,
+) -> impl web::Responder {
+    build_response(auth_admin(&srv, &req).and_then(|_| crate::admin::set_time_scale(&srv, *scale)))
+}
+
+#[web::get("/admin/broadcast")]
+async fn admin_broadcast(
+    srv: GameState,
+    qry: Query<BroadcastQuery>,
+    req: HttpRequest,
+) -> impl web::Responder {
+    let message = qry.message.as_deref().unwrap_or_default();
+    build_response(auth_admin(&srv, &req).and_then(|_| crate::admin::broadcast(&srv, message)))
+}
+
 #[web::get("/player/{id}")]
 async fn get_player(srv: GameState, id: Path<PlayerId>, req: HttpRequest) -> impl web::Responder {
     let Some(key) = get_player_key(&req) else {
         return build_response(Err(Errcode::NoPlayerKey));
     };
-    build_response(crate::player::get_player(srv, *id, key))
+    build_response(crate::player::get_player(&srv, *id, key))
+}
+
+#[web::get("/player/ledger")]
+async fn get_player_ledger(
+    srv: GameState,
+    qry: Query<LedgerQuery>,
+    req: HttpRequest,
+) -> impl web::Responder {
+    build_response(auth_player(&srv, &req).and_then(|p| crate::player::get_ledger(&srv, p, &qry)))
+}
+
+#[web::get("/player/alerts")]
+async fn list_alerts(srv: GameState, req: HttpRequest) -> impl web::Responder {
+    build_response(auth_player(&srv, &req).and_then(crate::player::list_alerts))
+}
+
+#[derive(Deserialize)]
+struct AlertQuery {
+    ship: Option<ShipId>,
+    station: Option<StationId>,
+    resource: Option<String>,
+}
+
+#[web::get("/player/alerts/new/{kind}/{threshold}")]
+async fn new_alert(
+    srv: GameState,
+    args: Path<(String, f64)>,
+    qry: Query<AlertQuery>,
+    req: HttpRequest,
+) -> impl web::Responder {
+    let (kind, threshold) = args.as_ref();
+    let rule = || -> Result<AlertRule, Errcode> {
+        Ok(AlertRule {
+            kind: parse_arg::<AlertKind>(kind, "kind")?,
+            threshold: *threshold,
+            ship: qry.ship,
+            station: qry.station,
+            resource: match qry.resource {
+                Some(ref r) => Some(parse_arg(r, "resource")?),
+                None => None,
+            },
+        })
+    };
+    build_response(rule().and_then(|rule| {
+        let player = auth_player(&srv, &req)?;
+        crate::player::new_alert(player, rule)
+    }))
+}
+
+#[web::get("/player/alerts/{alert_id}")]
+async fn get_alert(srv: GameState, id: Path<AlertId>, req: HttpRequest) -> impl web::Responder {
+    build_response(auth_player(&srv, &req).and_then(|p| crate::player::get_alert(p, *id)))
+}
+
+#[web::get("/player/alerts/{alert_id}/update/{threshold}")]
+async fn update_alert(
+    srv: GameState,
+    args: Path<(AlertId, f64)>,
+    req: HttpRequest,
+) -> impl web::Responder {
+    let (id, threshold) = args.as_ref();
+    build_response(
+        auth_player(&srv, &req).and_then(|p| crate::player::update_alert(p, *id, *threshold)),
+    )
+}
+
+#[web::get("/player/alerts/{alert_id}/delete")]
+async fn delete_alert(srv: GameState, id: Path<AlertId>, req: HttpRequest) -> impl web::Responder {
+    build_response(auth_player(&srv, &req).and_then(|p| crate::player::delete_alert(p, *id)))
 }
 
 #[web::get("/station/{station_id}")]
@@ -149,17 +422,7 @@ async fn get_station_status(
     id: Path<StationId>,
     req: HttpRequest,
 ) -> impl web::Responder {
-    let player = get_player!(srv, req);
-    let station = get_station!(srv, player, id.as_ref());
-    let station = station.read().unwrap();
-    build_response(Ok(serde_json::json!({
-        "id": station.id,
-        "position": station.position,
-        "crew": station.crew,
-        "cargo": station.cargo,
-        "idle_crew": station.idle_crew,
-        "trader": station.trader,
-    })))
+    build_response(auth_player(&srv, &req).and_then(|p| crate::station::get_status(&srv, p, *id)))
 }
 
 #[web::get("/station/{station_id}/shipyard/list")]
@@ -168,22 +431,9 @@ async fn list_shipyard_ships(
     id: Path<StationId>,
     req: HttpRequest,
 ) -> impl web::Responder {
-    let player = get_player!(srv, req);
-    let station = get_station!(srv, player, id.as_ref());
-    let station = station.read().unwrap();
-    let mut ships = vec![];
-    for ship in station.shipyard.iter() {
-        ships.push(serde_json::json!({
-            "id": ship.id,
-            "modules": ship.modules,
-            "reactor_power": ship.reactor_power,
-            "cargo_capacity": ship.cargo.capacity,
-            "fuel_tank_capacity": ship.fuel_tank_capacity,
-            "hull_decay_capacity": ship.hull_decay_capacity,
-            "price": ship.compute_price(),
-        }));
-    }
-    build_response(Ok(serde_json::json!({ "ships": ships })))
+    build_response(
+        auth_player(&srv, &req).and_then(|p| crate::station::list_shipyard(&srv, p, *id)),
+    )
 }
 
 #[web::get("/station/{station_id}/shipyard/buy/{id}")]
@@ -193,14 +443,9 @@ async fn shipyard_buy_ship(
     req: HttpRequest,
 ) -> impl web::Responder {
     let (station_id, ship_id) = args.as_ref();
-    let player = get_player!(srv, req);
-    let station = get_station!(srv, player, station_id);
-    let mut player = player.write().unwrap();
-    let mut station = station.write().unwrap();
     build_response(
-        player
-            .buy_ship(&mut station, *ship_id)
-            .map(|v| serde_json::json!({ "shipId": v, })),
+        auth_player(&srv, &req)
+            .and_then(|p| crate::station::buy_ship(&srv, p, *station_id, *ship_id)),
     )
 }
 
@@ -210,20 +455,10 @@ async fn shipyard_list_upgrades(
     station_id: Path<StationId>,
     req: HttpRequest,
 ) -> impl web::Responder {
-    let player = get_player!(srv, req);
-    let station = get_station!(srv, player, station_id.as_ref());
-    let station = station.read().unwrap();
-    let mut res = BTreeMap::new();
-    for upgr in ShipUpgrade::iter() {
-        res.insert(
-            upgr,
-            serde_json::json!({
-                "price": station.get_ship_upgrade_price(&upgr),
-                "description": upgr.description(),
-            }),
-        );
-    }
-    build_response(Ok(serde_json::to_value(res).unwrap()))
+    build_response(
+        auth_player(&srv, &req)
+            .and_then(|p| crate::station::list_ship_upgrades(&srv, p, *station_id)),
+    )
 }
 
 #[web::get("/station/{station_id}/shipyard/upgrade/{ship_id}/{upgrade_type}")]
@@ -233,17 +468,11 @@ async fn shipyard_buy_upgrade(
     req: HttpRequest,
 ) -> impl web::Responder {
     let (station_id, ship_id, upgrade_type) = args.as_ref();
-    let Ok(upgrade_type) = ShipUpgrade::from_str(upgrade_type) else {
-        return build_response(Err(Errcode::InvalidArgument("upgrade type")));
-    };
-    let player = get_player!(srv, req);
-    let station = get_station!(srv, player, station_id);
-    let mut player = player.write().unwrap();
-    let mut station = station.write().unwrap();
     build_response(
-        player
-            .buy_ship_upgrade(&mut station, ship_id, &upgrade_type)
-            .map(|v| serde_json::json!({ "cost": v })),
+        parse_arg::<ShipUpgrade>(upgrade_type, "upgrade type").and_then(|upgrade| {
+            let player = auth_player(&srv, &req)?;
+            crate::station::buy_ship_upgrade(&srv, player, *station_id, *ship_id, upgrade)
+        }),
     )
 }
 
@@ -254,17 +483,12 @@ async fn hire_crew(
     req: HttpRequest,
 ) -> impl web::Responder {
     let (station_id, crewtype) = args.as_ref();
-    let Ok(crewtype) = CrewMemberType::from_str(crewtype.as_str()) else {
-        return build_response(Err(Errcode::InvalidArgument("crewtype")));
-    };
-    let player = get_player!(srv, req);
-    let station = get_station!(srv, player, station_id);
-    build_response(crate::crew::hire_crew(
-        &srv.galaxy,
-        player,
-        station,
-        crewtype,
-    ))
+    build_response(
+        parse_arg::<CrewMemberType>(crewtype, "crewtype").and_then(|crewtype| {
+            let player = auth_player(&srv, &req)?;
+            crate::crew::hire_crew(&srv, player, *station_id, crewtype)
+        }),
+    )
 }
 
 #[web::get("/station/{station_id}/crew/upgrade/ship/{ship_id}")]
@@ -274,28 +498,10 @@ async fn get_crew_upgrades(
     req: HttpRequest,
 ) -> impl web::Responder {
     let (station_id, ship_id) = args.as_ref();
-    let player = get_player!(srv, req);
-    let station = get_station!(srv, player, station_id);
-    let player = player.read().unwrap();
-    let Some(ship) = player.ships.get(ship_id) else {
-        return build_response(Err(Errcode::ShipNotFound(*ship_id)));
-    };
-    if ship.position != station.read().unwrap().position {
-        return build_response(Err(Errcode::ShipNotInStation));
-    }
-
-    let mut res = BTreeMap::new();
-    for (cid, cm) in ship.crew.0.iter() {
-        res.insert(
-            cid,
-            serde_json::json!({
-                "member-type": cm.member_type,
-                "rank": cm.rank + 1,
-                "price": cm.price_next_rank(),
-            }),
-        );
-    }
-    build_response(Ok(serde_json::to_value(res).unwrap()))
+    build_response(
+        auth_player(&srv, &req)
+            .and_then(|p| crate::crew::list_crew_upgrades(&srv, p, *station_id, *ship_id)),
+    )
 }
 
 #[web::get("/station/{station_id}/crew/upgrade/ship/{ship_id}/{crew_id}")]
@@ -305,15 +511,10 @@ async fn buy_crew_upgrade(
     req: HttpRequest,
 ) -> impl web::Responder {
     let (station_id, ship_id, crew_id) = args.as_ref();
-    let player = get_player!(srv, req);
-    let station = get_station!(srv, player, station_id);
-    let mut player = player.write().unwrap();
-    let station = station.read().unwrap();
-    let res = player.upgrade_crew_rank(&station, ship_id, crew_id);
-    if res.is_ok() {
-        player.update_wages(&srv.galaxy);
-    }
-    build_response(res.map(|(p, r)| serde_json::json!({ "new-rank": r, "cost": p})))
+    build_response(
+        auth_player(&srv, &req)
+            .and_then(|p| crate::crew::upgrade_crew(&srv, p, *station_id, *ship_id, *crew_id)),
+    )
 }
 
 // TODO (#35)    Have an endpoint /station/{station_id}/crew/upgrade/{crew_id} instead
@@ -323,14 +524,9 @@ async fn upgrade_station_trader(
     srv: GameState,
     req: HttpRequest,
 ) -> impl web::Responder {
-    let player = get_player!(srv, req);
-    let station = get_station!(srv, player, station_id.as_ref());
-    let mut player = player.write().unwrap();
-    let res = player.upgrade_station_trader(station.write().unwrap().deref_mut());
-    if res.is_ok() {
-        player.update_wages(&srv.galaxy);
-    }
-    build_response(res.map(|(p, r)| serde_json::json!({ "new-rank": r, "cost": p })))
+    build_response(
+        auth_player(&srv, &req).and_then(|p| crate::crew::upgrade_trader(&srv, p, *station_id)),
+    )
 }
 
 #[web::get("/station/{station_id}/crew/assign/{crewid}/trading")]
@@ -340,13 +536,9 @@ async fn assign_trader(
     req: HttpRequest,
 ) -> impl web::Responder {
     let (station_id, crew_id) = args.as_ref();
-    let player = get_player!(srv, req);
-    let station = get_station!(srv, player, station_id);
-    let mut station = station.write().unwrap();
     build_response(
-        station
-            .assign_trader(*crew_id)
-            .map(|_| serde_json::json!({})),
+        auth_player(&srv, &req)
+            .and_then(|p| crate::crew::assign_trader(&srv, p, *station_id, *crew_id)),
     )
 }
 
@@ -357,17 +549,9 @@ async fn assign_pilot(
     req: HttpRequest,
 ) -> impl web::Responder {
     let (station_id, crew_id, ship_id) = args.as_ref();
-    let player = get_player!(srv, req);
-    let station = get_station!(srv, player, station_id);
-    let mut player = player.write().unwrap();
-    let Some(ship) = player.ships.get_mut(ship_id) else {
-        return build_response(Err(Errcode::ShipNotFound(*ship_id)));
-    };
-    let mut station = station.write().unwrap();
     build_response(
-        station
-            .onboard_pilot(*crew_id, ship)
-            .map(|_| serde_json::json!({})),
+        auth_player(&srv, &req)
+            .and_then(|p| crate::crew::assign_pilot(&srv, p, *station_id, *crew_id, *ship_id)),
     )
 }
 
@@ -378,26 +562,14 @@ async fn assign_operator(
     req: HttpRequest,
 ) -> impl web::Responder {
     let (station_id, crew_id, ship_id, modid) = args.as_ref();
-    let player = get_player!(srv, req);
-    let station = get_station!(srv, player, station_id);
-    let mut player = player.write().unwrap();
-    let Some(ship) = player.ships.get_mut(ship_id) else {
-        return build_response(Err(Errcode::ShipNotFound(*ship_id)));
-    };
-    let mut station = station.write().unwrap();
-    build_response(
-        station
-            .onboard_operator(*crew_id, ship, modid)
-            .map(|_| serde_json::json!({})),
-    )
+    build_response(auth_player(&srv, &req).and_then(|p| {
+        crate::crew::assign_operator(&srv, p, *station_id, *crew_id, *ship_id, *modid)
+    }))
 }
 
 #[web::get("/station/{station_id}/scan")]
 async fn scan(id: Path<StationId>, srv: GameState, req: HttpRequest) -> impl web::Responder {
-    let player = get_player!(srv, req);
-    let station = get_station!(srv, player, id.as_ref());
-    let results = station.read().unwrap().scan(&srv.galaxy);
-    build_response(Ok(serde_json::to_value(&results).unwrap()))
+    build_response(auth_player(&srv, &req).and_then(|p| crate::station::scan(&srv, p, *id)))
 }
 
 #[web::get("/station/{station_id}/shop/modules")]
@@ -406,15 +578,9 @@ async fn get_prices_ship_module(
     id: Path<StationId>,
     req: HttpRequest,
 ) -> impl web::Responder {
-    let player = get_player!(srv, req);
-    let _station = get_station!(srv, player, id.as_ref());
-    // TODO (#22) Price based on station
-    let mut res: BTreeMap<ShipModuleType, f64> = BTreeMap::new();
-    for smod in ShipModuleType::iter() {
-        let price = smod.get_price_buy();
-        res.insert(smod, price);
-    }
-    build_response(Ok(serde_json::to_value(res).unwrap()))
+    build_response(
+        auth_player(&srv, &req).and_then(|p| crate::station::list_module_prices(&srv, p, *id)),
+    )
 }
 
 #[web::get("/station/{station_id}/shop/modules/{ship_id}/buy/{modtype}")]
@@ -424,21 +590,10 @@ async fn buy_ship_module(
     req: HttpRequest,
 ) -> impl web::Responder {
     let (station_id, ship_id, modtype) = args.as_ref();
-    let player = get_player!(srv, req);
-
-    let Ok(modtype) = ShipModuleType::from_str(modtype.as_str()) else {
-        return build_response(Err(Errcode::InvalidArgument("modtype")));
-    };
-    let mut player = player.write().unwrap();
-    build_response(
-        player
-            .buy_ship_module(station_id, ship_id, modtype)
-            .map(|v| {
-                serde_json::json!({
-                    "id": v,
-                })
-            }),
-    )
+    build_response(auth_player(&srv, &req).and_then(|player| {
+        let modtype = parse_arg::<ShipModuleType>(modtype, "modtype")?;
+        crate::station::buy_ship_module(&srv, player, *station_id, *ship_id, modtype)
+    }))
 }
 
 #[web::get("/station/{station_id}/shop/modules/{ship_id}/upgrade")]
@@ -448,27 +603,10 @@ async fn get_ship_module_upgrade_prices(
     req: HttpRequest,
 ) -> impl web::Responder {
     let (station_id, ship_id) = args.as_ref();
-    let player = get_player!(srv, req);
-    let station = get_station!(srv, player, station_id);
-    let player = player.read().unwrap();
-    let Some(ship) = player.ships.get(ship_id) else {
-        return build_response(Err(Errcode::ShipNotFound(*ship_id)));
-    };
-    if ship.position != station.read().unwrap().position {
-        return build_response(Err(Errcode::ShipNotInStation));
-    }
-
-    let mut res = BTreeMap::new();
-    for (id, smod) in ship.modules.iter() {
-        res.insert(
-            id,
-            serde_json::json!({
-                "module-type": smod.modtype,
-                "price": smod.price_next_rank(),
-            }),
-        );
-    }
-    build_response(Ok(serde_json::to_value(res).unwrap()))
+    build_response(
+        auth_player(&srv, &req)
+            .and_then(|p| crate::station::list_module_upgrades(&srv, p, *station_id, *ship_id)),
+    )
 }
 
 #[web::get("/station/{station_id}/shop/modules/{ship_id}/upgrade/{modid}")]
@@ -478,19 +616,10 @@ async fn buy_ship_module_upgrade(
     req: HttpRequest,
 ) -> impl web::Responder {
     let (station_id, ship_id, mod_id) = args.as_ref();
-    let player = get_player!(srv, req);
-    let station = get_station!(srv, player, station_id);
-    let mut player = player.write().unwrap();
-    let station = station.read().unwrap();
     build_response(
-        player
-            .buy_ship_module_upgrade(&station, ship_id, mod_id)
-            .map(|(c, r)| {
-                serde_json::json!({
-                    "new-rank": r,
-                    "cost": c,
-                })
-            }),
+        auth_player(&srv, &req).and_then(|p| {
+            crate::station::buy_module_upgrade(&srv, p, *station_id, *ship_id, *mod_id)
+        }),
     )
 }
 
@@ -501,15 +630,8 @@ async fn buy_station_cargo(
     req: HttpRequest,
 ) -> impl web::Responder {
     let (id, amnt) = args.as_ref();
-    let player = get_player!(srv, req);
-    let station = get_station!(srv, player, id);
-
-    let mut player = player.write().unwrap();
-    let mut station = station.write().unwrap();
     build_response(
-        station
-            .buy_cargo(player.deref_mut(), amnt)
-            .map(|v| serde_json::to_value(v).unwrap()),
+        auth_player(&srv, &req).and_then(|p| crate::station::buy_cargo(&srv, p, *id, *amnt)),
     )
 }
 
@@ -519,18 +641,9 @@ async fn get_station_upgrades(
     id: Path<StationId>,
     req: HttpRequest,
 ) -> impl web::Responder {
-    let player = get_player!(srv, req);
-    let station = get_station!(srv, player, id.as_ref());
-    let station = station.read().unwrap();
-    let cargoprice = station.cargo_price();
-    let traderprice = station.trader.map(|trader| {
-        let cm = station.crew.0.get(&trader).unwrap();
-        cm.price_next_rank()
-    });
-    build_response(Ok(serde_json::json!({
-        "cargo-expansion": cargoprice,
-        "trader-upgrade": traderprice,
-    })))
+    build_response(
+        auth_player(&srv, &req).and_then(|p| crate::station::list_station_upgrades(&srv, p, *id)),
+    )
 }
 
 #[web::get("/station/{station_id}/refuel/{ship_id}")]
@@ -540,18 +653,9 @@ async fn refuel_ship(
     req: HttpRequest,
 ) -> impl web::Responder {
     let (station_id, ship_id) = args.as_ref();
-    let player = get_player!(srv, req);
-    let station = get_station!(srv, player, station_id);
-    let mut station = station.write().unwrap();
-    let mut player = player.write().unwrap();
-    let Some(ship) = player.ships.get_mut(ship_id) else {
-        return build_response(Err(Errcode::ShipNotFound(*ship_id)));
-    };
-
     build_response(
-        station
-            .refuel_ship(ship)
-            .map(|v| serde_json::json!({"added-fuel": v})),
+        auth_player(&srv, &req)
+            .and_then(|p| crate::station::refuel_ship(&srv, p, *station_id, *ship_id)),
     )
 }
 
@@ -562,18 +666,9 @@ async fn repair_ship(
     req: HttpRequest,
 ) -> impl web::Responder {
     let (station_id, ship_id) = args.as_ref();
-    let player = get_player!(srv, req);
-    let station = get_station!(srv, player, station_id);
-    let mut station = station.write().unwrap();
-    let mut player = player.write().unwrap();
-    let Some(ship) = player.ships.get_mut(ship_id) else {
-        return build_response(Err(Errcode::ShipNotFound(*ship_id)));
-    };
-
     build_response(
-        station
-            .repair_ship(ship)
-            .map(|v| serde_json::json!({"added-hull": v})),
+        auth_player(&srv, &req)
+            .and_then(|p| crate::station::repair_ship(&srv, p, *station_id, *ship_id)),
     )
 }
 
@@ -583,12 +678,7 @@ async fn get_ship_status(
     id: Path<ShipId>,
     req: HttpRequest,
 ) -> impl web::Responder {
-    let player = get_player!(srv, req);
-    let player = player.read().unwrap();
-    let Some(ship) = player.ships.get(id.as_ref()) else {
-        return build_response(Err(Errcode::ShipNotFound(*id)));
-    };
-    build_response(Ok(serde_json::to_value(ship).unwrap()))
+    build_response(auth_player(&srv, &req).and_then(|p| crate::ship::get_status(p, *id)))
 }
 
 #[web::get("/ship/{ship_id}/travelcost/{x}/{y}/{z}")]
@@ -598,14 +688,8 @@ async fn compute_travel_costs(
     req: HttpRequest,
 ) -> impl web::Responder {
     let (id, x, y, z) = args.as_ref();
-    let player = get_player!(srv, req);
-    let player = player.read().unwrap();
-    let Some(ship) = player.ships.get(id) else {
-        return build_response(Err(Errcode::ShipNotFound(*id)));
-    };
     build_response(
-        ship.compute_travel_costs((*x, *y, *z))
-            .map(|v| serde_json::to_value(v).unwrap()),
+        auth_player(&srv, &req).and_then(|p| crate::ship::travel_costs(p, *id, (*x, *y, *z))),
     )
 }
 
@@ -617,13 +701,9 @@ async fn ask_navigate(
     req: HttpRequest,
 ) -> impl web::Responder {
     let (id, x, y, z) = args.as_ref();
-    let coord = (*x, *y, *z);
-    let player = get_player!(srv, req);
-    let mut player = player.write().unwrap();
-    let Some(ship) = player.ships.get_mut(id) else {
-        return build_response(Err(Errcode::ShipNotFound(*id)));
-    };
-    build_response(ship.set_travel(coord).map(|cost| serde_json::json!(cost)))
+    build_response(
+        auth_player(&srv, &req).and_then(|p| crate::ship::navigate(p, *id, (*x, *y, *z))),
+    )
 }
 
 #[web::get("/ship/{ship_id}/extraction/start")]
@@ -632,14 +712,8 @@ async fn start_extraction(
     id: Path<ShipId>,
     req: HttpRequest,
 ) -> impl web::Responder {
-    let player = get_player!(srv, req);
-    let mut player = player.write().unwrap();
-    let Some(ship) = player.ships.get_mut(id.as_ref()) else {
-        return build_response(Err(Errcode::ShipNotFound(*id)));
-    };
     build_response(
-        ship.start_extraction(&srv.galaxy)
-            .map(|v| serde_json::to_value(v).unwrap()),
+        auth_player(&srv, &req).and_then(|p| crate::ship::start_extraction(&srv, p, *id)),
     )
 }
 
@@ -649,15 +723,7 @@ async fn stop_extraction(
     id: Path<ShipId>,
     req: HttpRequest,
 ) -> impl web::Responder {
-    let player = get_player!(srv, req);
-    let mut player = player.write().unwrap();
-    let Some(ship) = player.ships.get_mut(id.as_ref()) else {
-        return build_response(Err(Errcode::ShipNotFound(*id)));
-    };
-    build_response(
-        ship.stop_extraction()
-            .map(|v| serde_json::to_value(v).unwrap()),
-    )
+    build_response(auth_player(&srv, &req).and_then(|p| crate::ship::stop_extraction(p, *id)))
 }
 
 // MAN
@@ -668,42 +734,42 @@ async fn unload_ship_cargo(
     req: HttpRequest,
 ) -> impl web::Responder {
     let (id, resource, amnt) = args.as_ref();
-    let Ok(resource) = Resource::from_str(resource) else {
-        return build_response(Err(Errcode::InvalidArgument("resource")));
-    };
+    build_response(
+        parse_arg::<Resource>(resource, "resource").and_then(|resource| {
+            let player = auth_player(&srv, &req)?;
+            crate::ship::unload_cargo(&srv, player, *id, resource, *amnt)
+        }),
+    )
+}
 
-    let player = get_player!(srv, req);
-    let mut player = player.write().unwrap();
+#[web::get("/leaderboard")]
+async fn get_leaderboard(srv: GameState, qry: Query<LeaderboardQuery>) -> impl web::Responder {
+    build_response(crate::leaderboard::get_leaderboard(&srv, &qry))
+}
 
-    let Some(ship) = player.ships.get(id) else {
-        return build_response(Err(Errcode::ShipNotFound(*id)));
-    };
+#[web::get("/leaderboard/history")]
+async fn get_score_history(srv: GameState, qry: Query<ScoreHistoryQuery>) -> impl web::Responder {
+    build_response(crate::leaderboard::get_history(&srv, &qry))
+}
 
-    let Some(station) = player.stations.iter().find(|(_, s)| *s == &ship.position) else {
-        return build_response(Err(Errcode::ShipNotInStation));
-    };
+#[web::get("/session")]
+async fn get_current_session(srv: GameState) -> impl web::Responder {
+    build_response(crate::session::get_current(&srv))
+}
 
-    let station = srv.galaxy.get_station(station.1).unwrap();
-    let mut station = station.write().unwrap();
-    let pid = player.id;
-    let ship = player.ships.get_mut(id).unwrap();
-    let res = ship.unload_cargo(&resource, *amnt, station.deref_mut());
-    if let Ok(0.0) = res {
-        srv.syslog.event(
-            &pid,
-            SyslogEvent::UnloadedNothing {
-                station_cargo: station.cargo.clone(),
-                ship_cargo: ship.cargo.clone(),
-            },
-        );
-    }
-    build_response(res.map(|v| serde_json::json!({ "unloaded": v })))
+#[web::get("/sessions")]
+async fn list_sessions(srv: GameState) -> impl web::Responder {
+    build_response(crate::session::list_sessions(&srv))
+}
+
+#[web::get("/sessions/{id}")]
+async fn get_session(srv: GameState, id: Path<SessionId>) -> impl web::Responder {
+    build_response(crate::session::get_session(&srv, *id))
 }
 
 #[web::get("/market/prices")]
-async fn get_market_prices(srv: GameState) -> impl web::Responder {
-    let market = srv.market.read().unwrap();
-    build_response(Ok(serde_json::to_value(market.deref()).unwrap()))
+async fn get_market_prices(srv: GameState, qry: Query<MarketPricesQuery>) -> impl web::Responder {
+    build_response(crate::market::get_prices(&srv, &qry))
 }
 
 #[web::get("/market/{station_id}/buy/{resource}/{amnt}")]
@@ -713,18 +779,11 @@ async fn buy_resource(
     req: HttpRequest,
 ) -> impl web::Responder {
     let (station_id, resource, amnt) = args.as_ref();
-    let Ok(resource) = Resource::from_str(resource) else {
-        return build_response(Err(Errcode::InvalidArgument("resource")));
-    };
-    let player = get_player!(srv, req);
-    let station = get_station!(srv, player, station_id);
-    let mut player = player.write().unwrap();
-    let mut station = station.write().unwrap();
-    let mut market = srv.market.write().unwrap();
     build_response(
-        station
-            .buy_resource(&resource, *amnt, player.deref_mut(), market.deref_mut())
-            .map(|tx| serde_json::to_value(tx).unwrap()),
+        parse_arg::<Resource>(resource, "resource").and_then(|resource| {
+            let player = auth_player(&srv, &req)?;
+            crate::market::buy_resource(&srv, player, *station_id, resource, *amnt)
+        }),
     )
 }
 
@@ -735,18 +794,11 @@ async fn sell_resource(
     req: HttpRequest,
 ) -> impl web::Responder {
     let (station_id, resource, amnt) = args.as_ref();
-    let Ok(resource) = Resource::from_str(resource) else {
-        return build_response(Err(Errcode::InvalidArgument("resource")));
-    };
-    let player = get_player!(srv, req);
-    let station = get_station!(srv, player, station_id);
-    let mut player = player.write().unwrap();
-    let mut station = station.write().unwrap();
-    let mut market = srv.market.write().unwrap();
     build_response(
-        station
-            .sell_resource(&resource, *amnt, player.deref_mut(), market.deref_mut())
-            .map(|tx| serde_json::to_value(tx).unwrap()),
+        parse_arg::<Resource>(resource, "resource").and_then(|resource| {
+            let player = auth_player(&srv, &req)?;
+            crate::market::sell_resource(&srv, player, *station_id, resource, *amnt)
+        }),
     )
 }
 
@@ -756,22 +808,84 @@ async fn get_fee_rate(
     station_id: Path<StationId>,
     req: HttpRequest,
 ) -> impl web::Responder {
-    let player = get_player!(srv, req);
-    let station = get_station!(srv, player, station_id.as_ref());
-    let station = station.read().unwrap();
-    let Some(trader) = station.trader else {
-        return build_response(Err(Errcode::NoTraderAssigned));
+    build_response(
+        auth_player(&srv, &req).and_then(|p| crate::market::get_fee_rate(&srv, p, *station_id)),
+    )
+}
+
+#[web::get("/market/history/{resource}")]
+async fn get_market_history(
+    srv: GameState,
+    resource: Path<String>,
+    qry: Query<MarketHistoryQuery>,
+) -> impl web::Responder {
+    build_response(
+        parse_arg::<Resource>(&resource, "resource")
+            .and_then(|resource| crate::market::get_history(&srv, resource, &qry)),
+    )
+}
+
+#[web::get("/market/orders")]
+async fn list_player_orders(srv: GameState, req: HttpRequest) -> impl web::Responder {
+    build_response(auth_player(&srv, &req).and_then(|p| crate::market::player_orders(&srv, p)))
+}
+
+#[web::get("/market/orders/{resource}")]
+async fn get_order_book(
+    srv: GameState,
+    resource: Path<String>,
+    qry: Query<OrderBookQuery>,
+) -> impl web::Responder {
+    build_response(
+        parse_arg::<Resource>(&resource, "resource")
+            .and_then(|resource| crate::market::order_book(&srv, resource, &qry)),
+    )
+}
+
+#[derive(Deserialize)]
+struct PlaceOrderQuery {
+    ttl: Option<f64>,
+    ship: Option<ShipId>,
+}
+
+#[web::get("/market/{station_id}/order/{side}/{resource}/{amnt}/{price}")]
+async fn place_order(
+    srv: GameState,
+    args: Path<(StationId, String, String, f64, f64)>,
+    qry: Query<PlaceOrderQuery>,
+    req: HttpRequest,
+) -> impl web::Responder {
+    let (station_id, side, resource, amnt, price) = args.as_ref();
+    let order = || -> Result<OrderRequest, Errcode> {
+        Ok(OrderRequest {
+            side: parse_arg::<OrderSide>(side, "side")?,
+            resource: parse_arg::<Resource>(resource, "resource")?,
+            price: *price,
+            amount: *amnt,
+            ttl: qry.ttl.unwrap_or(ORDER_DEFAULT_TTL),
+            ship: qry.ship,
+        })
     };
-    let cm = station.crew.0.get(&trader).unwrap();
-    let fee = fee_rate(cm.rank);
-    build_response(Ok(serde_json::json!({
-        "fee_rate": fee,
-    })))
+    build_response(order().and_then(|order| {
+        let player = auth_player(&srv, &req)?;
+        crate::market::place_order(&srv, player, *station_id, order)
+    }))
+}
+
+#[web::get("/market/order/{order_id}/cancel")]
+async fn cancel_order(srv: GameState, id: Path<OrderId>, req: HttpRequest) -> impl web::Responder {
+    build_response(auth_player(&srv, &req).and_then(|p| crate::market::cancel_order(&srv, p, *id)))
 }
 
 pub fn configure(srv: &mut ServiceConfig) {
     srv.service(ping)
+        .service(list_worlds)
+        .service(openapi)
+        .service(metrics)
         .service(get_syslogs)
+        .service(ack_syslogs)
+        .service(syslog_stats)
+        .service(stream_syslogs)
         .service(hire_crew)
         .service(get_crew_upgrades)
         .service(buy_crew_upgrade)
@@ -799,10 +913,352 @@ pub fn configure(srv: &mut ServiceConfig) {
         .service(buy_station_cargo)
         .service(refuel_ship)
         .service(repair_ship)
+        .service(get_market_history)
+        .service(list_player_orders)
+        .service(get_order_book)
+        .service(place_order)
+        .service(cancel_order)
         .service(get_fee_rate)
         .service(get_market_prices)
+        .service(get_leaderboard)
+        .service(get_score_history)
+        .service(get_current_session)
+        .service(list_sessions)
+        .service(get_session)
         .service(buy_resource)
         .service(sell_resource)
+        .service(get_player_ledger)
+        .service(rotate_key)
+        .service(new_invite)
+        .service(ratelimit_stats)
+        .service(admin_list_players)
+        .service(admin_kick_player)
+        .service(admin_reset_player)
+        .service(admin_grant_money)
+        .service(admin_force_price)
+        .service(admin_snapshot)
+        .service(admin_pause)
+        .service(admin_resume)
+        .service(admin_time_scale)
+        .service(admin_broadcast)
+        .service(list_alerts)
+        .service(new_alert)
+        .service(get_alert)
+        .service(update_alert)
+        .service(delete_alert)
         .service(get_player)
         .service(new_player);
 }
+
+#[cfg(test)]
+fn test_request(addr: std::net::SocketAddr, path: &str, key: Option<&str>) -> Value {
+    use std::io::{Read, Write};
+
+    let mut stream = std::net::TcpStream::connect(addr).unwrap();
+    // A deadlocked server never answers, the test fails instead of hanging
+    stream
+        .set_read_timeout(Some(std::time::Duration::from_secs(20)))
+        .unwrap();
+    let auth = key
+        .map(|k| format!("Authorization: Bearer {k}\r\n"))
+        .unwrap_or_default();
+    write!(
+        stream,
+        "GET {path} HTTP/1.1\r\nHost: localhost\r\n{auth}Connection: close\r\n\r\n"
+    )
+    .unwrap();
+    let mut res = String::new();
+    stream.read_to_string(&mut res).unwrap();
+    let (_, body) = res.split_once("\r\n\r\n").unwrap();
+    serde_json::from_str(body).unwrap()
+}
+
+// Serves a single world on a local port, with its game thread running
+#[cfg(test)]
+fn test_server(
+    config: simeis_data::game::GameConfig,
+) -> (std::net::SocketAddr, simeis_data::game::Game) {
+    let (threads, worlds) = Worlds::init(vec![("test".to_string(), config)]);
+    let worlds = Arc::new(worlds);
+    let game = worlds.get(None).unwrap().clone();
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    std::thread::spawn(move || {
+        let _threads = threads;
+        ntex::rt::System::new("test").block_on(async move {
+            web::HttpServer::new(move || {
+                web::App::new()
+                    .state(worlds.clone())
+                    .configure(configure)
+                    .configure(crate::api_v2::configure)
+            })
+            .workers(8)
+            .disable_signals()
+            .listen(listener)
+            .unwrap()
+            .run()
+            .await
+        })
+    });
+    (addr, game)
+}
+
+// The request must succeed, or fail with one of the errors the game can legitimately give it
+#[cfg(test)]
+fn check_response(res: &Value, path: &str, allowed: &[&str]) {
+    let ok = res["error"] == "ok" || allowed.contains(&res["type"].as_str().unwrap_or(""));
+    assert!(ok, "{path}: {res}");
+}
+
+// Many clients using the same stations, the market and the admin routes at once,
+//     while the game thread runs: every request must get the answer it expects
+#[test]
+fn test_concurrent_requests() {
+    use simeis_data::game::GameConfig;
+
+    let config = GameConfig {
+        admin_key: Some("admin".to_string()),
+        time_scale: 50.0,
+        ..Default::default()
+    };
+    let (addr, _) = test_server(config);
+
+    // The admin starts once all the players are set up
+    let ready = Arc::new(std::sync::Barrier::new(13));
+    let mut clients = vec![];
+    for n in 0..12 {
+        let ready = ready.clone();
+        clients.push(std::thread::spawn(move || {
+            let request = |path: &str, key: Option<&str>| {
+                let res = test_request(addr, path, key);
+                check_response(&res, path, &[]);
+                res
+            };
+            let player = request(&format!("/player/new/stress-{n}"), None);
+            let key = player["key"].as_str().unwrap().to_string();
+            let key = Some(key.as_str());
+            let id = &player["playerId"];
+            let info = request(&format!("/player/{id}"), key);
+            let station = info["stations"].as_object().unwrap().keys().next().unwrap();
+            let shipyard = request(&format!("/station/{station}/shipyard/list"), key);
+            let ship = &shipyard["ships"][0]["id"];
+            request(&format!("/station/{station}/shipyard/buy/{ship}"), key);
+            let trader = request(&format!("/station/{station}/crew/hire/trader"), key);
+            let trader = &trader["id"];
+            request(
+                &format!("/station/{station}/crew/assign/{trader}/trading"),
+                key,
+            );
+
+            ready.wait();
+
+            // The admin resets the players at any time: the ship, the crew and the cargo go away,
+            //     and until then they can lose all their money
+            for i in 0..250 {
+                let (path, allowed): (String, &[&str]) = match i % 10 {
+                    0 => (format!("/station/{station}/crew/hire/pilot"), &[]),
+                    1 => (
+                        format!("/market/{station}/sell/fuel/1"),
+                        &["NoTraderAssigned", "SellNothing"],
+                    ),
+                    2 => (
+                        format!("/station/{station}/repair/{ship}"),
+                        &["ShipNotFound", "NoHullPlateInCargo"],
+                    ),
+                    3 => (
+                        format!("/market/{station}/buy/fuel/2"),
+                        &["NoTraderAssigned", "NotEnoughMoney", "CargoFull"],
+                    ),
+                    4 => (
+                        format!("/station/{station}/refuel/{ship}"),
+                        &["ShipNotFound", "NoFuelInCargo"],
+                    ),
+                    5 => (
+                        format!("/station/{station}/crew/upgrade/trader"),
+                        &["NoTraderAssigned", "NotEnoughMoney"],
+                    ),
+                    6 => (
+                        format!("/station/{station}/shop/cargo/buy/1"),
+                        &["NotEnoughMoney"],
+                    ),
+                    7 => (format!("/player/{id}"), &[]),
+                    8 => ("/leaderboard".to_string(), &[]),
+                    _ => ("/syslogs".to_string(), &[]),
+                };
+                let res = test_request(addr, &path, key);
+                check_response(&res, &path, &[allowed, &["PlayerLost"]].concat());
+            }
+        }));
+    }
+    clients.push(std::thread::spawn(move || {
+        ready.wait();
+        let admin = Some("admin");
+        for i in 0..300 {
+            let path = match i % 6 {
+                0 => "/admin/players".to_string(),
+                1 => format!("/admin/player/{}/grant/1000000", i % 12 + 1),
+                2 => "/admin/market/price/fuel/10".to_string(),
+                3 => "/admin/pause".to_string(),
+                4 => "/admin/resume".to_string(),
+                _ => format!("/admin/player/{}/reset", i % 12 + 1),
+            };
+            check_response(&test_request(addr, &path, admin), &path, &[]);
+        }
+    }));
+    for client in clients {
+        client.join().unwrap();
+    }
+}
+
+// Two players trade through the order book of a station, one of them from a ship docked there
+#[test]
+fn test_players_trade() {
+    use simeis_data::ship::resources::Resource;
+
+    let (addr, game) = test_server(Default::default());
+    let request = |path: &str, key: Option<&str>| {
+        let res = test_request(addr, path, key);
+        check_response(&res, path, &[]);
+        res
+    };
+    let players = ["seller", "buyer"].map(|name| {
+        let player = request(&format!("/player/new/{name}"), None);
+        let key = player["key"].as_str().unwrap().to_string();
+        let info = request(&format!("/player/{}", player["playerId"]), Some(&key));
+        let station = info["stations"].as_object().unwrap().keys().next().unwrap();
+        (player["playerId"].clone(), key, station.clone())
+    });
+    let [(seller, skey, sstation), (buyer, bkey, bstation)] = &players;
+    let (skey, bkey) = (Some(skey.as_str()), Some(bkey.as_str()));
+    assert_ne!(sstation, bstation);
+
+    let trader = request(&format!("/station/{sstation}/crew/hire/trader"), skey);
+    let trader = &trader["id"];
+    request(
+        &format!("/station/{sstation}/crew/assign/{trader}/trading"),
+        skey,
+    );
+    request(&format!("/market/{sstation}/buy/fuel/4"), skey);
+    let ask = request(&format!("/market/{sstation}/order/sell/fuel/4/10"), skey);
+    for price in ["inf", "-inf", "NaN", "0"] {
+        let path = format!("/market/{sstation}/order/buy/fuel/1/{price}");
+        let res = test_request(addr, &path, skey);
+        assert_eq!(res["type"], "InvalidArgument", "{price}");
+        assert_eq!(res["details"]["argument"], "price");
+    }
+
+    // Orders can only be placed at the stations the player owns, or from a ship docked there
+    let path = format!("/market/{sstation}/order/buy/fuel/3/12");
+    assert_eq!(test_request(addr, &path, bkey)["type"], "NoSuchStation");
+    let shipyard = request(&format!("/station/{bstation}/shipyard/list"), bkey);
+    let ship = &shipyard["ships"][0]["id"];
+    request(&format!("/station/{bstation}/shipyard/buy/{ship}"), bkey);
+    let path = format!("{path}?ship={ship}");
+    assert_eq!(test_request(addr, &path, bkey)["type"], "ShipNotInStation");
+
+    // The ship flies to the station of the seller
+    let buyer_id = buyer.as_u64().unwrap() as u32;
+    let sstation_id = sstation.parse().unwrap();
+    let mut token = LockToken::root();
+    let position = game
+        .market
+        .read(&mut token)
+        .unwrap()
+        .get_station(&sstation_id)
+        .unwrap()
+        .position;
+    let ship_id = ship.as_u64().unwrap();
+    let buyer_lock = game.players.read(&mut token).unwrap()[&buyer_id].clone();
+    buyer_lock
+        .write(&mut token)
+        .unwrap()
+        .ships
+        .get_mut(&ship_id)
+        .unwrap()
+        .position = position;
+
+    let bid = request(&path, bkey);
+    assert_eq!(bid["fills"].as_array().unwrap().len(), 1);
+    assert_eq!(bid["fills"][0]["added_cargo"][1], 3.0);
+    assert_eq!(bid["fills"][0]["removed_money"], 30.0);
+    let cargo = buyer_lock.read(&mut token).unwrap().ships[&ship_id]
+        .cargo
+        .resources[&Resource::Fuel];
+    assert_eq!(cargo, 3.0);
+    let orders = request("/market/orders", skey);
+    assert_eq!(orders["orders"][0]["id"], ask["order"]["id"]);
+    let amount = ask["order"]["amount"].as_f64().unwrap();
+    let remaining = orders["orders"][0]["remaining"].as_f64().unwrap();
+    assert!((remaining - (amount - 3.0)).abs() < 1e-9);
+    // What the ship didn't take doesn't rest on the book
+    assert!(request("/market/orders", bkey)["orders"]
+        .as_array()
+        .unwrap()
+        .is_empty());
+
+    // The seller gets paid at its own station by the game thread
+    let money = || {
+        request(&format!("/player/{seller}"), skey)["money"]
+            .as_f64()
+            .unwrap()
+    };
+    let before = money();
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+    while money() < before + 30.0 - 1.0 {
+        assert!(std::time::Instant::now() < deadline);
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+}
+
+// Without a cursor, polling the syslogs always gives the newest events
+#[test]
+fn test_syslogs_newest() {
+    let (addr, _) = test_server(Default::default());
+    let player = test_request(addr, "/player/new/poller", None);
+    let key = player["key"].as_str();
+    let info = test_request(addr, &format!("/player/{}", player["playerId"]), key);
+    let station = info["stations"].as_object().unwrap().keys().next().unwrap();
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+    let mut seen = vec![];
+    for _ in 0..3 {
+        test_request(addr, &format!("/station/{station}/crew/hire/pilot"), key);
+        let last = loop {
+            let res = test_request(addr, "/syslogs?limit=1", key);
+            if res["nb"] == 1 && res["events"][0]["type"] == "CrewHired" {
+                let seq = res["events"][0]["seq"].as_u64().unwrap();
+                if !seen.contains(&seq) {
+                    break seq;
+                }
+            }
+            assert!(std::time::Instant::now() < deadline);
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        };
+        seen.push(last);
+    }
+    let all = test_request(addr, "/syslogs", key);
+    assert_eq!(all["nb"], 4);
+    assert_eq!(all["events"][0]["type"], "GameStarted");
+}
+
+#[test]
+fn test_admin_snapshot_dir() {
+    use simeis_data::game::GameConfig;
+
+    let dir = std::env::temp_dir().join(format!("simeis-snapshots-{}", std::process::id()));
+    let config = GameConfig {
+        admin_key: Some("admin".to_string()),
+        snapshot_dir: Some(dir.to_string_lossy().into_owned()),
+        ..Default::default()
+    };
+    let (addr, _) = test_server(config);
+    let res = test_request(addr, "/admin/snapshot", Some("admin"));
+    check_response(&res, "/admin/snapshot", &[]);
+    // The name of the world tells apart the snapshots of the worlds sharing a directory
+    let path = std::path::PathBuf::from(res["path"].as_str().unwrap());
+    assert_eq!(path.parent(), Some(dir.as_path()));
+    let fname = path.file_name().unwrap().to_string_lossy();
+    assert!(fname.starts_with("snapshot-test-"), "{fname}");
+    let written: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(written, res["snapshot"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,875 @@
+// Second version of the API, served under /v2
+// State changes use POST / PUT / DELETE with a JSON body, options are passed as query parameters,
+//     and errors are reported with the HTTP status code instead of an "error" field
+use ntex::http::StatusCode;
+use std::sync::Arc;
+
+use ntex::web::types::{Json, Path, Query, State};
+use ntex::web::{self, HttpRequest, HttpResponse, ServiceConfig};
+use serde::Deserialize;
+
+use simeis_data::crew::{CrewId, CrewMemberType};
+use simeis_data::errors::{Errcode, ErrorCategory};
+use simeis_data::galaxy::station::StationId;
+use simeis_data::galaxy::SpaceUnit;
+use simeis_data::market::orderbook::{OrderId, OrderRequest, OrderSide, ORDER_DEFAULT_TTL};
+use simeis_data::player::alert::{AlertId, AlertKind, AlertRule};
+use simeis_data::player::PlayerId;
+use simeis_data::session::SessionId;
+use simeis_data::ship::module::{ShipModuleId, ShipModuleType};
+use simeis_data::ship::resources::Resource;
+use simeis_data::ship::upgrade::ShipUpgrade;
+use simeis_data::ship::ShipId;
+use simeis_data::syslog::SyslogSeq;
+
+use crate::api::{
+    auth_admin, auth_player, auth_player_any_phase, get_player_key, parse_arg, ApiResult,
+};
+use crate::leaderboard::{LeaderboardQuery, ScoreHistoryQuery};
+use crate::market::{MarketHistoryQuery, MarketPricesQuery, OrderBookQuery};
+use crate::metrics::ResponseError;
+use crate::player::LedgerQuery;
+use crate::ratelimit::RateLimiter;
+use crate::syslog::{SyslogQuery, SyslogStreamQuery};
+use crate::world::Worlds;
+use crate::GameState;
+
+pub fn error_status(err: &Errcode) -> StatusCode {
+    match err.category() {
+        ErrorCategory::Auth
+            if matches!(
+                err,
+                Errcode::PlayerLost | Errcode::InvalidInvite | Errcode::NotAdmin
+            ) =>
+        {
+            StatusCode::FORBIDDEN
+        }
+        ErrorCategory::Auth => StatusCode::UNAUTHORIZED,
+        ErrorCategory::NotFound => StatusCode::NOT_FOUND,
+        ErrorCategory::InvalidArgument => StatusCode::BAD_REQUEST,
+        ErrorCategory::Funds => StatusCode::PAYMENT_REQUIRED,
+        ErrorCategory::Conflict => StatusCode::CONFLICT,
+        ErrorCategory::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+    }
+}
+
+fn build_response_status(res: ApiResult, status: StatusCode) -> HttpResponse {
+    match res {
+        Ok(data) => HttpResponse::build(status).json(&data),
+        Err(e) => {
+            let res = HttpResponse::build(error_status(&e)).json(&e);
+            res.extensions_mut().insert(ResponseError((&e).into()));
+            res
+        }
+    }
+}
+
+pub fn build_response(res: ApiResult) -> HttpResponse {
+    build_response_status(res, StatusCode::OK)
+}
+
+fn build_response_created(res: ApiResult) -> HttpResponse {
+    build_response_status(res, StatusCode::CREATED)
+}
+
+fn parse_opt<T: std::str::FromStr>(
+    arg: &Option<String>,
+    name: &'static str,
+) -> Result<Option<T>, Errcode> {
+    match arg {
+        Some(ref a) => parse_arg(a, name).map(Some),
+        None => Ok(None),
+    }
+}
+
+#[derive(Deserialize)]
+struct NewPlayerBody {
+    name: String,
+    invite: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct AckBody {
+    seq: SyslogSeq,
+    client: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct AlertBody {
+    kind: String,
+    threshold: f64,
+    ship: Option<ShipId>,
+    station: Option<StationId>,
+    resource: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ThresholdBody {
+    threshold: f64,
+}
+
+#[derive(Deserialize)]
+struct BuyShipBody {
+    id: ShipId,
+}
+
+#[derive(Deserialize)]
+struct UpgradeBody {
+    upgrade: String,
+}
+
+#[derive(Deserialize)]
+struct HireBody {
+    crew_type: String,
+}
+
+#[derive(Deserialize)]
+struct CrewBody {
+    crew_id: CrewId,
+}
+
+#[derive(Deserialize)]
+struct ModuleBody {
+    module_type: String,
+}
+
+#[derive(Deserialize)]
+struct CargoBody {
+    amount: usize,
+}
+
+#[derive(Deserialize)]
+struct CoordBody {
+    x: SpaceUnit,
+    y: SpaceUnit,
+    z: SpaceUnit,
+}
+
+#[derive(Deserialize)]
+struct ResourceBody {
+    resource: String,
+    amount: f64,
+}
+
+#[derive(Deserialize)]
+struct OrderBody {
+    side: String,
+    resource: String,
+    amount: f64,
+    price: f64,
+    ttl: Option<f64>,
+    ship: Option<ShipId>,
+}
+
+#[derive(Deserialize)]
+struct GrantBody {
+    amount: f64,
+}
+
+#[derive(Deserialize)]
+struct PriceBody {
+    price: f64,
+    station: Option<StationId>,
+}
+
+#[derive(Deserialize)]
+struct TimeScaleBody {
+    scale: f64,
+}
+
+#[derive(Deserialize)]
+struct BroadcastBody {
+    message: String,
+}
+
+#[web::get("/ping")]
+async fn ping() -> HttpResponse {
+    build_response(Ok(serde_json::json!({"ping": "pong"})))
+}
+
+#[web::get("/worlds")]
+async fn list_worlds(worlds: State<Arc<Worlds>>) -> HttpResponse {
+    build_response(worlds.list())
+}
+
+#[web::get("/syslogs")]
+async fn get_syslogs(srv: GameState, qry: Query<SyslogQuery>, req: HttpRequest) -> HttpResponse {
+    build_response(
+        auth_player_any_phase(&srv, &req).and_then(|p| crate::syslog::get_events(&srv, p, &qry)),
+    )
+}
+
+#[web::post("/syslogs/ack")]
+async fn ack_syslogs(srv: GameState, body: Json<AckBody>, req: HttpRequest) -> HttpResponse {
+    build_response(
+        auth_player_any_phase(&srv, &req)
+            .and_then(|p| crate::syslog::ack_events(&srv, p, body.client.as_ref(), body.seq)),
+    )
+}
+
+#[web::get("/syslogs/stats")]
+async fn syslog_stats(srv: GameState) -> HttpResponse {
+    build_response(crate::syslog::get_stats(&srv))
+}
+
+#[web::get("/syslogs/stream")]
+async fn stream_syslogs(
+    srv: GameState,
+    qry: Query<SyslogStreamQuery>,
+    req: HttpRequest,
+) -> Result<HttpResponse, web::Error> {
+    let player = match auth_player_any_phase(&srv, &req) {
+        Ok(player) => player,
+        Err(e) => return Ok(build_response(Err(e))),
+    };
+    crate::syslog::stream_events(&srv, req, player, qry.since).await
+}
+
+#[web::post("/players")]
+async fn new_player(srv: GameState, body: Json<NewPlayerBody>) -> HttpResponse {
+    build_response_created(crate::player::new_player(
+        &srv,
+        &body.name,
+        body.invite.as_deref(),
+    ))
+}
+
+#[web::post("/player/key")]
+async fn rotate_key(srv: GameState, req: HttpRequest) -> HttpResponse {
+    build_response(auth_player(&srv, &req).and_then(|p| crate::player::rotate_key(&srv, p)))
+}
+
+#[web::get("/admin/ratelimit")]
+async fn ratelimit_stats(
+    srv: GameState,
+    limiter: State<Arc<RateLimiter>>,
+    req: HttpRequest,
+) -> HttpResponse {
+    build_response(auth_admin(&srv, &req).and_then(|_| limiter.stats()))
+}
+
+#[web::post("/admin/invites")]
+async fn new_invite(srv: GameState, req: HttpRequest) -> HttpResponse {
+    build_response_created(auth_admin(&srv, &req).and_then(|_| crate::player::new_invite(&srv)))
+}
+
+#[web::get("/admin/players")]
+async fn admin_list_players(srv: GameState, req: HttpRequest) -> HttpResponse {
+    build_response(auth_admin(&srv, &req).and_then(|_| crate::admin::list_players(&srv)))
+}
+
+#[web::delete("/admin/players/{id}")]
+async fn admin_kick_player(srv: GameState, id: Path<PlayerId>, req: HttpRequest) -> HttpResponse {
+    build_response(auth_admin(&srv, &req).and_then(|_| crate::admin::kick_player(&srv, *id)))
+}
+
+#[web::post("/admin/players/{id}/reset")]
+async fn admin_reset_player(srv: GameState, id: Path<PlayerId>, req: HttpRequest) -> HttpResponse {
+    build_response(auth_admin(&srv, &req).and_then(|_| crate::admin::reset_player(&srv, *id)))
+}
+
+#[web::post("/admin/players/{id}/grant")]
+async fn admin_grant_money(
+    srv: GameState,
+    id: Path<PlayerId>,
+    body: Json<GrantBody>,
+    req: HttpRequest,
+) -> HttpResponse {
+    build_response(
+        auth_admin(&srv, &req).and_then(|_| crate::admin::grant_money(&srv, *id, body.amount)),
+    )
+}
+
+#[web::put("/admin/market/prices/{resource}")]
+async fn admin_force_price(
+    srv: GameState,
+    resource: Path<String>,
+    body: Json<PriceBody>,
+    req: HttpRequest,
+) -> HttpResponse {
+    build_response(auth_admin(&srv, &req).and_then(|_| {
+        let resource = parse_arg::<Resource>(&resource, "resource")?;
+        crate::admin::force_price(&srv, body.station, resource, body.price)
+    }))
+}
+
+#[web::post("/admin/snapshots")]
+async fn admin_snapshot(srv: GameState, req: HttpRequest) -> HttpResponse {
+    build_response_created(auth_admin(&srv, &req).and_then(|_| crate::admin::snapshot(&srv)))
+}
+
+#[web::post("/admin/pause")]
+async fn admin_pause(srv: GameState, req: HttpRequest) -> HttpResponse {
+    build_response(auth_admin(&srv, &req).and_then(|_| crate::admin::set_paused(&srv, true)))
+}
+
+#[web::post("/admin/resume")]
+async fn admin_resume(srv: GameState, req: HttpRequest) -> HttpResponse {
+    build_response(auth_admin(&srv, &req).and_then(|_| crate::admin::set_paused(&srv, false)))
+}
+
+#[web::put("/admin/timescale")]
+async fn admin_time_scale(
+    srv: GameState,
+    body: Json<TimeScaleBody>,
+    req: HttpRequest,
+) -> HttpResponse {
+    build_response(
+        auth_admin(&srv, &req).and_then(|_| crate::admin::set_time_scale(&srv, body.scale)),
+    )
+}
+
+#[web::post("/admin/broadcast")]
+async fn admin_broadcast(
+    srv: GameState,
+    body: Json<BroadcastBody>,
+    req: HttpRequest,
+) -> HttpResponse {
+    build_response(
+        auth_admin(&srv, &req).and_then(|_| crate::admin::broadcast(&srv, &body.message)),
+    )
+}
+
+#[web::get("/players/{id}")]
+async fn get_player(srv: GameState, id: Path<PlayerId>, req: HttpRequest) -> HttpResponse {
+    let Some(key) = get_player_key(&req) else {
+        return build_response(Err(Errcode::NoPlayerKey));
+    };
+    build_response(crate::player::get_player(&srv, *id, key))
+}
+
+#[web::get("/player/ledger")]
+async fn get_ledger(srv: GameState, qry: Query<LedgerQuery>, req: HttpRequest) -> HttpResponse {
+    build_response(auth_player(&srv, &req).and_then(|p| crate::player::get_ledger(&srv, p, &qry)))
+}
+
+#[web::get("/player/alerts")]
+async fn list_alerts(srv: GameState, req: HttpRequest) -> HttpResponse {
+    build_response(auth_player(&srv, &req).and_then(crate::player::list_alerts))
+}
+
+#[web::post("/player/alerts")]
+async fn new_alert(srv: GameState, body: Json<AlertBody>, req: HttpRequest) -> HttpResponse {
+    let rule = || -> Result<AlertRule, Errcode> {
+        Ok(AlertRule {
+            kind: parse_arg::<AlertKind>(&body.kind, "kind")?,
+            threshold: body.threshold,
+            ship: body.ship,
+            station: body.station,
+            resource: parse_opt(&body.resource, "resource")?,
+        })
+    };
+    build_response_created(rule().and_then(|rule| {
+        let player = auth_player(&srv, &req)?;
+        crate::player::new_alert(player, rule)
+    }))
+}
+
+#[web::get("/player/alerts/{alert_id}")]
+async fn get_alert(srv: GameState, id: Path<AlertId>, req: HttpRequest) -> HttpResponse {
+    build_response(auth_player(&srv, &req).and_then(|p| crate::player::get_alert(p, *id)))
+}
+
+#[web::put("/player/alerts/{alert_id}")]
+async fn update_alert(
+    srv: GameState,
+    id: Path<AlertId>,
+    body: Json<ThresholdBody>,
+    req: HttpRequest,
+) -> HttpResponse {
+    build_response(
+        auth_player(&srv, &req).and_then(|p| crate::player::update_alert(p, *id, body.threshold)),
+    )
+}
+
+#[web::delete("/player/alerts/{alert_id}")]
+async fn delete_alert(srv: GameState, id: Path<AlertId>, req: HttpRequest) -> HttpResponse {
+    build_response(auth_player(&srv, &req).and_then(|p| crate::player::delete_alert(p, *id)))
+}
+
+#[web::get("/stations/{station_id}")]
+async fn get_station(srv: GameState, id: Path<StationId>, req: HttpRequest) -> HttpResponse {
+    build_response(auth_player(&srv, &req).and_then(|p| crate::station::get_status(&srv, p, *id)))
+}
+
+#[web::get("/stations/{station_id}/scan")]
+async fn scan(srv: GameState, id: Path<StationId>, req: HttpRequest) -> HttpResponse {
+    build_response(auth_player(&srv, &req).and_then(|p| crate::station::scan(&srv, p, *id)))
+}
+
+#[web::get("/stations/{station_id}/upgrades")]
+async fn list_station_upgrades(
+    srv: GameState,
+    id: Path<StationId>,
+    req: HttpRequest,
+) -> HttpResponse {
+    build_response(
+        auth_player(&srv, &req).and_then(|p| crate::station::list_station_upgrades(&srv, p, *id)),
+    )
+}
+
+#[web::post("/stations/{station_id}/cargo")]
+async fn buy_cargo(
+    srv: GameState,
+    id: Path<StationId>,
+    body: Json<CargoBody>,
+    req: HttpRequest,
+) -> HttpResponse {
+    build_response(
+        auth_player(&srv, &req).and_then(|p| crate::station::buy_cargo(&srv, p, *id, body.amount)),
+    )
+}
+
+#[web::get("/stations/{station_id}/shipyard")]
+async fn list_shipyard(srv: GameState, id: Path<StationId>, req: HttpRequest) -> HttpResponse {
+    build_response(
+        auth_player(&srv, &req).and_then(|p| crate::station::list_shipyard(&srv, p, *id)),
+    )
+}
+
+#[web::get("/stations/{station_id}/shipyard/upgrades")]
+async fn list_ship_upgrades(srv: GameState, id: Path<StationId>, req: HttpRequest) -> HttpResponse {
+    build_response(
+        auth_player(&srv, &req).and_then(|p| crate::station::list_ship_upgrades(&srv, p, *id)),
+    )
+}
+
+#[web::post("/stations/{station_id}/ships")]
+async fn buy_ship(
+    srv: GameState,
+    id: Path<StationId>,
+    body: Json<BuyShipBody>,
+    req: HttpRequest,
+) -> HttpResponse {
+    build_response_created(
+        auth_player(&srv, &req).and_then(|p| crate::station::buy_ship(&srv, p, *id, body.id)),
+    )
+}
+
+#[web::post("/stations/{station_id}/ships/{ship_id}/upgrades")]
+async fn buy_ship_upgrade(
+    srv: GameState,
+    args: Path<(StationId, ShipId)>,
+    body: Json<UpgradeBody>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let (station_id, ship_id) = args.as_ref();
+    build_response(
+        parse_arg::<ShipUpgrade>(&body.upgrade, "upgrade").and_then(|upgrade| {
+            let player = auth_player(&srv, &req)?;
+            crate::station::buy_ship_upgrade(&srv, player, *station_id, *ship_id, upgrade)
+        }),
+    )
+}
+
+#[web::post("/stations/{station_id}/ships/{ship_id}/refuel")]
+async fn refuel_ship(
+    srv: GameState,
+    args: Path<(StationId, ShipId)>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let (station_id, ship_id) = args.as_ref();
+    build_response(
+        auth_player(&srv, &req)
+            .and_then(|p| crate::station::refuel_ship(&srv, p, *station_id, *ship_id)),
+    )
+}
+
+#[web::post("/stations/{station_id}/ships/{ship_id}/repair")]
+async fn repair_ship(
+    srv: GameState,
+    args: Path<(StationId, ShipId)>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let (station_id, ship_id) = args.as_ref();
+    build_response(
+        auth_player(&srv, &req)
+            .and_then(|p| crate::station::repair_ship(&srv, p, *station_id, *ship_id)),
+    )
+}
+
+#[web::get("/stations/{station_id}/modules")]
+async fn list_module_prices(srv: GameState, id: Path<StationId>, req: HttpRequest) -> HttpResponse {
+    build_response(
+        auth_player(&srv, &req).and_then(|p| crate::station::list_module_prices(&srv, p, *id)),
+    )
+}
+
+#[web::get("/stations/{station_id}/ships/{ship_id}/modules")]
+async fn list_module_upgrades(
+    srv: GameState,
+    args: Path<(StationId, ShipId)>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let (station_id, ship_id) = args.as_ref();
+    build_response(
+        auth_player(&srv, &req)
+            .and_then(|p| crate::station::list_module_upgrades(&srv, p, *station_id, *ship_id)),
+    )
+}
+
+#[web::post("/stations/{station_id}/ships/{ship_id}/modules")]
+async fn buy_module(
+    srv: GameState,
+    args: Path<(StationId, ShipId)>,
+    body: Json<ModuleBody>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let (station_id, ship_id) = args.as_ref();
+    build_response_created(
+        parse_arg::<ShipModuleType>(&body.module_type, "module_type").and_then(|modtype| {
+            let player = auth_player(&srv, &req)?;
+            crate::station::buy_ship_module(&srv, player, *station_id, *ship_id, modtype)
+        }),
+    )
+}
+
+#[web::post("/stations/{station_id}/ships/{ship_id}/modules/{mod_id}/upgrade")]
+async fn upgrade_module(
+    srv: GameState,
+    args: Path<(StationId, ShipId, ShipModuleId)>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let (station_id, ship_id, mod_id) = args.as_ref();
+    build_response(
+        auth_player(&srv, &req).and_then(|p| {
+            crate::station::buy_module_upgrade(&srv, p, *station_id, *ship_id, *mod_id)
+        }),
+    )
+}
+
+#[web::post("/stations/{station_id}/crew")]
+async fn hire_crew(
+    srv: GameState,
+    id: Path<StationId>,
+    body: Json<HireBody>,
+    req: HttpRequest,
+) -> HttpResponse {
+    build_response_created(
+        parse_arg::<CrewMemberType>(&body.crew_type, "crew_type").and_then(|crewtype| {
+            let player = auth_player(&srv, &req)?;
+            crate::crew::hire_crew(&srv, player, *id, crewtype)
+        }),
+    )
+}
+
+#[web::put("/stations/{station_id}/trader")]
+async fn assign_trader(
+    srv: GameState,
+    id: Path<StationId>,
+    body: Json<CrewBody>,
+    req: HttpRequest,
+) -> HttpResponse {
+    build_response(
+        auth_player(&srv, &req)
+            .and_then(|p| crate::crew::assign_trader(&srv, p, *id, body.crew_id)),
+    )
+}
+
+#[web::post("/stations/{station_id}/trader/upgrade")]
+async fn upgrade_trader(srv: GameState, id: Path<StationId>, req: HttpRequest) -> HttpResponse {
+    build_response(auth_player(&srv, &req).and_then(|p| crate::crew::upgrade_trader(&srv, p, *id)))
+}
+
+#[web::get("/stations/{station_id}/ships/{ship_id}/crew")]
+async fn list_crew_upgrades(
+    srv: GameState,
+    args: Path<(StationId, ShipId)>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let (station_id, ship_id) = args.as_ref();
+    build_response(
+        auth_player(&srv, &req)
+            .and_then(|p| crate::crew::list_crew_upgrades(&srv, p, *station_id, *ship_id)),
+    )
+}
+
+#[web::post("/stations/{station_id}/ships/{ship_id}/crew/{crew_id}/upgrade")]
+async fn upgrade_crew(
+    srv: GameState,
+    args: Path<(StationId, ShipId, CrewId)>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let (station_id, ship_id, crew_id) = args.as_ref();
+    build_response(
+        auth_player(&srv, &req)
+            .and_then(|p| crate::crew::upgrade_crew(&srv, p, *station_id, *ship_id, *crew_id)),
+    )
+}
+
+#[web::put("/stations/{station_id}/ships/{ship_id}/pilot")]
+async fn assign_pilot(
+    srv: GameState,
+    args: Path<(StationId, ShipId)>,
+    body: Json<CrewBody>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let (station_id, ship_id) = args.as_ref();
+    build_response(
+        auth_player(&srv, &req)
+            .and_then(|p| crate::crew::assign_pilot(&srv, p, *station_id, body.crew_id, *ship_id)),
+    )
+}
+
+#[web::put("/stations/{station_id}/ships/{ship_id}/modules/{mod_id}/operator")]
+async fn assign_operator(
+    srv: GameState,
+    args: Path<(StationId, ShipId, ShipModuleId)>,
+    body: Json<CrewBody>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let (station_id, ship_id, mod_id) = args.as_ref();
+    build_response(auth_player(&srv, &req).and_then(|p| {
+        crate::crew::assign_operator(&srv, p, *station_id, body.crew_id, *ship_id, *mod_id)
+    }))
+}
+
+#[web::post("/stations/{station_id}/market/buy")]
+async fn buy_resource(
+    srv: GameState,
+    id: Path<StationId>,
+    body: Json<ResourceBody>,
+    req: HttpRequest,
+) -> HttpResponse {
+    build_response(
+        parse_arg::<Resource>(&body.resource, "resource").and_then(|resource| {
+            let player = auth_player(&srv, &req)?;
+            crate::market::buy_resource(&srv, player, *id, resource, body.amount)
+        }),
+    )
+}
+
+#[web::post("/stations/{station_id}/market/sell")]
+async fn sell_resource(
+    srv: GameState,
+    id: Path<StationId>,
+    body: Json<ResourceBody>,
+    req: HttpRequest,
+) -> HttpResponse {
+    build_response(
+        parse_arg::<Resource>(&body.resource, "resource").and_then(|resource| {
+            let player = auth_player(&srv, &req)?;
+            crate::market::sell_resource(&srv, player, *id, resource, body.amount)
+        }),
+    )
+}
+
+#[web::get("/stations/{station_id}/market/fee_rate")]
+async fn get_fee_rate(srv: GameState, id: Path<StationId>, req: HttpRequest) -> HttpResponse {
+    build_response(auth_player(&srv, &req).and_then(|p| crate::market::get_fee_rate(&srv, p, *id)))
+}
+
+#[web::post("/stations/{station_id}/market/orders")]
+async fn place_order(
+    srv: GameState,
+    id: Path<StationId>,
+    body: Json<OrderBody>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let order = || -> Result<OrderRequest, Errcode> {
+        Ok(OrderRequest {
+            side: parse_arg::<OrderSide>(&body.side, "side")?,
+            resource: parse_arg::<Resource>(&body.resource, "resource")?,
+            price: body.price,
+            amount: body.amount,
+            ttl: body.ttl.unwrap_or(ORDER_DEFAULT_TTL),
+            ship: body.ship,
+        })
+    };
+    build_response_created(order().and_then(|order| {
+        let player = auth_player(&srv, &req)?;
+        crate::market::place_order(&srv, player, *id, order)
+    }))
+}
+
+#[web::get("/ships/{ship_id}")]
+async fn get_ship(srv: GameState, id: Path<ShipId>, req: HttpRequest) -> HttpResponse {
+    build_response(auth_player(&srv, &req).and_then(|p| crate::ship::get_status(p, *id)))
+}
+
+#[web::get("/ships/{ship_id}/travelcost")]
+async fn travel_costs(
+    srv: GameState,
+    id: Path<ShipId>,
+    qry: Query<CoordBody>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let coord = (qry.x, qry.y, qry.z);
+    build_response(auth_player(&srv, &req).and_then(|p| crate::ship::travel_costs(p, *id, coord)))
+}
+
+#[web::post("/ships/{ship_id}/navigate")]
+async fn navigate(
+    srv: GameState,
+    id: Path<ShipId>,
+    body: Json<CoordBody>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let coord = (body.x, body.y, body.z);
+    build_response(auth_player(&srv, &req).and_then(|p| crate::ship::navigate(p, *id, coord)))
+}
+
+#[web::post("/ships/{ship_id}/extraction")]
+async fn start_extraction(srv: GameState, id: Path<ShipId>, req: HttpRequest) -> HttpResponse {
+    build_response(
+        auth_player(&srv, &req).and_then(|p| crate::ship::start_extraction(&srv, p, *id)),
+    )
+}
+
+#[web::delete("/ships/{ship_id}/extraction")]
+async fn stop_extraction(srv: GameState, id: Path<ShipId>, req: HttpRequest) -> HttpResponse {
+    build_response(auth_player(&srv, &req).and_then(|p| crate::ship::stop_extraction(p, *id)))
+}
+
+#[web::post("/ships/{ship_id}/unload")]
+async fn unload_cargo(
+    srv: GameState,
+    id: Path<ShipId>,
+    body: Json<ResourceBody>,
+    req: HttpRequest,
+) -> HttpResponse {
+    build_response(
+        parse_arg::<Resource>(&body.resource, "resource").and_then(|resource| {
+            let player = auth_player(&srv, &req)?;
+            crate::ship::unload_cargo(&srv, player, *id, resource, body.amount)
+        }),
+    )
+}
+
+#[web::get("/leaderboard")]
+async fn get_leaderboard(srv: GameState, qry: Query<LeaderboardQuery>) -> HttpResponse {
+    build_response(crate::leaderboard::get_leaderboard(&srv, &qry))
+}
+
+#[web::get("/leaderboard/history")]
+async fn get_score_history(srv: GameState, qry: Query<ScoreHistoryQuery>) -> HttpResponse {
+    build_response(crate::leaderboard::get_history(&srv, &qry))
+}
+
+#[web::get("/session")]
+async fn get_current_session(srv: GameState) -> HttpResponse {
+    build_response(crate::session::get_current(&srv))
+}
+
+#[web::get("/sessions")]
+async fn list_sessions(srv: GameState) -> HttpResponse {
+    build_response(crate::session::list_sessions(&srv))
+}
+
+#[web::get("/sessions/{id}")]
+async fn get_session(srv: GameState, id: Path<SessionId>) -> HttpResponse {
+    build_response(crate::session::get_session(&srv, *id))
+}
+
+#[web::get("/market/prices")]
+async fn get_prices(srv: GameState, qry: Query<MarketPricesQuery>) -> HttpResponse {
+    build_response(crate::market::get_prices(&srv, &qry))
+}
+
+#[web::get("/market/history/{resource}")]
+async fn get_history(
+    srv: GameState,
+    resource: Path<String>,
+    qry: Query<MarketHistoryQuery>,
+) -> HttpResponse {
+    build_response(
+        parse_arg::<Resource>(&resource, "resource")
+            .and_then(|resource| crate::market::get_history(&srv, resource, &qry)),
+    )
+}
+
+#[web::get("/market/book/{resource}")]
+async fn get_order_book(
+    srv: GameState,
+    resource: Path<String>,
+    qry: Query<OrderBookQuery>,
+) -> HttpResponse {
+    build_response(
+        parse_arg::<Resource>(&resource, "resource")
+            .and_then(|resource| crate::market::order_book(&srv, resource, &qry)),
+    )
+}
+
+#[web::get("/market/orders")]
+async fn list_orders(srv: GameState, req: HttpRequest) -> HttpResponse {
+    build_response(auth_player(&srv, &req).and_then(|p| crate::market::player_orders(&srv, p)))
+}
+
+#[web::delete("/market/orders/{order_id}")]
+async fn cancel_order(srv: GameState, id: Path<OrderId>, req: HttpRequest) -> HttpResponse {
+    build_response(auth_player(&srv, &req).and_then(|p| crate::market::cancel_order(&srv, p, *id)))
+}
+
+pub fn configure(srv: &mut ServiceConfig) {
+    srv.service(
+        web::scope("/v2")
+            .service(ping)
+            .service(list_worlds)
+            .service(get_syslogs)
+            .service(ack_syslogs)
+            .service(syslog_stats)
+            .service(stream_syslogs)
+            .service(new_player)
+            .service(get_player)
+            .service(get_ledger)
+            .service(rotate_key)
+            .service(new_invite)
+            .service(ratelimit_stats)
+            .service(admin_list_players)
+            .service(admin_kick_player)
+            .service(admin_reset_player)
+            .service(admin_grant_money)
+            .service(admin_force_price)
+            .service(admin_snapshot)
+            .service(admin_pause)
+            .service(admin_resume)
+            .service(admin_time_scale)
+            .service(admin_broadcast)
+            .service(list_alerts)
+            .service(new_alert)
+            .service(get_alert)
+            .service(update_alert)
+            .service(delete_alert)
+            .service(get_station)
+            .service(scan)
+            .service(list_station_upgrades)
+            .service(buy_cargo)
+            .service(list_shipyard)
+            .service(list_ship_upgrades)
+            .service(buy_ship)
+            .service(buy_ship_upgrade)
+            .service(refuel_ship)
+            .service(repair_ship)
+            .service(list_module_prices)
+            .service(list_module_upgrades)
+            .service(buy_module)
+            .service(upgrade_module)
+            .service(hire_crew)
+            .service(assign_trader)
+            .service(upgrade_trader)
+            .service(list_crew_upgrades)
+            .service(upgrade_crew)
+            .service(assign_pilot)
+            .service(assign_operator)
+            .service(buy_resource)
+            .service(sell_resource)
+            .service(get_fee_rate)
+            .service(place_order)
+            .service(get_ship)
+            .service(travel_costs)
+            .service(navigate)
+            .service(start_extraction)
+            .service(stop_extraction)
+            .service(unload_cargo)
+            .service(get_prices)
+            .service(get_leaderboard)
+            .service(get_score_history)
+            .service(get_current_session)
+            .service(list_sessions)
+            .service(get_session)
+            .service(get_history)
+            .service(get_order_book)
+            .service(list_orders)
+            .service(cancel_order),
+    );
+}
@@ -1,24 +1,177 @@
-use std::sync::{Arc, RwLock};
+use std::collections::BTreeMap;
+use std::ops::DerefMut;
 
 use rand::Rng;
+use serde_json::json;
 use simeis_data::{
-    crew::{CrewMember, CrewMemberType},
-    galaxy::{station::Station, Galaxy},
-    player::Player,
+    crew::{CrewId, CrewMember, CrewMemberType},
+    errors::Errcode,
+    galaxy::station::StationId,
+    lock::LockToken,
+    ship::{module::ShipModuleId, ShipId},
+    syslog::SyslogEvent,
 };
 
-use crate::api::ApiResult;
+use crate::api::{get_station, ApiResult, PlayerRef};
+use crate::GameState;
 
 pub fn hire_crew(
-    galaxy: &Galaxy,
-    player: Arc<RwLock<Player>>,
-    station: Arc<RwLock<Station>>,
+    srv: &GameState,
+    player: PlayerRef,
+    station_id: StationId,
     crewtype: CrewMemberType,
 ) -> ApiResult {
+    let mut token = LockToken::root();
+    let station = get_station(srv, &mut token, &player, &station_id)?;
     let mut rng = rand::rng();
     let id = rng.random();
-    let member = CrewMember::from(crewtype);
-    station.write().unwrap().idle_crew.0.insert(id, member);
-    player.write().unwrap().update_wages(galaxy);
-    Ok(serde_json::json!({ "id": id }))
+    let member = CrewMember::from(crewtype.clone());
+    station
+        .write(&mut token)
+        .unwrap()
+        .idle_crew
+        .0
+        .insert(id, member);
+    let mut player = player.write(&mut token).unwrap();
+    let (player, token) = player.split();
+    player.update_wages(token, &srv.galaxy);
+    let evt = SyslogEvent::CrewHired {
+        station: station_id,
+        crew: id,
+        member_type: crewtype,
+    };
+    srv.syslog.event(&player.id, evt);
+    Ok(json!({ "id": id }))
+}
+
+pub fn list_crew_upgrades(
+    srv: &GameState,
+    player: PlayerRef,
+    station_id: StationId,
+    ship_id: ShipId,
+) -> ApiResult {
+    let mut token = LockToken::root();
+    let station = get_station(srv, &mut token, &player, &station_id)?;
+    let mut player = player.read(&mut token).unwrap();
+    let (player, token) = player.split();
+    let Some(ship) = player.ships.get(&ship_id) else {
+        return Err(Errcode::ShipNotFound(ship_id));
+    };
+    if ship.position != station.read(token).unwrap().position {
+        return Err(Errcode::ShipNotInStation);
+    }
+
+    let mut res = BTreeMap::new();
+    for (cid, cm) in ship.crew.0.iter() {
+        res.insert(
+            cid,
+            json!({
+                "member-type": cm.member_type,
+                "rank": cm.rank + 1,
+                "price": cm.price_next_rank(),
+            }),
+        );
+    }
+    Ok(serde_json::to_value(res).unwrap())
+}
+
+pub fn upgrade_crew(
+    srv: &GameState,
+    player: PlayerRef,
+    station_id: StationId,
+    ship_id: ShipId,
+    crew_id: CrewId,
+) -> ApiResult {
+    let mut token = LockToken::root();
+    let station = get_station(srv, &mut token, &player, &station_id)?;
+    let mut player = player.write(&mut token).unwrap();
+    let (player, token) = player.split();
+    let (cost, rank) = {
+        let station = station.read(token).unwrap();
+        player.upgrade_crew_rank(&station, &ship_id, &crew_id)?
+    };
+    player.update_wages(token, &srv.galaxy);
+    let evt = SyslogEvent::CrewPromoted {
+        crew: crew_id,
+        ship: Some(ship_id),
+        rank,
+        cost,
+    };
+    srv.syslog.event(&player.id, evt);
+    Ok(json!({ "new-rank": rank, "cost": cost }))
+}
+
+pub fn upgrade_trader(srv: &GameState, player: PlayerRef, station_id: StationId) -> ApiResult {
+    let mut token = LockToken::root();
+    let station = get_station(srv, &mut token, &player, &station_id)?;
+    let mut player = player.write(&mut token).unwrap();
+    let (player, token) = player.split();
+    let (res, trader) = {
+        let mut station = station.write(token).unwrap();
+        let res = player.upgrade_station_trader(station.deref_mut());
+        (res, station.trader)
+    };
+    let (cost, rank) = res?;
+    player.update_wages(token, &srv.galaxy);
+    if let Some(crew) = trader {
+        let evt = SyslogEvent::CrewPromoted {
+            crew,
+            ship: None,
+            rank,
+            cost,
+        };
+        srv.syslog.event(&player.id, evt);
+    }
+    Ok(json!({ "new-rank": rank, "cost": cost }))
+}
+
+pub fn assign_trader(
+    srv: &GameState,
+    player: PlayerRef,
+    station_id: StationId,
+    crew_id: CrewId,
+) -> ApiResult {
+    let mut token = LockToken::root();
+    let station = get_station(srv, &mut token, &player, &station_id)?;
+    let mut station = station.write(&mut token).unwrap();
+    station.assign_trader(crew_id).map(|_| json!({}))
+}
+
+pub fn assign_pilot(
+    srv: &GameState,
+    player: PlayerRef,
+    station_id: StationId,
+    crew_id: CrewId,
+    ship_id: ShipId,
+) -> ApiResult {
+    let mut token = LockToken::root();
+    let station = get_station(srv, &mut token, &player, &station_id)?;
+    let mut player = player.write(&mut token).unwrap();
+    let (player, token) = player.split();
+    let Some(ship) = player.ships.get_mut(&ship_id) else {
+        return Err(Errcode::ShipNotFound(ship_id));
+    };
+    let mut station = station.write(token).unwrap();
+    station.onboard_pilot(crew_id, ship).map(|_| json!({}))
+}
+
+pub fn assign_operator(
+    srv: &GameState,
+    player: PlayerRef,
+    station_id: StationId,
+    crew_id: CrewId,
+    ship_id: ShipId,
+    mod_id: ShipModuleId,
+) -> ApiResult {
+    let mut token = LockToken::root();
+    let station = get_station(srv, &mut token, &player, &station_id)?;
+    let mut player = player.write(&mut token).unwrap();
+    let (player, token) = player.split();
+    let Some(ship) = player.ships.get_mut(&ship_id) else {
+        return Err(Errcode::ShipNotFound(ship_id));
+    };
+    let mut station = station.write(token).unwrap();
+    station
+        .onboard_operator(crew_id, ship, &mod_id)
+        .map(|_| json!({}))
 }
@@ -0,0 +1,56 @@
+use serde::Deserialize;
+use serde_json::json;
+
+use simeis_data::lock::LockToken;
+use simeis_data::player::PlayerId;
+use simeis_data::score::{ScoreSnapshot, SCORE_SNAPSHOT_PERIOD};
+
+use crate::api::ApiResult;
+use crate::GameState;
+
+#[derive(Deserialize)]
+pub struct LeaderboardQuery {
+    pub limit: Option<usize>,
+}
+
+#[derive(Deserialize)]
+pub struct ScoreHistoryQuery {
+    pub since: Option<f64>,
+    pub player: Option<PlayerId>,
+}
+
+pub fn get_leaderboard(srv: &GameState, qry: &LeaderboardQuery) -> ApiResult {
+    let mut ranking = srv.leaderboard(&mut LockToken::root());
+    if let Some(limit) = qry.limit {
+        ranking.truncate(limit);
+    }
+    Ok(json!({
+        "time": srv.clock.now(),
+        "formula": srv.score_formula,
+        "ranking": ranking,
+    }))
+}
+
+pub fn get_history(srv: &GameState, qry: &ScoreHistoryQuery) -> ApiResult {
+    let mut token = LockToken::root();
+    let history = srv.score_history.read(&mut token).unwrap();
+    let snapshots = history
+        .since(qry.since.unwrap_or(0.0))
+        .map(|snap| match qry.player {
+            Some(id) => ScoreSnapshot {
+                time: snap.time,
+                scores: snap
+                    .scores
+                    .iter()
+                    .filter(|(p, _)| **p == id)
+                    .map(|(p, s)| (*p, *s))
+                    .collect(),
+            },
+            None => snap.clone(),
+        })
+        .collect::<Vec<ScoreSnapshot>>();
+    Ok(json!({
+        "period": SCORE_SNAPSHOT_PERIOD,
+        "snapshots": snapshots,
+    }))
+}
@@ -0,0 +1,85 @@
+// Access log replacing `middleware::Logger`, which writes the raw query string,
+//     so the player keys and invite tokens passed as query parameters are redacted
+use std::time::Instant;
+
+use ntex::service::{Middleware, Service, ServiceCtx};
+use ntex::web::{WebRequest, WebResponse};
+
+const REDACTED_PARAMS: [&str; 2] = ["key", "invite"];
+
+pub struct AccessLog;
+
+impl<S> Middleware<S> for AccessLog {
+    type Service = AccessLogMiddleware<S>;
+
+    fn create(&self, service: S) -> Self::Service {
+        AccessLogMiddleware { service }
+    }
+}
+
+pub struct AccessLogMiddleware<S> {
+    service: S,
+}
+
+fn redact_query(query: &str) -> String {
+    query
+        .split('&')
+        .map(|param| match param.split_once('=') {
+            Some((name, _)) if REDACTED_PARAMS.contains(&name) => format!("{name}=<redacted>"),
+            _ => param.to_string(),
+        })
+        .collect::<Vec<String>>()
+        .join("&")
+}
+
+impl<S, E> Service<WebRequest<E>> for AccessLogMiddleware<S>
+where
+    S: Service<WebRequest<E>, Response = WebResponse>,
+{
+    type Response = WebResponse;
+    type Error = S::Error;
+
+    ntex::forward_poll!(service);
+    ntex::forward_ready!(service);
+    ntex::forward_shutdown!(service);
+
+    async fn call(
+        &self,
+        req: WebRequest<E>,
+        ctx: ServiceCtx<'_, Self>,
+    ) -> Result<Self::Response, Self::Error> {
+        let start = Instant::now();
+        let addr = req
+            .peer_addr()
+            .map(|a| a.ip().to_string())
+            .unwrap_or("-".to_string());
+        let query = req.query_string();
+        let line = if query.is_empty() {
+            format!("{} {} {:?}", req.method(), req.path(), req.version())
+        } else {
+            let query = redact_query(query);
+            format!(
+                "{} {}?{query} {:?}",
+                req.method(),
+                req.path(),
+                req.version()
+            )
+        };
+        let res = ctx.call(&self.service, req).await?;
+        log::info!(
+            "{addr} \"{line}\" {} {:.6}",
+            res.status().as_u16(),
+            start.elapsed().as_secs_f64()
+        );
+        Ok(res)
+    }
+}
+
+#[test]
+fn test_redact_query() {
+    assert_eq!(
+        redact_query("key=abc%2Bdef&since=3&invite=xyz"),
+        "key=<redacted>&since=3&invite=<redacted>"
+    );
+    assert_eq!(redact_query("station=1"), "station=1");
+}
@@ -1,20 +1,41 @@
+// The stack of middlewares makes the type of the server service deeply nested
+#![recursion_limit = "256"]
+
+use std::sync::Arc;
+
 use ntex::web;
 
-use simeis_data::game::Game;
+use simeis_data::game::GameConfig;
 
+mod admin;
 mod api;
+mod api_v2;
 mod crew;
+mod leaderboard;
+mod logger;
+mod market;
+mod metrics;
+mod openapi;
 mod player;
+mod ratelimit;
+mod session;
+mod ship;
+mod station;
+mod syslog;
+mod world;
+
+use metrics::{HttpMetrics, Metrics};
+use ratelimit::{RateLimit, RateLimitConfig, RateLimiter};
+use world::{WorldRouter, Worlds};
 
-pub type GameState = ntex::web::types::State<Game>;
+pub use world::GameState;
 
 #[ntex::main]
 async fn main() -> std::io::Result<()> {
-    #[cfg(not(feature = "testing"))]
-    let port = 8080;
-
-    #[cfg(feature = "testing")]
-    let port = 9345;
+    let port: u16 = std::env::var("SIMEIS_PORT")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(8080);
 
     env_logger::builder()
         .parse_default_env()
@@ -24,21 +45,96 @@ async fn main() -> std::io::Result<()> {
         .filter_module("ntex::http::h1", log::LevelFilter::Warn)
         .init();
     log::info!("Running on http://127.0.0.1:{port}");
-    let (gamethread, state) = Game::init();
-    let game = state.clone();
+    let names = std::env::var("SIMEIS_WORLDS").unwrap_or("default".to_string());
+    let mut configs = vec![];
+    for name in names.split(',').map(str::trim) {
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            log::warn!("Invalid world name: {name:?}");
+            continue;
+        }
+        if configs.iter().any(|(n, _)| n == name) {
+            continue;
+        }
+        log::info!("Starting world {name}");
+        configs.push((name.to_string(), world_config(name)));
+    }
+    if configs.is_empty() {
+        return Err(std::io::Error::other("No valid world in SIMEIS_WORLDS"));
+    }
+    let ratelimits = RateLimitConfig::from_env().map_err(|e| {
+        std::io::Error::other(format!("Invalid rate limit in the environment, {e}"))
+    })?;
+    let (gamethreads, worlds) = Worlds::init(configs);
+    let worlds = Arc::new(worlds);
+    let limiter = Arc::new(RateLimiter::new(ratelimits));
+    let http_metrics = Arc::new(HttpMetrics::default());
 
+    let worlds_state = worlds.clone();
     #[allow(clippy::redundant_closure)] // DEV
     let res = web::HttpServer::new(move || {
         web::App::new()
-            .wrap(web::middleware::Logger::default())
-            .state(state.clone())
+            .wrap(RateLimit(limiter.clone()))
+            .wrap(Metrics(http_metrics.clone()))
+            .wrap(WorldRouter(worlds_state.clone()))
+            .wrap(logger::AccessLog)
+            .state(worlds_state.clone())
+            .state(limiter.clone())
+            .state(http_metrics.clone())
             .configure(|srv| api::configure(srv))
+            .configure(|srv| api_v2::configure(srv))
     })
     .stop_runtime()
     .bind(("127.0.0.1", port))?
     .run()
     .await;
 
-    game.stop(gamethread);
+    worlds.stop(gamethreads);
     res
 }
+
+// Settings of a world, each one can be given for this world only, as SIMEIS_<WORLD>_<NAME>
+fn world_config(world: &str) -> GameConfig {
+    let prefix = world.to_uppercase().replace('-', "_");
+    let env = |name: &str| {
+        std::env::var(format!("SIMEIS_{prefix}_{name}"))
+            .or_else(|_| std::env::var(format!("SIMEIS_{name}")))
+    };
+    let mut config = GameConfig::default();
+    if let Some(size) = env("SYSLOG_HISTORY").ok().and_then(|v| v.parse().ok()) {
+        config.syslog_history = size;
+    }
+    config.admin_key = env("ADMIN_KEY").ok();
+    config.require_invite = env("REQUIRE_INVITE").is_ok_and(|v| v == "1");
+    config.rich_players = env("RICH_PLAYERS").is_ok_and(|v| v == "1");
+    let env_secs = |name: &str| env(name).ok().and_then(|v| v.parse::<f64>().ok());
+    config.session.lobby = env_secs("SESSION_LOBBY").unwrap_or(0.0);
+    config.session.duration = env_secs("SESSION_DURATION");
+    config.session.rollover = env("SESSION_ROLLOVER").is_ok_and(|v| v == "1");
+    // Each world writes in its own subdirectory, their sessions are numbered the same way
+    let world_dir = |dir: String| {
+        let dir = std::path::Path::new(&dir).join(world);
+        dir.to_string_lossy().into_owned()
+    };
+    config.session.export_dir = env("SESSION_EXPORT_DIR").ok().map(world_dir);
+    config.snapshot_dir = env("SNAPSHOT_DIR").ok().map(world_dir);
+    match env_secs("TIME_SCALE") {
+        Some(scale) if scale.is_finite() && scale > 0.0 => config.time_scale = scale,
+        Some(scale) => log::warn!("Invalid time scale: {scale}"),
+        None => {}
+    }
+    if let Some(workers) = env("WORKERS").ok().and_then(|v| v.parse().ok()) {
+        config.workers = workers;
+    }
+    if let Ok(formula) = env("SCORE_FORMULA") {
+        match formula.parse() {
+            Ok(formula) => config.score_formula = formula,
+            Err(_) => log::warn!("Invalid score formula: {formula}"),
+        }
+    }
+    config.seed = env("SEED").ok().and_then(|v| v.parse().ok());
+    config
+}
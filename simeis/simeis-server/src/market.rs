@@ -0,0 +1,255 @@
+use std::ops::DerefMut;
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use simeis_data::errors::Errcode;
+use simeis_data::galaxy::station::StationId;
+use simeis_data::lock::LockToken;
+use simeis_data::market::history::Candle;
+use simeis_data::market::orderbook::{OrderId, OrderRequest};
+use simeis_data::market::{fee_rate, MarketTx};
+use simeis_data::player::PlayerId;
+use simeis_data::ship::resources::Resource;
+use simeis_data::ship::ShipId;
+use simeis_data::syslog::SyslogEvent;
+
+use crate::api::{get_station, ApiResult, PlayerRef};
+use crate::GameState;
+
+const HISTORY_DEFAULT_INTERVAL: f64 = 60.0;
+const HISTORY_DEFAULT_COUNT: usize = 60;
+const HISTORY_MAX_COUNT: usize = 1000;
+
+#[derive(Deserialize)]
+pub struct MarketPricesQuery {
+    pub station: Option<StationId>,
+}
+
+#[derive(Deserialize)]
+pub struct OrderBookQuery {
+    pub station: Option<StationId>,
+}
+
+#[derive(Deserialize)]
+pub struct MarketHistoryQuery {
+    pub station: Option<StationId>,
+    pub interval: Option<f64>,
+    pub count: Option<usize>,
+}
+
+fn trade_events(
+    srv: &GameState,
+    pid: PlayerId,
+    station: StationId,
+    order: Option<OrderId>,
+    tx: &MarketTx,
+) {
+    let evt = SyslogEvent::TradeExecuted {
+        station,
+        order,
+        tx: tx.clone(),
+    };
+    srv.syslog.event(&pid, evt);
+}
+
+pub fn get_prices(srv: &GameState, qry: &MarketPricesQuery) -> ApiResult {
+    let mut token = LockToken::root();
+    let market = srv.market.read(&mut token).unwrap();
+    let Some(ref station_id) = qry.station else {
+        return Ok(json!({
+            "prices": market.average_prices(),
+        }));
+    };
+    let Some(smarket) = market.get_station(station_id) else {
+        return Err(Errcode::NoSuchStation(*station_id));
+    };
+    Ok(json!({
+        "station": station_id,
+        "position": smarket.position,
+        "prices": smarket.prices,
+        "supply": smarket.supply,
+    }))
+}
+
+pub fn buy_resource(
+    srv: &GameState,
+    player: PlayerRef,
+    station_id: StationId,
+    resource: Resource,
+    amnt: f64,
+) -> ApiResult {
+    let mut token = LockToken::root();
+    let station = get_station(srv, &mut token, &player, &station_id)?;
+    let mut player = player.write(&mut token).unwrap();
+    let (player, token) = player.split();
+    let mut station = station.write(token).unwrap();
+    let (station, token) = station.split();
+    let mut market = srv.market.write(token).unwrap();
+    let was_full = station.cargo.is_full();
+    let tx = station.buy_resource(&resource, amnt, player, market.deref_mut())?;
+    trade_events(srv, player.id, station.id, None, &tx);
+    if !was_full && station.cargo.is_full() {
+        let evt = SyslogEvent::StationCargoFull {
+            station: station.id,
+            cargo: station.cargo.clone(),
+        };
+        srv.syslog.event(&player.id, evt);
+    }
+    Ok(serde_json::to_value(tx).unwrap())
+}
+
+pub fn sell_resource(
+    srv: &GameState,
+    player: PlayerRef,
+    station_id: StationId,
+    resource: Resource,
+    amnt: f64,
+) -> ApiResult {
+    let mut token = LockToken::root();
+    let station = get_station(srv, &mut token, &player, &station_id)?;
+    let mut player = player.write(&mut token).unwrap();
+    let (player, token) = player.split();
+    let mut station = station.write(token).unwrap();
+    let (station, token) = station.split();
+    let mut market = srv.market.write(token).unwrap();
+    let tx = station.sell_resource(&resource, amnt, player, market.deref_mut())?;
+    trade_events(srv, player.id, station.id, None, &tx);
+    Ok(serde_json::to_value(tx).unwrap())
+}
+
+pub fn get_fee_rate(srv: &GameState, player: PlayerRef, station_id: StationId) -> ApiResult {
+    let mut token = LockToken::root();
+    let station = get_station(srv, &mut token, &player, &station_id)?;
+    let station = station.read(&mut token).unwrap();
+    let Some(trader) = station.trader else {
+        return Err(Errcode::NoTraderAssigned);
+    };
+    let cm = station.crew.0.get(&trader).unwrap();
+    let fee = fee_rate(cm.rank);
+    Ok(json!({
+        "fee_rate": fee,
+    }))
+}
+
+pub fn get_history(srv: &GameState, resource: Resource, qry: &MarketHistoryQuery) -> ApiResult {
+    let interval = qry.interval.unwrap_or(HISTORY_DEFAULT_INTERVAL);
+    if interval.is_nan() || interval < 1.0 {
+        return Err(Errcode::InvalidArgument("interval"));
+    }
+    let count = qry
+        .count
+        .unwrap_or(HISTORY_DEFAULT_COUNT)
+        .min(HISTORY_MAX_COUNT);
+
+    let mut token = LockToken::root();
+    let market = srv.market.read(&mut token).unwrap();
+    let history = match qry.station {
+        Some(ref station_id) => {
+            let Some(smarket) = market.get_station(station_id) else {
+                return Err(Errcode::NoSuchStation(*station_id));
+            };
+            smarket.history.get(&resource).unwrap()
+        }
+        None => market.history.get(&resource).unwrap(),
+    };
+    let now = srv.clock.now();
+    let candles = history
+        .candles(interval, count, now)
+        .into_iter()
+        .map(|mut c| {
+            c.start += srv.tstart;
+            c
+        })
+        .collect::<Vec<Candle>>();
+    Ok(json!({
+        "resource": resource,
+        "station": qry.station,
+        "interval": interval,
+        "candles": candles,
+    }))
+}
+
+pub fn player_orders(srv: &GameState, player: PlayerRef) -> ApiResult {
+    let mut token = LockToken::root();
+    let player_id = player.read(&mut token).unwrap().id;
+    let market = srv.market.read(&mut token).unwrap();
+    let orders = market.orderbook.player_orders(&player_id);
+    Ok(json!({ "orders": orders }))
+}
+
+// Orders placed at a station, or at every station when none is given
+pub fn order_book(srv: &GameState, resource: Resource, qry: &OrderBookQuery) -> ApiResult {
+    let mut token = LockToken::root();
+    let market = srv.market.read(&mut token).unwrap();
+    let station = qry.station.as_ref();
+    if let Some(id) = station {
+        if market.get_station(id).is_none() {
+            return Err(Errcode::NoSuchStation(*id));
+        }
+    }
+    let book = &market.orderbook;
+    let bids = book.bids(station, &resource);
+    let asks = book.asks(station, &resource);
+    Ok(json!({
+        "station": station,
+        "bids": bids.iter().map(|o| o.public_data()).collect::<Vec<Value>>(),
+        "asks": asks.iter().map(|o| o.public_data()).collect::<Vec<Value>>(),
+    }))
+}
+
+pub fn place_order(
+    srv: &GameState,
+    player: PlayerRef,
+    station_id: StationId,
+    order: OrderRequest,
+) -> ApiResult {
+    if let Some(ship) = order.ship {
+        return place_ship_order(srv, player, station_id, ship, order);
+    }
+    let mut token = LockToken::root();
+    let station = get_station(srv, &mut token, &player, &station_id)?;
+    let mut player = player.write(&mut token).unwrap();
+    let (player, token) = player.split();
+    let mut station = station.write(token).unwrap();
+    let (station, token) = station.split();
+    let mut market = srv.market.write(token).unwrap();
+    let now = srv.clock.now();
+    let (order, fills) = station.place_order(&order, player, market.deref_mut(), now)?;
+    for tx in fills.iter() {
+        trade_events(srv, player.id, station.id, Some(order.id), tx);
+    }
+    Ok(json!({ "order": order, "fills": fills }))
+}
+
+// Any station can be traded with from a ship docked there, not only the ones of the player
+fn place_ship_order(
+    srv: &GameState,
+    player: PlayerRef,
+    station_id: StationId,
+    ship: ShipId,
+    order: OrderRequest,
+) -> ApiResult {
+    let mut token = LockToken::root();
+    let mut player = player.write(&mut token).unwrap();
+    let (player, token) = player.split();
+    let mut market = srv.market.write(token).unwrap();
+    let Some(position) = market.get_station(&station_id).map(|m| m.position) else {
+        return Err(Errcode::NoSuchStation(station_id));
+    };
+    let now = srv.clock.now();
+    let (order, fills) =
+        player.place_ship_order(&ship, (station_id, position), &order, &mut market, now)?;
+    for tx in fills.iter() {
+        trade_events(srv, player.id, station_id, Some(order.id), tx);
+    }
+    Ok(json!({ "order": order, "fills": fills }))
+}
+
+pub fn cancel_order(srv: &GameState, player: PlayerRef, id: OrderId) -> ApiResult {
+    let mut token = LockToken::root();
+    let mut player = player.write(&mut token).unwrap();
+    let (player, token) = player.split();
+    srv.cancel_order(token, player, &id)
+        .map(|tx| serde_json::to_value(tx).unwrap())
+}
@@ -0,0 +1,350 @@
+// Metrics of the game and of the HTTP server, served at /metrics in the Prometheus text format
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+
+use ntex::service::{Middleware, Service, ServiceCtx};
+use ntex::web::{HttpRequest, WebRequest, WebResponse};
+use strum::IntoEnumIterator;
+
+use simeis_data::game::{Game, ITER_PERIOD};
+use simeis_data::lock::LockToken;
+use simeis_data::ship::resources::Resource;
+
+// Set on the responses by the API when the request failed, to count them by Errcode
+pub struct ResponseError(pub &'static str);
+
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+struct RequestLabels {
+    method: String,
+    route: String,
+    status: u16,
+    error: &'static str,
+}
+
+#[derive(Default)]
+pub struct HttpMetrics {
+    requests: Mutex<BTreeMap<RequestLabels, u64>>,
+}
+
+impl HttpMetrics {
+    fn count(&self, labels: RequestLabels) {
+        *self.requests.lock().unwrap().entry(labels).or_default() += 1;
+    }
+}
+
+// Route pattern of a request, with the path parameters replaced by their names
+//     so that the number of label values stays bounded
+pub fn route_pattern(req: &HttpRequest) -> String {
+    let mut params = req.match_info().iter().peekable();
+    req.path()
+        .split('/')
+        .map(|segment| match params.peek() {
+            Some((name, value)) if *value == segment => {
+                let name = format!("{{{name}}}");
+                params.next();
+                name
+            }
+            _ => segment.to_string(),
+        })
+        .collect::<Vec<String>>()
+        .join("/")
+}
+
+pub struct Metrics(pub Arc<HttpMetrics>);
+
+impl<S> Middleware<S> for Metrics {
+    type Service = MetricsMiddleware<S>;
+
+    fn create(&self, service: S) -> Self::Service {
+        MetricsMiddleware {
+            service,
+            metrics: self.0.clone(),
+        }
+    }
+}
+
+pub struct MetricsMiddleware<S> {
+    service: S,
+    metrics: Arc<HttpMetrics>,
+}
+
+impl<S, E> Service<WebRequest<E>> for MetricsMiddleware<S>
+where
+    S: Service<WebRequest<E>, Response = WebResponse>,
+{
+    type Response = WebResponse;
+    type Error = S::Error;
+
+    ntex::forward_poll!(service);
+    ntex::forward_ready!(service);
+    ntex::forward_shutdown!(service);
+
+    async fn call(
+        &self,
+        req: WebRequest<E>,
+        ctx: ServiceCtx<'_, Self>,
+    ) -> Result<Self::Response, Self::Error> {
+        let res = ctx.call(&self.service, req).await?;
+        // Removed and not only read, the response heads are pooled with their extensions
+        let error = res
+            .response()
+            .extensions_mut()
+            .remove::<ResponseError>()
+            .map(|e| e.0);
+        // Paths not matching any route are not counted separately, anyone could make up new ones
+        // The router consumes the whole path of the requests it routed, the others never reached it
+        //     or matched nothing, like the ones stopped by the rate limit
+        let route = if res.request().match_info().path().is_empty() {
+            route_pattern(res.request())
+        } else {
+            "unmatched".to_string()
+        };
+        self.metrics.count(RequestLabels {
+            method: res.request().method().to_string(),
+            route,
+            status: res.status().as_u16(),
+            error: error.unwrap_or("ok"),
+        });
+        Ok(res)
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+fn single(out: &mut String, name: &str, kind: &str, help: &str, value: f64) {
+    header(out, name, kind, help);
+    let _ = writeln!(out, "{name} {value}");
+}
+
+pub fn render(game: &Game, http: &HttpMetrics) -> String {
+    let mut out = String::new();
+
+    let ticks = &game.tick_stats;
+    single(
+        &mut out,
+        "simeis_tick_period_seconds",
+        "gauge",
+        "Target duration of an iteration of the game thread",
+        ITER_PERIOD.as_secs_f64(),
+    );
+    single(
+        &mut out,
+        "simeis_tick_duration_seconds",
+        "gauge",
+        "Duration of the last iteration of the game thread",
+        ticks.last(),
+    );
+    single(
+        &mut out,
+        "simeis_tick_duration_max_seconds",
+        "gauge",
+        "Longest iteration of the game thread",
+        ticks.max(),
+    );
+    single(
+        &mut out,
+        "simeis_tick_duration_seconds_total",
+        "counter",
+        "Time spent in the iterations of the game thread",
+        ticks.total(),
+    );
+    single(
+        &mut out,
+        "simeis_ticks_total",
+        "counter",
+        "Iterations of the game thread",
+        ticks.iterations() as f64,
+    );
+    single(
+        &mut out,
+        "simeis_tick_overruns_total",
+        "counter",
+        "Iterations of the game thread that took longer than the period",
+        ticks.overruns() as f64,
+    );
+    single(
+        &mut out,
+        "simeis_tick_dropped_seconds_total",
+        "counter",
+        "Real time not simulated because the game thread was too late",
+        ticks.dropped(),
+    );
+    single(
+        &mut out,
+        "simeis_game_paused",
+        "gauge",
+        "Whether the game is paused",
+        if game.is_paused() { 1.0 } else { 0.0 },
+    );
+    single(
+        &mut out,
+        "simeis_time_scale",
+        "gauge",
+        "Game seconds simulated per real second",
+        game.time_scale(),
+    );
+    single(
+        &mut out,
+        "simeis_game_time_seconds",
+        "gauge",
+        "Time elapsed in the game simulation",
+        game.clock.now(),
+    );
+
+    let (mut active, mut lost, mut money) = (0, 0, 0.0);
+    let mut ships: BTreeMap<&'static str, u64> = BTreeMap::new();
+    let mut token = LockToken::root();
+    let mut players = game.players.read(&mut token).unwrap();
+    let (all_players, players_token) = players.split();
+    for player in all_players.values() {
+        let player = player.read(players_token).unwrap();
+        if player.lost {
+            lost += 1;
+        } else {
+            active += 1;
+            money += player.money;
+        }
+        for ship in player.ships.values() {
+            *ships.entry((&ship.state).into()).or_default() += 1;
+        }
+    }
+    header(&mut out, "simeis_players", "gauge", "Players in the game");
+    let _ = writeln!(out, "simeis_players{{status=\"active\"}} {active}");
+    let _ = writeln!(out, "simeis_players{{status=\"lost\"}} {lost}");
+    header(&mut out, "simeis_ships", "gauge", "Ships of the players");
+    for (state, nb) in ships {
+        let _ = writeln!(out, "simeis_ships{{state=\"{state}\"}} {nb}");
+    }
+    single(
+        &mut out,
+        "simeis_money",
+        "gauge",
+        "Money owned by the players still in the game",
+        money,
+    );
+
+    drop(players);
+
+    let market = game.market.read(&mut token).unwrap();
+    header(
+        &mut out,
+        "simeis_market_price",
+        "gauge",
+        "Average price of a resource over all the stations",
+    );
+    for r in Resource::iter() {
+        let price = market.average_price(&r);
+        let _ = writeln!(out, "simeis_market_price{{resource=\"{r:?}\"}} {price}");
+    }
+    single(
+        &mut out,
+        "simeis_market_trades_total",
+        "counter",
+        "Trades made on the markets, including the order book fills",
+        market.trades as f64,
+    );
+    single(
+        &mut out,
+        "simeis_market_trades_per_minute",
+        "gauge",
+        "Trades made during the last minute of game time",
+        market.trades_per_minute() as f64,
+    );
+    drop(market);
+
+    single(
+        &mut out,
+        "simeis_syslog_pending",
+        "gauge",
+        "Syslog events sent but not yet delivered to the histories",
+        game.syslog_stats.pending() as f64,
+    );
+    single(
+        &mut out,
+        "simeis_syslog_delivered_total",
+        "counter",
+        "Syslog events delivered to the histories",
+        game.syslog_stats.delivered() as f64,
+    );
+
+    header(
+        &mut out,
+        "simeis_http_requests_total",
+        "counter",
+        "HTTP requests by route, status and error",
+    );
+    for (labels, nb) in http.requests.lock().unwrap().iter() {
+        let _ = writeln!(
+            out,
+            "simeis_http_requests_total{{method=\"{}\",route=\"{}\",status=\"{}\",error=\"{}\"}} {nb}",
+            labels.method, labels.route, labels.status, labels.error,
+        );
+    }
+    out
+}
+
+#[test]
+fn test_render_metrics() {
+    let game = Game::new(Default::default());
+    game.new_player(&mut LockToken::root(), "metrics", None)
+        .unwrap();
+    let http = HttpMetrics::default();
+    http.count(RequestLabels {
+        method: "GET".to_string(),
+        route: "/player/{id}".to_string(),
+        status: 200,
+        error: "NoPlayerKey",
+    });
+    let out = render(&game, &http);
+    assert!(out.contains("simeis_players{status=\"active\"} 1\n"));
+    assert!(out.contains("simeis_market_price{resource=\"Fuel\"}"));
+    assert!(out.contains(
+        "simeis_http_requests_total{method=\"GET\",route=\"/player/{id}\",status=\"200\",error=\"NoPlayerKey\"} 1\n"
+    ));
+    // Every sample belongs to a declared metric
+    for line in out.lines().filter(|l| !l.starts_with('#')) {
+        let name = line.split(['{', ' ']).next().unwrap();
+        assert!(out.contains(&format!("# TYPE {name} ")), "{line}");
+    }
+}
+
+#[ntex::test]
+async fn test_unmatched_route_label() {
+    use crate::ratelimit::{RateLimit, RateLimitConfig, RateLimiter};
+    use ntex::web::{self, test};
+
+    let http = Arc::new(HttpMetrics::default());
+    let limiter = Arc::new(RateLimiter::new(RateLimitConfig::default()));
+    let app = test::init_service(
+        web::App::new()
+            .wrap(RateLimit(limiter))
+            .wrap(Metrics(http.clone()))
+            .service(web::resource("/player/{id}").to(|| async { "ok" })),
+    )
+    .await;
+    let req = test::TestRequest::with_uri("/player/1").to_request();
+    test::call_service(&app, req).await;
+    // Past the burst of the limit, the responses never reach the router
+    for n in 0..150 {
+        let req = test::TestRequest::with_uri(&format!("/random/{n}")).to_request();
+        test::call_service(&app, req).await;
+    }
+
+    let requests = http.requests.lock().unwrap();
+    let routes = requests
+        .keys()
+        .map(|l| (l.route.as_str(), l.error))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        routes,
+        vec![
+            ("/player/{id}", "ok"),
+            ("unmatched", "RateLimited"),
+            ("unmatched", "ok")
+        ]
+    );
+}
@@ -0,0 +1,1350 @@
+// OpenAPI 3 description of the server, served at /openapi.json
+// Every operation is described once, with its path in the first version of the API,
+//     and its method and path under /v2 when it exists there
+use std::collections::BTreeMap;
+
+use serde_json::{json, Map, Value};
+
+use simeis_data::errors::Errcode;
+
+use crate::api_v2::error_status;
+
+type Fields = &'static [(&'static str, &'static str)];
+
+#[derive(PartialEq)]
+enum Auth {
+    None,
+    Player,
+    Admin,
+}
+
+struct ApiOp {
+    summary: &'static str,
+    v1: &'static str,
+    v2: Option<(&'static str, &'static str)>,
+    auth: Auth,
+    // Query parameters, skipped for a version when it passes them in the path or in the body
+    query: Fields,
+    // JSON body of the v2 operation
+    body: Fields,
+    response: Fields,
+    errors: &'static [Errcode],
+}
+
+const AUTH_ERRORS: &[Errcode] = &[
+    Errcode::NoPlayerKey,
+    Errcode::NoPlayerWithKey,
+    Errcode::PlayerLost,
+    Errcode::GameNotStarted(0.0),
+    Errcode::GameOver(0),
+];
+
+const SYSLOG_QUERY: Fields = &[
+    ("since", "integer"),
+    ("client", "string"),
+    ("types", "string"),
+    ("limit", "integer"),
+];
+const UPGRADE_RESPONSE: Fields = &[("new-rank", "integer"), ("cost", "number")];
+const CARGO_RESPONSE: Fields = &[
+    ("capacity", "number"),
+    ("usage", "number"),
+    ("resources", "{number}"),
+];
+const TRAVEL_RESPONSE: Fields = &[
+    ("direction", "[number]"),
+    ("distance", "number"),
+    ("duration", "number"),
+    ("fuel_consumption", "number"),
+    ("hull_usage", "number"),
+];
+const TX_RESPONSE: Fields = &[
+    ("added_cargo", "array"),
+    ("removed_cargo", "array"),
+    ("added_money", "number"),
+    ("removed_money", "number"),
+    ("fees", "number"),
+];
+// Operations answering with plain text instead of JSON
+const TEXT_RESPONSES: &[&str] = &["/metrics"];
+// v2 operations answering with 201 Created
+const CREATED: &[&str] = &[
+    "/players",
+    "/admin/invites",
+    "/admin/snapshots",
+    "/player/alerts",
+    "/stations/{station_id}/ships",
+    "/stations/{station_id}/crew",
+    "/stations/{station_id}/ships/{ship_id}/modules",
+    "/stations/{station_id}/market/orders",
+];
+const ALERT_RESPONSE: Fields = &[
+    ("id", "integer"),
+    ("rule", "object"),
+    ("triggered", "boolean"),
+];
+
+static OPERATIONS: &[ApiOp] = &[
+    ApiOp {
+        summary: "Check that the server is up",
+        v1: "/ping",
+        v2: Some(("get", "/ping")),
+        auth: Auth::None,
+        query: &[],
+        body: &[],
+        response: &[("ping", "string")],
+        errors: &[],
+    },
+    ApiOp {
+        summary: "Worlds running on this server, pick one with a /worlds/{name} prefix or a Simeis-World header",
+        v1: "/worlds",
+        v2: Some(("get", "/worlds")),
+        auth: Auth::None,
+        query: &[],
+        body: &[],
+        response: &[("worlds", "[object]")],
+        errors: &[],
+    },
+    ApiOp {
+        summary: "This document",
+        v1: "/openapi.json",
+        v2: None,
+        auth: Auth::None,
+        query: &[],
+        body: &[],
+        response: &[("openapi", "string"), ("paths", "object")],
+        errors: &[],
+    },
+    ApiOp {
+        summary: "Events of the player after a sequence number or the last acknowledged one, else the newest ones",
+        v1: "/syslogs",
+        v2: Some(("get", "/syslogs")),
+        auth: Auth::Player,
+        query: SYSLOG_QUERY,
+        body: &[],
+        response: &[
+            ("nb", "integer"),
+            ("events", "[object]"),
+            ("first_seq", "integer"),
+            ("last_seq", "integer"),
+            ("acked", "integer"),
+        ],
+        errors: &[Errcode::InvalidArgument("client")],
+    },
+    ApiOp {
+        summary: "Acknowledge the events up to a sequence number, for one client of the player",
+        v1: "/syslogs/ack/{seq}",
+        v2: Some(("post", "/syslogs/ack")),
+        auth: Auth::Player,
+        query: &[("client", "string")],
+        body: &[("seq", "integer"), ("client", "string")],
+        response: &[("acked", "integer")],
+        errors: &[Errcode::InvalidArgument("client")],
+    },
+    ApiOp {
+        summary: "Delivery statistics of the event queue",
+        v1: "/syslogs/stats",
+        v2: Some(("get", "/syslogs/stats")),
+        auth: Auth::None,
+        query: &[],
+        body: &[],
+        response: &[
+            ("pending", "integer"),
+            ("delivered", "integer"),
+            ("last_latency", "number"),
+            ("max_latency", "number"),
+        ],
+        errors: &[],
+    },
+    ApiOp {
+        summary: "WebSocket pushing the events of the player as they happen",
+        v1: "/syslogs/stream",
+        v2: Some(("get", "/syslogs/stream")),
+        auth: Auth::Player,
+        query: &[("since", "integer")],
+        body: &[],
+        response: &[],
+        errors: &[],
+    },
+    ApiOp {
+        summary: "Game and server metrics, in the Prometheus text format",
+        v1: "/metrics",
+        v2: None,
+        auth: Auth::None,
+        query: &[],
+        body: &[],
+        response: &[],
+        errors: &[],
+    },
+    ApiOp {
+        summary: "Create a new player",
+        v1: "/player/new/{name}",
+        v2: Some(("post", "/players")),
+        auth: Auth::None,
+        query: &[("invite", "string")],
+        body: &[("name", "string"), ("invite", "string")],
+        response: &[("playerId", "integer"), ("key", "string")],
+        errors: &[
+            Errcode::PlayerAlreadyExists(0, String::new()),
+            Errcode::InvalidInvite,
+        ],
+    },
+    ApiOp {
+        summary: "Replace the key of the player, the previous one stops working",
+        v1: "/player/key/rotate",
+        v2: Some(("post", "/player/key")),
+        auth: Auth::Player,
+        query: &[],
+        body: &[],
+        response: &[("key", "string")],
+        errors: &[],
+    },
+    ApiOp {
+        summary: "Requests allowed and rejected by the rate limiter of each route group",
+        v1: "/admin/ratelimit",
+        v2: Some(("get", "/admin/ratelimit")),
+        auth: Auth::Admin,
+        query: &[],
+        body: &[],
+        response: &[("buckets", "integer"), ("groups", "{object}")],
+        errors: &[],
+    },
+    ApiOp {
+        summary: "Issue a single-use invite token to create a player",
+        v1: "/admin/invite",
+        v2: Some(("post", "/admin/invites")),
+        auth: Auth::Admin,
+        query: &[],
+        body: &[],
+        response: &[("invite", "string")],
+        errors: &[],
+    },
+    ApiOp {
+        summary: "List all the players, with their money, costs and status",
+        v1: "/admin/players",
+        v2: Some(("get", "/admin/players")),
+        auth: Auth::Admin,
+        query: &[],
+        body: &[],
+        response: &[
+            ("paused", "boolean"),
+            ("time_scale", "number"),
+            ("players", "[object]"),
+        ],
+        errors: &[],
+    },
+    ApiOp {
+        summary: "Remove a player from the game, its orders are dropped",
+        v1: "/admin/player/{id}/kick",
+        v2: Some(("delete", "/admin/players/{id}")),
+        auth: Auth::Admin,
+        query: &[],
+        body: &[],
+        response: &[],
+        errors: &[Errcode::PlayerNotFound(0)],
+    },
+    ApiOp {
+        summary: "Start a player over with its initial money and an empty station",
+        v1: "/admin/player/{id}/reset",
+        v2: Some(("post", "/admin/players/{id}/reset")),
+        auth: Auth::Admin,
+        query: &[],
+        body: &[],
+        response: &[],
+        errors: &[Errcode::PlayerNotFound(0)],
+    },
+    ApiOp {
+        summary: "Give money to a player, or take it back with a negative amount",
+        v1: "/admin/player/{id}/grant/{amount}",
+        v2: Some(("post", "/admin/players/{id}/grant")),
+        auth: Auth::Admin,
+        query: &[],
+        body: &[("amount", "number")],
+        response: &[("money", "number")],
+        errors: &[
+            Errcode::PlayerNotFound(0),
+            Errcode::InvalidArgument("amount"),
+        ],
+    },
+    ApiOp {
+        summary: "Force the price of a resource on a station, or on all of them",
+        v1: "/admin/market/price/{resource}/{price}",
+        v2: Some(("put", "/admin/market/prices/{resource}")),
+        auth: Auth::Admin,
+        query: &[("station", "integer")],
+        body: &[("price", "number"), ("station", "integer")],
+        response: &[],
+        errors: &[Errcode::InvalidArgument("price"), Errcode::NoSuchStation(0)],
+    },
+    ApiOp {
+        summary: "Snapshot of the whole game, also written to SIMEIS_SNAPSHOT_DIR/<world> if set",
+        v1: "/admin/snapshot",
+        v2: Some(("post", "/admin/snapshots")),
+        auth: Auth::Admin,
+        query: &[],
+        body: &[],
+        response: &[("path", "string"), ("snapshot", "object")],
+        errors: &[],
+    },
+    ApiOp {
+        summary: "Pause the game, its clock stops and nothing is simulated",
+        v1: "/admin/pause",
+        v2: Some(("post", "/admin/pause")),
+        auth: Auth::Admin,
+        query: &[],
+        body: &[],
+        response: &[("paused", "boolean")],
+        errors: &[],
+    },
+    ApiOp {
+        summary: "Resume a paused game",
+        v1: "/admin/resume",
+        v2: Some(("post", "/admin/resume")),
+        auth: Auth::Admin,
+        query: &[],
+        body: &[],
+        response: &[("paused", "boolean")],
+        errors: &[],
+    },
+    ApiOp {
+        summary: "Set the game seconds simulated per real second",
+        v1: "/admin/timescale/{scale}",
+        v2: Some(("put", "/admin/timescale")),
+        auth: Auth::Admin,
+        query: &[],
+        body: &[("scale", "number")],
+        response: &[("time_scale", "number")],
+        errors: &[Errcode::InvalidArgument("scale")],
+    },
+    ApiOp {
+        summary: "Send a message to the syslog of every player",
+        v1: "/admin/broadcast",
+        v2: Some(("post", "/admin/broadcast")),
+        auth: Auth::Admin,
+        query: &[("message", "string")],
+        body: &[("message", "string")],
+        response: &[("players", "integer")],
+        errors: &[Errcode::InvalidArgument("message")],
+    },
+    ApiOp {
+        summary: "Public data of a player, or all of it when the key is its own",
+        v1: "/player/{id}",
+        v2: Some(("get", "/players/{id}")),
+        auth: Auth::None,
+        query: &[("key", "string")],
+        body: &[],
+        response: &[
+            ("id", "integer"),
+            ("name", "string"),
+            ("stations", "{array}"),
+            ("money", "number"),
+            ("ships", "[object]"),
+            ("costs", "number"),
+        ],
+        errors: &[Errcode::NoPlayerKey, Errcode::PlayerNotFound(0)],
+    },
+    ApiOp {
+        summary: "Money movements of the player",
+        v1: "/player/ledger",
+        v2: Some(("get", "/player/ledger")),
+        auth: Auth::Player,
+        query: &[
+            ("category", "string"),
+            ("since", "number"),
+            ("until", "number"),
+            ("offset", "integer"),
+            ("limit", "integer"),
+        ],
+        body: &[],
+        response: &[
+            ("offset", "integer"),
+            ("nb", "integer"),
+            ("entries", "[object]"),
+            ("pnl", "object"),
+        ],
+        errors: &[Errcode::InvalidArgument("category")],
+    },
+    ApiOp {
+        summary: "Alerts registered by the player",
+        v1: "/player/alerts",
+        v2: Some(("get", "/player/alerts")),
+        auth: Auth::Player,
+        query: &[],
+        body: &[],
+        response: &[("alerts", "[object]")],
+        errors: &[],
+    },
+    ApiOp {
+        summary: "Register an alert",
+        v1: "/player/alerts/new/{kind}/{threshold}",
+        v2: Some(("post", "/player/alerts")),
+        auth: Auth::Player,
+        query: &[
+            ("ship", "integer"),
+            ("station", "integer"),
+            ("resource", "string"),
+        ],
+        body: &[
+            ("kind", "string"),
+            ("threshold", "number"),
+            ("ship", "integer"),
+            ("station", "integer"),
+            ("resource", "string"),
+        ],
+        response: &[("id", "integer")],
+        errors: &[
+            Errcode::InvalidArgument("kind"),
+            Errcode::ShipNotFound(0),
+            Errcode::NoSuchStation(0),
+            Errcode::TooManyAlerts(0),
+        ],
+    },
+    ApiOp {
+        summary: "Get an alert",
+        v1: "/player/alerts/{alert_id}",
+        v2: Some(("get", "/player/alerts/{alert_id}")),
+        auth: Auth::Player,
+        query: &[],
+        body: &[],
+        response: ALERT_RESPONSE,
+        errors: &[Errcode::NoSuchAlert(0)],
+    },
+    ApiOp {
+        summary: "Change the threshold of an alert",
+        v1: "/player/alerts/{alert_id}/update/{threshold}",
+        v2: Some(("put", "/player/alerts/{alert_id}")),
+        auth: Auth::Player,
+        query: &[],
+        body: &[("threshold", "number")],
+        response: ALERT_RESPONSE,
+        errors: &[
+            Errcode::NoSuchAlert(0),
+            Errcode::InvalidArgument("threshold"),
+        ],
+    },
+    ApiOp {
+        summary: "Delete an alert",
+        v1: "/player/alerts/{alert_id}/delete",
+        v2: Some(("delete", "/player/alerts/{alert_id}")),
+        auth: Auth::Player,
+        query: &[],
+        body: &[],
+        response: ALERT_RESPONSE,
+        errors: &[Errcode::NoSuchAlert(0)],
+    },
+    ApiOp {
+        summary: "Status of a station",
+        v1: "/station/{station_id}",
+        v2: Some(("get", "/stations/{station_id}")),
+        auth: Auth::Player,
+        query: &[],
+        body: &[],
+        response: &[
+            ("id", "integer"),
+            ("position", "[integer]"),
+            ("crew", "{object}"),
+            ("cargo", "object"),
+            ("idle_crew", "[integer]"),
+            ("trader", "integer"),
+        ],
+        errors: &[Errcode::NoSuchStation(0)],
+    },
+    ApiOp {
+        summary: "Planets and stations around a station",
+        v1: "/station/{station_id}/scan",
+        v2: Some(("get", "/stations/{station_id}/scan")),
+        auth: Auth::Player,
+        query: &[],
+        body: &[],
+        response: &[("planets", "[object]"), ("stations", "[object]")],
+        errors: &[Errcode::NoSuchStation(0)],
+    },
+    ApiOp {
+        summary: "Ships for sale at the shipyard",
+        v1: "/station/{station_id}/shipyard/list",
+        v2: Some(("get", "/stations/{station_id}/shipyard")),
+        auth: Auth::Player,
+        query: &[],
+        body: &[],
+        response: &[("ships", "[object]")],
+        errors: &[Errcode::NoSuchStation(0)],
+    },
+    ApiOp {
+        summary: "Buy a ship from the shipyard",
+        v1: "/station/{station_id}/shipyard/buy/{id}",
+        v2: Some(("post", "/stations/{station_id}/ships")),
+        auth: Auth::Player,
+        query: &[],
+        body: &[("id", "integer")],
+        response: &[("shipId", "integer")],
+        errors: &[
+            Errcode::NoSuchStation(0),
+            Errcode::ShipNotFound(0),
+            Errcode::NotEnoughMoney(0.0, 0.0),
+        ],
+    },
+    ApiOp {
+        summary: "Prices of the ship upgrades",
+        v1: "/station/{station_id}/shipyard/upgrade",
+        v2: Some(("get", "/stations/{station_id}/shipyard/upgrades")),
+        auth: Auth::Player,
+        query: &[],
+        body: &[],
+        response: &[("*", "object")],
+        errors: &[Errcode::NoSuchStation(0)],
+    },
+    ApiOp {
+        summary: "Install an upgrade on a ship",
+        v1: "/station/{station_id}/shipyard/upgrade/{ship_id}/{upgrade_type}",
+        v2: Some(("post", "/stations/{station_id}/ships/{ship_id}/upgrades")),
+        auth: Auth::Player,
+        query: &[],
+        body: &[("upgrade", "string")],
+        response: &[("cost", "number")],
+        errors: &[
+            Errcode::InvalidArgument("upgrade"),
+            Errcode::NoSuchStation(0),
+            Errcode::ShipNotFound(0),
+            Errcode::ShipNotInStation,
+            Errcode::NotEnoughMoney(0.0, 0.0),
+        ],
+    },
+    ApiOp {
+        summary: "Hire a crew member",
+        v1: "/station/{station_id}/crew/hire/{crewtype}",
+        v2: Some(("post", "/stations/{station_id}/crew")),
+        auth: Auth::Player,
+        query: &[],
+        body: &[("crew_type", "string")],
+        response: &[("id", "integer")],
+        errors: &[
+            Errcode::InvalidArgument("crew_type"),
+            Errcode::NoSuchStation(0),
+        ],
+    },
+    ApiOp {
+        summary: "Prices of the rank upgrades of the crew of a ship",
+        v1: "/station/{station_id}/crew/upgrade/ship/{ship_id}",
+        v2: Some(("get", "/stations/{station_id}/ships/{ship_id}/crew")),
+        auth: Auth::Player,
+        query: &[],
+        body: &[],
+        response: &[("*", "object")],
+        errors: &[
+            Errcode::NoSuchStation(0),
+            Errcode::ShipNotFound(0),
+            Errcode::ShipNotInStation,
+        ],
+    },
+    ApiOp {
+        summary: "Upgrade the rank of a crew member of a ship",
+        v1: "/station/{station_id}/crew/upgrade/ship/{ship_id}/{crew_id}",
+        v2: Some((
+            "post",
+            "/stations/{station_id}/ships/{ship_id}/crew/{crew_id}/upgrade",
+        )),
+        auth: Auth::Player,
+        query: &[],
+        body: &[],
+        response: UPGRADE_RESPONSE,
+        errors: &[
+            Errcode::NoSuchStation(0),
+            Errcode::ShipNotFound(0),
+            Errcode::ShipNotInStation,
+            Errcode::CrewMemberNotFound(0),
+            Errcode::NotEnoughMoney(0.0, 0.0),
+        ],
+    },
+    ApiOp {
+        summary: "Upgrade the rank of the trader of the station",
+        v1: "/station/{station_id}/crew/upgrade/trader",
+        v2: Some(("post", "/stations/{station_id}/trader/upgrade")),
+        auth: Auth::Player,
+        query: &[],
+        body: &[],
+        response: UPGRADE_RESPONSE,
+        errors: &[
+            Errcode::NoSuchStation(0),
+            Errcode::NoTraderAssigned,
+            Errcode::NotEnoughMoney(0.0, 0.0),
+        ],
+    },
+    ApiOp {
+        summary: "Assign a crew member as the trader of the station",
+        v1: "/station/{station_id}/crew/assign/{crewid}/trading",
+        v2: Some(("put", "/stations/{station_id}/trader")),
+        auth: Auth::Player,
+        query: &[],
+        body: &[("crew_id", "integer")],
+        response: &[],
+        errors: &[
+            Errcode::NoSuchStation(0),
+            Errcode::CrewMemberNotFound(0),
+            Errcode::CrewMemberNotIdle(0),
+            Errcode::WrongCrewType(simeis_data::crew::CrewMemberType::Trader),
+        ],
+    },
+    ApiOp {
+        summary: "Assign a crew member as the pilot of a ship",
+        v1: "/station/{station_id}/crew/assign/{crewid}/{shipid}/pilot",
+        v2: Some(("put", "/stations/{station_id}/ships/{ship_id}/pilot")),
+        auth: Auth::Player,
+        query: &[],
+        body: &[("crew_id", "integer")],
+        response: &[],
+        errors: &[
+            Errcode::NoSuchStation(0),
+            Errcode::ShipNotFound(0),
+            Errcode::ShipNotInStation,
+            Errcode::CrewMemberNotFound(0),
+            Errcode::CrewMemberNotIdle(0),
+            Errcode::WrongCrewType(simeis_data::crew::CrewMemberType::Pilot),
+        ],
+    },
+    ApiOp {
+        summary: "Assign a crew member as the operator of a ship module",
+        v1: "/station/{station_id}/crew/assign/{crewid}/{shipid}/{modid}",
+        v2: Some((
+            "put",
+            "/stations/{station_id}/ships/{ship_id}/modules/{mod_id}/operator",
+        )),
+        auth: Auth::Player,
+        query: &[],
+        body: &[("crew_id", "integer")],
+        response: &[],
+        errors: &[
+            Errcode::NoSuchStation(0),
+            Errcode::ShipNotFound(0),
+            Errcode::ShipNotInStation,
+            Errcode::NoSuchModule(0),
+            Errcode::CrewMemberNotFound(0),
+            Errcode::CrewMemberNotIdle(0),
+            Errcode::CrewNotNeeded,
+            Errcode::WrongCrewType(simeis_data::crew::CrewMemberType::Operator),
+        ],
+    },
+    ApiOp {
+        summary: "Prices of the ship modules",
+        v1: "/station/{station_id}/shop/modules",
+        v2: Some(("get", "/stations/{station_id}/modules")),
+        auth: Auth::Player,
+        query: &[],
+        body: &[],
+        response: &[("*", "number")],
+        errors: &[Errcode::NoSuchStation(0)],
+    },
+    ApiOp {
+        summary: "Buy a module for a ship",
+        v1: "/station/{station_id}/shop/modules/{ship_id}/buy/{modtype}",
+        v2: Some(("post", "/stations/{station_id}/ships/{ship_id}/modules")),
+        auth: Auth::Player,
+        query: &[],
+        body: &[("module_type", "string")],
+        response: &[("id", "integer")],
+        errors: &[
+            Errcode::InvalidArgument("module_type"),
+            Errcode::NoSuchStation(0),
+            Errcode::ShipNotFound(0),
+            Errcode::ShipNotInStation,
+            Errcode::NotEnoughMoney(0.0, 0.0),
+        ],
+    },
+    ApiOp {
+        summary: "Prices of the rank upgrades of the modules of a ship",
+        v1: "/station/{station_id}/shop/modules/{ship_id}/upgrade",
+        v2: Some(("get", "/stations/{station_id}/ships/{ship_id}/modules")),
+        auth: Auth::Player,
+        query: &[],
+        body: &[],
+        response: &[("*", "object")],
+        errors: &[
+            Errcode::NoSuchStation(0),
+            Errcode::ShipNotFound(0),
+            Errcode::ShipNotInStation,
+        ],
+    },
+    ApiOp {
+        summary: "Upgrade the rank of a ship module",
+        v1: "/station/{station_id}/shop/modules/{ship_id}/upgrade/{modid}",
+        v2: Some((
+            "post",
+            "/stations/{station_id}/ships/{ship_id}/modules/{mod_id}/upgrade",
+        )),
+        auth: Auth::Player,
+        query: &[],
+        body: &[],
+        response: UPGRADE_RESPONSE,
+        errors: &[
+            Errcode::NoSuchStation(0),
+            Errcode::ShipNotFound(0),
+            Errcode::ShipNotInStation,
+            Errcode::NoSuchModule(0),
+            Errcode::NotEnoughMoney(0.0, 0.0),
+        ],
+    },
+    ApiOp {
+        summary: "Expand the cargo of the station",
+        v1: "/station/{station_id}/shop/cargo/buy/{amount}",
+        v2: Some(("post", "/stations/{station_id}/cargo")),
+        auth: Auth::Player,
+        query: &[],
+        body: &[("amount", "integer")],
+        response: CARGO_RESPONSE,
+        errors: &[Errcode::NoSuchStation(0), Errcode::NotEnoughMoney(0.0, 0.0)],
+    },
+    ApiOp {
+        summary: "Prices of the station upgrades",
+        v1: "/station/{station_id}/upgrades",
+        v2: Some(("get", "/stations/{station_id}/upgrades")),
+        auth: Auth::Player,
+        query: &[],
+        body: &[],
+        response: &[("cargo-expansion", "number"), ("trader-upgrade", "number")],
+        errors: &[Errcode::NoSuchStation(0)],
+    },
+    ApiOp {
+        summary: "Refuel a ship with the fuel of the station cargo",
+        v1: "/station/{station_id}/refuel/{ship_id}",
+        v2: Some(("post", "/stations/{station_id}/ships/{ship_id}/refuel")),
+        auth: Auth::Player,
+        query: &[],
+        body: &[],
+        response: &[("added-fuel", "number")],
+        errors: &[
+            Errcode::NoSuchStation(0),
+            Errcode::ShipNotFound(0),
+            Errcode::ShipNotInStation,
+            Errcode::NoFuelInCargo,
+        ],
+    },
+    ApiOp {
+        summary: "Repair a ship with the hull plates of the station cargo",
+        v1: "/station/{station_id}/repair/{ship_id}",
+        v2: Some(("post", "/stations/{station_id}/ships/{ship_id}/repair")),
+        auth: Auth::Player,
+        query: &[],
+        body: &[],
+        response: &[("added-hull", "number")],
+        errors: &[
+            Errcode::NoSuchStation(0),
+            Errcode::ShipNotFound(0),
+            Errcode::ShipNotInStation,
+            Errcode::NoHullPlateInCargo,
+        ],
+    },
+    ApiOp {
+        summary: "Status of a ship",
+        v1: "/ship/{ship_id}",
+        v2: Some(("get", "/ships/{ship_id}")),
+        auth: Auth::Player,
+        query: &[],
+        body: &[],
+        response: &[
+            ("id", "integer"),
+            ("reactor_power", "integer"),
+            ("fuel_tank_capacity", "number"),
+            ("hull_decay_capacity", "number"),
+            ("modules", "{object}"),
+            ("position", "[integer]"),
+            ("crew", "{object}"),
+            ("cargo", "object"),
+            ("fuel_tank", "number"),
+            ("hull_decay", "number"),
+            ("pilot", "integer"),
+            ("state", "object"),
+            ("stats", "object"),
+        ],
+        errors: &[Errcode::ShipNotFound(0)],
+    },
+    ApiOp {
+        summary: "Costs of a travel to some coordinates",
+        v1: "/ship/{ship_id}/travelcost/{x}/{y}/{z}",
+        v2: Some(("get", "/ships/{ship_id}/travelcost")),
+        auth: Auth::Player,
+        query: &[("x", "integer"), ("y", "integer"), ("z", "integer")],
+        body: &[],
+        response: TRAVEL_RESPONSE,
+        errors: &[
+            Errcode::ShipNotFound(0),
+            Errcode::NoPilotAssigned,
+            Errcode::NullDistance,
+        ],
+    },
+    ApiOp {
+        summary: "Start a travel to some coordinates",
+        v1: "/ship/{ship_id}/navigate/{x}/{y}/{z}",
+        v2: Some(("post", "/ships/{ship_id}/navigate")),
+        auth: Auth::Player,
+        query: &[],
+        body: &[("x", "integer"), ("y", "integer"), ("z", "integer")],
+        response: TRAVEL_RESPONSE,
+        errors: &[
+            Errcode::ShipNotFound(0),
+            Errcode::ShipNotIdle,
+            Errcode::NoPilotAssigned,
+            Errcode::NullDistance,
+            Errcode::CannotPerformTravel,
+        ],
+    },
+    ApiOp {
+        summary: "Start extracting resources on the planet the ship is on",
+        v1: "/ship/{ship_id}/extraction/start",
+        v2: Some(("post", "/ships/{ship_id}/extraction")),
+        auth: Auth::Player,
+        query: &[],
+        body: &[],
+        response: &[("*", "number")],
+        errors: &[
+            Errcode::ShipNotFound(0),
+            Errcode::ShipNotIdle,
+            Errcode::CannotExtractWithoutPlanet,
+        ],
+    },
+    ApiOp {
+        summary: "Stop the extraction of a ship",
+        v1: "/ship/{ship_id}/extraction/stop",
+        v2: Some(("delete", "/ships/{ship_id}/extraction")),
+        auth: Auth::Player,
+        query: &[],
+        body: &[],
+        response: &[],
+        errors: &[Errcode::ShipNotFound(0), Errcode::ShipNotExtracting],
+    },
+    ApiOp {
+        summary: "Unload resources from a ship to the station it is docked on",
+        v1: "/ship/{ship_id}/unload/{resource}/{amount}",
+        v2: Some(("post", "/ships/{ship_id}/unload")),
+        auth: Auth::Player,
+        query: &[],
+        body: &[("resource", "string"), ("amount", "number")],
+        response: &[("unloaded", "number")],
+        errors: &[
+            Errcode::InvalidArgument("resource"),
+            Errcode::ShipNotFound(0),
+            Errcode::ShipNotInStation,
+            Errcode::CargoFull,
+        ],
+    },
+    ApiOp {
+        summary: "Players ranked by their score, computed from their net worth",
+        v1: "/leaderboard",
+        v2: Some(("get", "/leaderboard")),
+        auth: Auth::None,
+        query: &[("limit", "integer")],
+        body: &[],
+        response: &[
+            ("time", "number"),
+            ("formula", "{number}"),
+            ("ranking", "[object]"),
+        ],
+        errors: &[],
+    },
+    ApiOp {
+        summary: "Total scores of the players, recorded periodically",
+        v1: "/leaderboard/history",
+        v2: Some(("get", "/leaderboard/history")),
+        auth: Auth::None,
+        query: &[("since", "number"), ("player", "integer")],
+        body: &[],
+        response: &[("period", "number"), ("snapshots", "[object]")],
+        errors: &[],
+    },
+    ApiOp {
+        summary: "Phase and times of the current session",
+        v1: "/session",
+        v2: Some(("get", "/session")),
+        auth: Auth::None,
+        query: &[],
+        body: &[],
+        response: &[
+            ("id", "integer"),
+            ("phase", "string"),
+            ("now", "number"),
+            ("start", "number"),
+            ("end", "number"),
+            ("remaining", "number"),
+        ],
+        errors: &[],
+    },
+    ApiOp {
+        summary: "All the sessions of the server",
+        v1: "/sessions",
+        v2: Some(("get", "/sessions")),
+        auth: Auth::None,
+        query: &[],
+        body: &[],
+        response: &[("current", "integer"), ("sessions", "[object]")],
+        errors: &[],
+    },
+    ApiOp {
+        summary: "A session, with its final ranking once it is over",
+        v1: "/sessions/{id}",
+        v2: Some(("get", "/sessions/{id}")),
+        auth: Auth::None,
+        query: &[],
+        body: &[],
+        response: &[
+            ("id", "integer"),
+            ("phase", "string"),
+            ("created", "number"),
+            ("start", "number"),
+            ("end", "number"),
+            ("ranking", "[object]"),
+        ],
+        errors: &[Errcode::NoSuchSession(0)],
+    },
+    ApiOp {
+        summary: "Average prices of the resources, or the market of a station",
+        v1: "/market/prices",
+        v2: Some(("get", "/market/prices")),
+        auth: Auth::None,
+        query: &[("station", "integer")],
+        body: &[],
+        response: &[
+            ("prices", "{number}"),
+            ("station", "integer"),
+            ("position", "[integer]"),
+            ("supply", "{number}"),
+        ],
+        errors: &[Errcode::NoSuchStation(0)],
+    },
+    ApiOp {
+        summary: "Buy resources on the market of a station",
+        v1: "/market/{station_id}/buy/{resource}/{amnt}",
+        v2: Some(("post", "/stations/{station_id}/market/buy")),
+        auth: Auth::Player,
+        query: &[],
+        body: &[("resource", "string"), ("amount", "number")],
+        response: TX_RESPONSE,
+        errors: &[
+            Errcode::InvalidArgument("resource"),
+            Errcode::NoSuchStation(0),
+            Errcode::NoTraderAssigned,
+            Errcode::BuyNothing,
+            Errcode::NotEnoughMoney(0.0, 0.0),
+        ],
+    },
+    ApiOp {
+        summary: "Sell resources on the market of a station",
+        v1: "/market/{station_id}/sell/{resource}/{amnt}",
+        v2: Some(("post", "/stations/{station_id}/market/sell")),
+        auth: Auth::Player,
+        query: &[],
+        body: &[("resource", "string"), ("amount", "number")],
+        response: TX_RESPONSE,
+        errors: &[
+            Errcode::InvalidArgument("resource"),
+            Errcode::NoSuchStation(0),
+            Errcode::NoTraderAssigned,
+            Errcode::SellNothing,
+        ],
+    },
+    ApiOp {
+        summary: "Fee rate applied by the trader of a station",
+        v1: "/market/{station_id}/fee_rate",
+        v2: Some(("get", "/stations/{station_id}/market/fee_rate")),
+        auth: Auth::Player,
+        query: &[],
+        body: &[],
+        response: &[("fee_rate", "number")],
+        errors: &[Errcode::NoSuchStation(0), Errcode::NoTraderAssigned],
+    },
+    ApiOp {
+        summary: "Price candles of a resource",
+        v1: "/market/history/{resource}",
+        v2: Some(("get", "/market/history/{resource}")),
+        auth: Auth::None,
+        query: &[
+            ("station", "integer"),
+            ("interval", "number"),
+            ("count", "integer"),
+        ],
+        body: &[],
+        response: &[
+            ("resource", "string"),
+            ("station", "integer"),
+            ("interval", "number"),
+            ("candles", "[object]"),
+        ],
+        errors: &[
+            Errcode::InvalidArgument("resource"),
+            Errcode::InvalidArgument("interval"),
+            Errcode::NoSuchStation(0),
+        ],
+    },
+    ApiOp {
+        summary: "Open orders of the player",
+        v1: "/market/orders",
+        v2: Some(("get", "/market/orders")),
+        auth: Auth::Player,
+        query: &[],
+        body: &[],
+        response: &[("orders", "[object]")],
+        errors: &[],
+    },
+    ApiOp {
+        summary: "Order book of a resource, at a station or at all of them",
+        v1: "/market/orders/{resource}",
+        v2: Some(("get", "/market/book/{resource}")),
+        auth: Auth::None,
+        query: &[("station", "integer")],
+        body: &[],
+        response: &[
+            ("station", "integer"),
+            ("bids", "[object]"),
+            ("asks", "[object]"),
+        ],
+        errors: &[Errcode::InvalidArgument("resource"), Errcode::NoSuchStation(0)],
+    },
+    ApiOp {
+        summary: "Place a limit order on the market of a station, or take the orders there from a docked ship",
+        v1: "/market/{station_id}/order/{side}/{resource}/{amnt}/{price}",
+        v2: Some(("post", "/stations/{station_id}/market/orders")),
+        auth: Auth::Player,
+        query: &[("ttl", "number"), ("ship", "integer")],
+        body: &[
+            ("side", "string"),
+            ("resource", "string"),
+            ("amount", "number"),
+            ("price", "number"),
+            ("ttl", "number"),
+            ("ship", "integer"),
+        ],
+        response: &[("order", "object"), ("fills", "[object]")],
+        errors: &[
+            Errcode::InvalidArgument("side"),
+            Errcode::InvalidArgument("resource"),
+            Errcode::NoSuchStation(0),
+            Errcode::ShipNotFound(0),
+            Errcode::ShipNotInStation,
+            Errcode::NoTraderAssigned,
+            Errcode::BuyNothing,
+            Errcode::SellNothing,
+            Errcode::NotEnoughMoney(0.0, 0.0),
+        ],
+    },
+    ApiOp {
+        summary: "Cancel an open order",
+        v1: "/market/order/{order_id}/cancel",
+        v2: Some(("delete", "/market/orders/{order_id}")),
+        auth: Auth::Player,
+        query: &[],
+        body: &[],
+        response: TX_RESPONSE,
+        errors: &[Errcode::NoSuchOrder(0)],
+    },
+];
+
+fn schema(ty: &str) -> Value {
+    if let Some(inner) = ty.strip_prefix('[') {
+        json!({ "type": "array", "items": schema(inner.trim_end_matches(']')) })
+    } else if let Some(inner) = ty.strip_prefix('{') {
+        json!({ "type": "object", "additionalProperties": schema(inner.trim_end_matches('}')) })
+    } else {
+        json!({ "type": ty })
+    }
+}
+
+fn object_schema(fields: Fields) -> Value {
+    let mut props = Map::new();
+    for (name, ty) in fields {
+        if *name == "*" {
+            return schema(&format!("{{{ty}}}"));
+        }
+        props.insert(name.to_string(), schema(ty));
+    }
+    json!({ "type": "object", "properties": props })
+}
+
+fn path_params(path: &str) -> Vec<&str> {
+    path.split('/')
+        .filter_map(|p| p.strip_prefix('{')?.strip_suffix('}'))
+        .collect()
+}
+
+fn path_param_type(name: &str) -> &'static str {
+    match name {
+        "name" | "kind" | "crewtype" | "upgrade_type" | "modtype" | "resource" | "side" => "string",
+        "threshold" | "amnt" | "price" => "number",
+        _ => "integer",
+    }
+}
+
+fn errcode_type(err: &Errcode) -> &'static str {
+    err.into()
+}
+
+fn op_errors(op: &ApiOp) -> Vec<&'static Errcode> {
+    let mut errors: Vec<&Errcode> = vec![];
+    match op.auth {
+        Auth::None => {}
+        Auth::Player => errors.extend(AUTH_ERRORS.iter()),
+        Auth::Admin => errors.push(&Errcode::NotAdmin),
+    }
+    errors.push(&Errcode::RateLimited(0.0));
+    for err in op.errors.iter() {
+        if !errors.iter().any(|e| errcode_type(e) == errcode_type(err)) {
+            errors.push(err);
+        }
+    }
+    errors
+}
+
+fn parameters(op: &ApiOp, path: &str, body: Fields) -> Vec<Value> {
+    let in_path = path_params(path);
+    let mut params = in_path
+        .iter()
+        .map(|p| {
+            json!({
+                "name": p,
+                "in": "path",
+                "required": true,
+                "schema": schema(path_param_type(p)),
+            })
+        })
+        .collect::<Vec<Value>>();
+    for (name, ty) in op.query.iter() {
+        if in_path.contains(name) || body.iter().any(|(b, _)| b == name) {
+            continue;
+        }
+        params.push(json!({ "name": name, "in": "query", "schema": schema(ty) }));
+    }
+    if op.auth != Auth::None {
+        params.push(json!({
+            "name": "key",
+            "in": "query",
+            "description": "Deprecated, use the Authorization header",
+            "schema": { "type": "string" },
+        }));
+    }
+    params
+}
+
+fn security(op: &ApiOp) -> Value {
+    match op.auth {
+        Auth::None => json!([]),
+        _ => json!([{ "bearer": [] }]),
+    }
+}
+
+fn error_ref() -> Value {
+    json!({ "$ref": "#/components/schemas/Error" })
+}
+
+// In the first version of the API, every response has the status 200
+//     and the "error" field is "ok" on success
+fn v1_operation(op: &ApiOp) -> Value {
+    if TEXT_RESPONSES.contains(&op.v1) {
+        return json!({
+            "summary": op.summary,
+            "responses": {
+                "200": {
+                    "description": op.summary,
+                    "content": { "text/plain": { "schema": { "type": "string" } } },
+                },
+            },
+        });
+    }
+    let mut success = object_schema(op.response);
+    if let Some(props) = success.get_mut("properties") {
+        props["error"] = json!({ "type": "string", "enum": ["ok"] });
+    }
+    let errors = op_errors(op)
+        .into_iter()
+        .map(errcode_type)
+        .collect::<Vec<&str>>();
+    json!({
+        "summary": op.summary,
+        "tags": ["v1"],
+        "parameters": parameters(op, op.v1, &[]),
+        "security": security(op),
+        "x-errors": errors,
+        "responses": {
+            "200": {
+                "description": "Result, or the error that occurred",
+                "content": { "application/json": { "schema": {
+                    "oneOf": [success, error_ref()],
+                }}},
+            },
+        },
+    })
+}
+
+fn v2_operation(op: &ApiOp, method: &str, path: &str) -> Value {
+    let status = if method == "post" && CREATED.contains(&path) {
+        "201"
+    } else {
+        "200"
+    };
+    let mut responses = Map::new();
+    responses.insert(
+        status.to_string(),
+        json!({
+            "description": op.summary,
+            "content": { "application/json": { "schema": object_schema(op.response) } },
+        }),
+    );
+    let mut by_status: BTreeMap<u16, Vec<&str>> = BTreeMap::new();
+    for err in op_errors(op) {
+        by_status
+            .entry(error_status(err).as_u16())
+            .or_default()
+            .push(errcode_type(err));
+    }
+    for (status, types) in by_status {
+        responses.insert(
+            status.to_string(),
+            json!({
+                "description": types.join(", "),
+                "content": { "application/json": { "schema": error_ref() } },
+            }),
+        );
+    }
+    let mut res = json!({
+        "summary": op.summary,
+        "tags": ["v2"],
+        "parameters": parameters(op, path, op.body),
+        "security": security(op),
+        "responses": responses,
+    });
+    if !op.body.is_empty() {
+        res["requestBody"] = json!({
+            "required": true,
+            "content": { "application/json": { "schema": object_schema(op.body) } },
+        });
+    }
+    res
+}
+
+pub fn spec() -> Value {
+    let mut paths: BTreeMap<String, Map<String, Value>> = BTreeMap::new();
+    let mut all_errors = vec![];
+    for op in OPERATIONS.iter() {
+        paths
+            .entry(op.v1.to_string())
+            .or_default()
+            .insert("get".to_string(), v1_operation(op));
+        if let Some((method, path)) = op.v2 {
+            paths
+                .entry(format!("/v2{path}"))
+                .or_default()
+                .insert(method.to_string(), v2_operation(op, method, path));
+        }
+        for err in op_errors(op) {
+            let err = errcode_type(err);
+            if !all_errors.contains(&err) {
+                all_errors.push(err);
+            }
+        }
+    }
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "Simeis",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "paths": paths,
+        "components": {
+            "securitySchemes": {
+                "bearer": { "type": "http", "scheme": "bearer" },
+            },
+            "schemas": {
+                "Error": {
+                    "type": "object",
+                    "properties": {
+                        "error": { "type": "string" },
+                        "code": { "type": "integer" },
+                        "type": { "type": "string", "enum": all_errors },
+                        "category": {
+                            "type": "string",
+                            "enum": [
+                                "auth",
+                                "not_found",
+                                "invalid_argument",
+                                "funds",
+                                "conflict",
+                                "rate_limited",
+                            ],
+                        },
+                        "details": { "type": "object" },
+                    },
+                },
+            },
+        },
+    })
+}
+
+// Method and path of the handlers of a source file, read from their #[web::<method>] attribute
+#[cfg(test)]
+fn handler_routes<'a>(
+    prefix: &'a str,
+    source: &'a str,
+) -> impl Iterator<Item = (String, String)> + 'a {
+    source.lines().filter_map(move |line| {
+        let attr = line.trim().strip_prefix("#[web::")?;
+        let (method, rest) = attr.split_once("(\"")?;
+        let (path, _) = rest.split_once('"')?;
+        Some((method.to_string(), format!("{prefix}{path}")))
+    })
+}
+
+// Every documented operation is served by the app, on a route with the same parameters
+#[ntex::test]
+async fn test_openapi_covers_routes() {
+    use ntex::http::{Method, StatusCode};
+    use ntex::web::{self, test};
+
+    let app = test::init_service(
+        web::App::new()
+            .configure(crate::api::configure)
+            .configure(crate::api_v2::configure),
+    )
+    .await;
+    let spec = spec();
+    for (path, methods) in spec["paths"].as_object().unwrap() {
+        // Each parameter gets its own value, to find back its name in the routed request
+        let mut nparam = 0;
+        let uri = path
+            .split('/')
+            .map(|segment| {
+                if !segment.starts_with('{') {
+                    return segment.to_string();
+                }
+                nparam += 1;
+                format!("param{nparam}")
+            })
+            .collect::<Vec<String>>()
+            .join("/");
+        for method in methods.as_object().unwrap().keys() {
+            let req = test::TestRequest::with_uri(&uri)
+                .method(Method::from_bytes(method.to_uppercase().as_bytes()).unwrap())
+                .to_request();
+            let res = test::call_service(&app, req).await;
+            let routed = res.request().match_info().path().is_empty()
+                && res.status() != StatusCode::METHOD_NOT_ALLOWED;
+            assert!(
+                routed && crate::metrics::route_pattern(res.request()) == *path,
+                "{} {path} is documented but not served",
+                method.to_uppercase(),
+            );
+        }
+    }
+
+    // And every handler is documented, with the route of its attribute
+    let handlers = handler_routes("", include_str!("api.rs"))
+        .chain(handler_routes("/v2", include_str!("api_v2.rs")))
+        .collect::<Vec<(String, String)>>();
+    assert!(!handlers.is_empty());
+    for (method, path) in handlers {
+        assert!(
+            spec["paths"][&path].get(&method).is_some(),
+            "{} {path} is served but not documented",
+            method.to_uppercase(),
+        );
+    }
+
+    // The probe does tell the missing routes apart
+    let req = test::TestRequest::with_uri("/v2/nothing/param1").to_request();
+    let res = test::call_service(&app, req).await;
+    assert!(!res.request().match_info().path().is_empty());
+}
@@ -1,18 +1,60 @@
+use std::str::FromStr;
+
+use serde::Deserialize;
 use serde_json::json;
 
 use simeis_data::errors::Errcode;
+use simeis_data::lock::LockToken;
+use simeis_data::player::alert::{Alert, AlertId, AlertRule};
+use simeis_data::player::ledger::{LedgerCategory, LedgerEntry};
 use simeis_data::player::{PlayerId, PlayerKey};
 use simeis_data::ship::Ship;
 
-use crate::{api::ApiResult, GameState};
+use crate::api::{ApiResult, PlayerRef};
+use crate::GameState;
+
+const LEDGER_DEFAULT_LIMIT: usize = 100;
+const LEDGER_MAX_LIMIT: usize = 1000;
+
+#[derive(Deserialize)]
+pub struct LedgerQuery {
+    pub category: Option<String>,
+    pub since: Option<f64>,
+    pub until: Option<f64>,
+    pub offset: Option<usize>,
+    pub limit: Option<usize>,
+}
+
+pub fn new_player(srv: &GameState, name: &str, invite: Option<&str>) -> ApiResult {
+    srv.new_player(&mut LockToken::root(), name, invite)
+        .map(|(id, key)| {
+            json!({
+                "playerId": id,
+                "key": key,
+            })
+        })
+}
+
+pub fn rotate_key(srv: &GameState, player: PlayerRef) -> ApiResult {
+    let mut token = LockToken::root();
+    let id = player.read(&mut token).unwrap().id;
+    srv.rotate_player_key(&mut token, &id)
+        .map(|key| json!({ "key": key }))
+}
+
+pub fn new_invite(srv: &GameState) -> ApiResult {
+    Ok(json!({ "invite": srv.new_invite(&mut LockToken::root()) }))
+}
 
-pub fn get_player(srv: GameState, id: PlayerId, key: PlayerKey) -> ApiResult {
-    let players = srv.players.read().unwrap();
+pub fn get_player(srv: &GameState, id: PlayerId, key: PlayerKey) -> ApiResult {
+    let mut token = LockToken::root();
+    let mut players = srv.players.read(&mut token).unwrap();
+    let (players, token) = players.split();
     let Some(playerlck) = players.get(&id) else {
         return Err(Errcode::PlayerNotFound(id));
     };
 
-    let player = playerlck.read().unwrap();
+    let player = playerlck.read(token).unwrap();
 
     if player.key == key {
         Ok(json!({
@@ -33,3 +75,83 @@ pub fn get_player(srv: GameState, id: PlayerId, key: PlayerKey) -> ApiResult {
         }))
     }
 }
+
+pub fn get_ledger(srv: &GameState, player: PlayerRef, qry: &LedgerQuery) -> ApiResult {
+    let category = match qry.category {
+        Some(ref c) => {
+            let Ok(c) = LedgerCategory::from_str(c) else {
+                return Err(Errcode::InvalidArgument("category"));
+            };
+            Some(c)
+        }
+        None => None,
+    };
+    let offset = qry.offset.unwrap_or(0);
+    let limit = qry
+        .limit
+        .unwrap_or(LEDGER_DEFAULT_LIMIT)
+        .min(LEDGER_MAX_LIMIT);
+
+    let mut token = LockToken::root();
+    let player = player.read(&mut token).unwrap();
+    let entries = player
+        .ledger
+        .entries()
+        .filter(|e| category.is_none_or(|c| e.category == c))
+        .filter(|e| qry.since.is_none_or(|t| srv.tstart + e.timestamp >= t))
+        .filter(|e| qry.until.is_none_or(|t| srv.tstart + e.timestamp < t))
+        .skip(offset)
+        .take(limit)
+        .map(|e| {
+            let mut e = e.clone();
+            e.timestamp += srv.tstart;
+            e
+        })
+        .collect::<Vec<LedgerEntry>>();
+    Ok(json!({
+        "offset": offset,
+        "nb": entries.len(),
+        "entries": entries,
+        "pnl": player.ledger.profit_and_loss(),
+    }))
+}
+
+pub fn list_alerts(player: PlayerRef) -> ApiResult {
+    let mut token = LockToken::root();
+    let player = player.read(&mut token).unwrap();
+    let alerts = player.alerts.iter().collect::<Vec<&Alert>>();
+    Ok(json!({ "alerts": alerts }))
+}
+
+pub fn new_alert(player: PlayerRef, rule: AlertRule) -> ApiResult {
+    let mut token = LockToken::root();
+    let mut player = player.write(&mut token).unwrap();
+    player.add_alert(rule).map(|id| json!({ "id": id }))
+}
+
+pub fn get_alert(player: PlayerRef, id: AlertId) -> ApiResult {
+    let mut token = LockToken::root();
+    let player = player.read(&mut token).unwrap();
+    player
+        .alerts
+        .get(&id)
+        .map(|a| serde_json::to_value(a).unwrap())
+}
+
+pub fn update_alert(player: PlayerRef, id: AlertId, threshold: f64) -> ApiResult {
+    let mut token = LockToken::root();
+    let mut player = player.write(&mut token).unwrap();
+    player
+        .alerts
+        .set_threshold(&id, threshold)
+        .map(|a| serde_json::to_value(a).unwrap())
+}
+
+pub fn delete_alert(player: PlayerRef, id: AlertId) -> ApiResult {
+    let mut token = LockToken::root();
+    let mut player = player.write(&mut token).unwrap();
+    player
+        .alerts
+        .remove(&id)
+        .map(|a| serde_json::to_value(a).unwrap())
+}
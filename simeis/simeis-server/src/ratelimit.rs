@@ -0,0 +1,357 @@
+// Token bucket rate limiting per player and route group
+// Requests without the key of a player are limited by IP address,
+//     admin routes only count the requests without a valid admin key
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use ntex::http::header::{HeaderValue, RETRY_AFTER};
+use ntex::service::{Middleware, Service, ServiceCtx};
+use ntex::web::{WebRequest, WebResponse};
+use serde_json::json;
+use strum::{EnumIter, IntoEnumIterator, IntoStaticStr};
+
+use simeis_data::errors::Errcode;
+use simeis_data::lock::LockToken;
+use simeis_data::player::PlayerId;
+
+use crate::api::{player_key_from_token, token_from_parts, ApiResult};
+use crate::world::request_world;
+
+// Above this number of buckets, the ones that are full again are dropped
+const MAX_IDLE_BUCKETS: usize = 10_000;
+// The buckets are looked through at most once in this period to drop the idle ones
+const SWEEP_PERIOD: Duration = Duration::from_secs(1);
+
+#[derive(EnumIter, IntoStaticStr, Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[strum(serialize_all = "lowercase")]
+pub enum RouteGroup {
+    Market,
+    Syslog,
+    Admin,
+    Default,
+}
+
+impl RouteGroup {
+    fn of(path: &str) -> RouteGroup {
+        let path = path.strip_prefix("/v2").unwrap_or(path);
+        if path.starts_with("/admin") {
+            RouteGroup::Admin
+        } else if path.starts_with("/syslogs") {
+            RouteGroup::Syslog
+        } else if path.starts_with("/market") || path.contains("/market/") {
+            RouteGroup::Market
+        } else {
+            RouteGroup::Default
+        }
+    }
+}
+
+// Who the requests are counted for
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum Client {
+    // Player owning the key, in its world
+    Player(String, PlayerId),
+    // Any key not belonging to a player is ignored, or each made up key would get a full bucket
+    Ip(Option<IpAddr>),
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct BucketConfig {
+    // Tokens added per second
+    pub rate: f64,
+    // Maximum number of tokens, the size of a burst of requests
+    pub burst: f64,
+}
+
+impl BucketConfig {
+    fn default_for(group: RouteGroup) -> BucketConfig {
+        match group {
+            RouteGroup::Market => BucketConfig {
+                rate: 20.0,
+                burst: 40.0,
+            },
+            RouteGroup::Syslog => BucketConfig {
+                rate: 10.0,
+                burst: 20.0,
+            },
+            // Only counts the failed attempts, to slow down the guessing of admin keys
+            RouteGroup::Admin => BucketConfig {
+                rate: 1.0,
+                burst: 10.0,
+            },
+            RouteGroup::Default => BucketConfig {
+                rate: 50.0,
+                burst: 100.0,
+            },
+        }
+    }
+}
+
+pub struct RateLimitConfig(BTreeMap<RouteGroup, Option<BucketConfig>>);
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        RateLimitConfig(
+            RouteGroup::iter()
+                .map(|g| (g, Some(BucketConfig::default_for(g))))
+                .collect(),
+        )
+    }
+}
+
+impl RateLimitConfig {
+    // SIMEIS_RATELIMIT_<GROUP> is either "<rate>,<burst>", or "off" to disable the limit
+    pub fn from_env() -> Result<RateLimitConfig, String> {
+        let mut config = RateLimitConfig::default();
+        for group in RouteGroup::iter() {
+            let name: &'static str = group.into();
+            let var = format!("SIMEIS_RATELIMIT_{}", name.to_uppercase());
+            let Ok(val) = std::env::var(&var) else {
+                continue;
+            };
+            let bucket = BucketConfig::parse(&val).map_err(|e| format!("{var}={val}: {e}"))?;
+            config.0.insert(group, bucket);
+        }
+        Ok(config)
+    }
+}
+
+impl BucketConfig {
+    // A bucket that never refills, or can't hold a single request, would block the group forever
+    fn parse(val: &str) -> Result<Option<BucketConfig>, String> {
+        if val == "off" {
+            return Ok(None);
+        }
+        let Some((rate, burst)) = val
+            .split_once(',')
+            .and_then(|(r, b)| Some((r.trim().parse().ok()?, b.trim().parse().ok()?)))
+        else {
+            return Err("expected \"<rate>,<burst>\" or \"off\"".to_string());
+        };
+        if !(rate > 0.0 && f64::is_finite(rate)) {
+            return Err("the rate must be a positive number of requests per second".to_string());
+        }
+        if !(burst >= 1.0 && f64::is_finite(burst)) {
+            return Err("the burst must allow at least one request".to_string());
+        }
+        Ok(Some(BucketConfig { rate, burst }))
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    last: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self, config: &BucketConfig, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * config.rate).min(config.burst);
+        self.last = now;
+    }
+}
+
+#[derive(Default, Clone, Copy)]
+struct GroupStats {
+    allowed: u64,
+    limited: u64,
+}
+
+#[derive(Default)]
+struct LimiterState {
+    buckets: HashMap<(Client, RouteGroup), Bucket>,
+    stats: BTreeMap<RouteGroup, GroupStats>,
+    last_sweep: Option<Instant>,
+}
+
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    state: Mutex<LimiterState>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> RateLimiter {
+        RateLimiter {
+            config,
+            state: Mutex::new(LimiterState::default()),
+        }
+    }
+
+    // Takes a token from the bucket, or returns the number of seconds before one is available
+    fn check(&self, client: Client, group: RouteGroup, now: Instant) -> Result<(), f64> {
+        let Some(Some(config)) = self.config.0.get(&group) else {
+            return Ok(());
+        };
+        let mut state = self.state.lock().unwrap();
+        let sweep = state
+            .last_sweep
+            .is_none_or(|last| now.saturating_duration_since(last) >= SWEEP_PERIOD);
+        if state.buckets.len() > MAX_IDLE_BUCKETS && sweep {
+            state.last_sweep = Some(now);
+            let configs = &self.config.0;
+            state.buckets.retain(|(_, g), b| {
+                let Some(Some(config)) = configs.get(g) else {
+                    return false;
+                };
+                b.refill(config, now);
+                b.tokens < config.burst
+            });
+        }
+
+        let bucket = state.buckets.entry((client, group)).or_insert(Bucket {
+            tokens: config.burst,
+            last: now,
+        });
+        bucket.refill(config, now);
+        let res = if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err((1.0 - bucket.tokens) / config.rate)
+        };
+        let stats = state.stats.entry(group).or_default();
+        match res {
+            Ok(()) => stats.allowed += 1,
+            Err(_) => stats.limited += 1,
+        }
+        res
+    }
+
+    pub fn stats(&self) -> ApiResult {
+        let state = self.state.lock().unwrap();
+        let groups = RouteGroup::iter()
+            .map(|g| {
+                let name: &'static str = g.into();
+                let config = self.config.0.get(&g).cloned().flatten();
+                let stats = state.stats.get(&g).cloned().unwrap_or_default();
+                (
+                    name,
+                    json!({
+                        "rate": config.map(|c| c.rate),
+                        "burst": config.map(|c| c.burst),
+                        "allowed": stats.allowed,
+                        "limited": stats.limited,
+                    }),
+                )
+            })
+            .collect::<BTreeMap<&str, serde_json::Value>>();
+        Ok(json!({
+            "buckets": state.buckets.len(),
+            "groups": groups,
+        }))
+    }
+}
+
+pub struct RateLimit(pub Arc<RateLimiter>);
+
+impl<S> Middleware<S> for RateLimit {
+    type Service = RateLimitMiddleware<S>;
+
+    fn create(&self, service: S) -> Self::Service {
+        RateLimitMiddleware {
+            service,
+            limiter: self.0.clone(),
+        }
+    }
+}
+
+pub struct RateLimitMiddleware<S> {
+    service: S,
+    limiter: Arc<RateLimiter>,
+}
+
+impl<S, E> Service<WebRequest<E>> for RateLimitMiddleware<S>
+where
+    S: Service<WebRequest<E>, Response = WebResponse>,
+{
+    type Response = WebResponse;
+    type Error = S::Error;
+
+    ntex::forward_poll!(service);
+    ntex::forward_ready!(service);
+    ntex::forward_shutdown!(service);
+
+    async fn call(
+        &self,
+        req: WebRequest<E>,
+        ctx: ServiceCtx<'_, Self>,
+    ) -> Result<Self::Response, Self::Error> {
+        let group = RouteGroup::of(req.path());
+        let token = token_from_parts(req.headers(), req.query_string());
+        let world = request_world(&req);
+        let client = match (token, world) {
+            (Some(token), Some((_, game))) if group == RouteGroup::Admin => {
+                if game.is_admin_key(&token) {
+                    return ctx.call(&self.service, req).await;
+                }
+                None
+            }
+            (Some(token), Some((name, game))) => player_key_from_token(&token)
+                .and_then(|key| {
+                    let mut token = LockToken::root();
+                    let index = game.player_index.read(&mut token).unwrap();
+                    index.get(&key).copied()
+                })
+                .map(|id| Client::Player(name, id)),
+            _ => None,
+        };
+        let client = client.unwrap_or(Client::Ip(req.peer_addr().map(|addr| addr.ip())));
+        let Err(retry) = self.limiter.check(client, group, Instant::now()) else {
+            return ctx.call(&self.service, req).await;
+        };
+
+        let err = Err(Errcode::RateLimited(retry));
+        let mut res = if req.path().starts_with("/v2/") {
+            crate::api_v2::build_response(err)
+        } else {
+            crate::api::build_response(err)
+        };
+        let retry = HeaderValue::from(retry.ceil() as u64);
+        res.headers_mut().insert(RETRY_AFTER, retry);
+        Ok(req.into_response(res))
+    }
+}
+
+#[test]
+fn test_token_bucket() {
+    let mut config = RateLimitConfig::default();
+    let bucket = BucketConfig {
+        rate: 2.0,
+        burst: 3.0,
+    };
+    config.0.insert(RouteGroup::Market, Some(bucket));
+    config.0.insert(RouteGroup::Default, None);
+    let limiter = RateLimiter::new(config);
+    let now = Instant::now();
+    let a = || Client::Player("default".to_string(), 1);
+    let b = Client::Player("other".to_string(), 1);
+
+    for _ in 0..3 {
+        assert!(limiter.check(a(), RouteGroup::Market, now).is_ok());
+    }
+    let retry = limiter.check(a(), RouteGroup::Market, now).unwrap_err();
+    assert!((retry - 0.5).abs() < 1e-9);
+    // Buckets are separate per client and per group
+    assert!(limiter.check(b, RouteGroup::Market, now).is_ok());
+    for _ in 0..100 {
+        assert!(limiter.check(a(), RouteGroup::Default, now).is_ok());
+    }
+
+    let later = now + std::time::Duration::from_millis(500);
+    assert!(limiter.check(a(), RouteGroup::Market, later).is_ok());
+    assert!(limiter.check(a(), RouteGroup::Market, later).is_err());
+}
+
+#[test]
+fn test_bucket_config_parse() {
+    let bucket = BucketConfig::parse("2.5, 10").unwrap().unwrap();
+    assert_eq!((bucket.rate, bucket.burst), (2.5, 10.0));
+    assert!(BucketConfig::parse("off").unwrap().is_none());
+    for val in [
+        "0,10", "-1,10", "NaN,10", "inf,10", "1,0.5", "1,NaN", "1", "a,b",
+    ] {
+        assert!(BucketConfig::parse(val).is_err(), "{val}");
+    }
+}
@@ -0,0 +1,55 @@
+use serde_json::json;
+
+use simeis_data::errors::Errcode;
+use simeis_data::lock::LockToken;
+use simeis_data::session::SessionId;
+
+use crate::api::ApiResult;
+use crate::GameState;
+
+pub fn get_current(srv: &GameState) -> ApiResult {
+    let mut token = LockToken::root();
+    let sessions = srv.sessions.read(&mut token).unwrap();
+    let session = &sessions.current;
+    let now = srv.clock.now();
+    Ok(json!({
+        "id": session.id,
+        "phase": session.phase,
+        "now": now,
+        "start": session.start,
+        "end": session.end,
+        "remaining": session.end.map(|end| (end - now).max(0.0)),
+    }))
+}
+
+pub fn list_sessions(srv: &GameState) -> ApiResult {
+    let mut token = LockToken::root();
+    let sessions = srv.sessions.read(&mut token).unwrap();
+    let list = sessions
+        .list()
+        .into_iter()
+        .map(|s| {
+            json!({
+                "id": s.id,
+                "phase": s.phase,
+                "created": s.created,
+                "start": s.start,
+                "end": s.end,
+            })
+        })
+        .collect::<Vec<_>>();
+    Ok(json!({
+        "current": sessions.current.id,
+        "sessions": list,
+    }))
+}
+
+// Includes the final ranking once the session is over
+pub fn get_session(srv: &GameState, id: SessionId) -> ApiResult {
+    let mut token = LockToken::root();
+    let sessions = srv.sessions.read(&mut token).unwrap();
+    match sessions.get(id) {
+        Some(session) => Ok(serde_json::to_value(session).unwrap()),
+        None => Err(Errcode::NoSuchSession(id)),
+    }
+}
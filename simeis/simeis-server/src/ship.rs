@@ -0,0 +1,106 @@
+use std::ops::DerefMut;
+
+use serde_json::json;
+
+use simeis_data::errors::Errcode;
+use simeis_data::galaxy::SpaceCoord;
+use simeis_data::lock::LockToken;
+use simeis_data::ship::resources::Resource;
+use simeis_data::ship::ShipId;
+use simeis_data::syslog::SyslogEvent;
+
+use crate::api::{ApiResult, PlayerRef};
+use crate::GameState;
+
+pub fn get_status(player: PlayerRef, id: ShipId) -> ApiResult {
+    let mut token = LockToken::root();
+    let player = player.read(&mut token).unwrap();
+    let Some(ship) = player.ships.get(&id) else {
+        return Err(Errcode::ShipNotFound(id));
+    };
+    Ok(serde_json::to_value(ship).unwrap())
+}
+
+pub fn travel_costs(player: PlayerRef, id: ShipId, coord: SpaceCoord) -> ApiResult {
+    let mut token = LockToken::root();
+    let player = player.read(&mut token).unwrap();
+    let Some(ship) = player.ships.get(&id) else {
+        return Err(Errcode::ShipNotFound(id));
+    };
+    ship.compute_travel_costs(coord)
+        .map(|v| serde_json::to_value(v).unwrap())
+}
+
+pub fn navigate(player: PlayerRef, id: ShipId, coord: SpaceCoord) -> ApiResult {
+    let mut token = LockToken::root();
+    let mut player = player.write(&mut token).unwrap();
+    let Some(ship) = player.ships.get_mut(&id) else {
+        return Err(Errcode::ShipNotFound(id));
+    };
+    ship.set_travel(coord).map(|cost| json!(cost))
+}
+
+pub fn start_extraction(srv: &GameState, player: PlayerRef, id: ShipId) -> ApiResult {
+    let mut token = LockToken::root();
+    let mut player = player.write(&mut token).unwrap();
+    let (player, token) = player.split();
+    let Some(ship) = player.ships.get_mut(&id) else {
+        return Err(Errcode::ShipNotFound(id));
+    };
+    ship.start_extraction(token, &srv.galaxy)
+        .map(|v| serde_json::to_value(v).unwrap())
+}
+
+pub fn stop_extraction(player: PlayerRef, id: ShipId) -> ApiResult {
+    let mut token = LockToken::root();
+    let mut player = player.write(&mut token).unwrap();
+    let Some(ship) = player.ships.get_mut(&id) else {
+        return Err(Errcode::ShipNotFound(id));
+    };
+    ship.stop_extraction()
+        .map(|v| serde_json::to_value(v).unwrap())
+}
+
+pub fn unload_cargo(
+    srv: &GameState,
+    player: PlayerRef,
+    id: ShipId,
+    resource: Resource,
+    amnt: f64,
+) -> ApiResult {
+    let mut token = LockToken::root();
+    let mut player = player.write(&mut token).unwrap();
+    let (player, token) = player.split();
+
+    let Some(ship) = player.ships.get(&id) else {
+        return Err(Errcode::ShipNotFound(id));
+    };
+
+    let Some(station) = player.stations.iter().find(|(_, s)| *s == &ship.position) else {
+        return Err(Errcode::ShipNotInStation);
+    };
+
+    let station = srv.galaxy.get_station(token, station.1).unwrap();
+    let mut station = station.write(token).unwrap();
+    let pid = player.id;
+    let ship = player.ships.get_mut(&id).unwrap();
+    let was_full = station.cargo.is_full();
+    let res = ship.unload_cargo(&resource, amnt, station.deref_mut());
+    if !was_full && station.cargo.is_full() {
+        let evt = SyslogEvent::StationCargoFull {
+            station: station.id,
+            cargo: station.cargo.clone(),
+        };
+        srv.syslog.event(&pid, evt);
+    }
+    if let Ok(0.0) = res {
+        srv.syslog.event(
+            &pid,
+            SyslogEvent::UnloadedNothing {
+                station_cargo: station.cargo.clone(),
+                ship_cargo: ship.cargo.clone(),
+            },
+        );
+    }
+    res.map(|v| json!({ "unloaded": v }))
+}
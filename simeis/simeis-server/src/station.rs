@@ -0,0 +1,277 @@
+use std::collections::BTreeMap;
+
+use serde_json::json;
+use strum::IntoEnumIterator;
+
+use simeis_data::errors::Errcode;
+use simeis_data::galaxy::station::{Station, StationId};
+use simeis_data::lock::LockToken;
+use simeis_data::ship::module::{ShipModuleId, ShipModuleType};
+use simeis_data::ship::upgrade::ShipUpgrade;
+use simeis_data::ship::ShipId;
+use simeis_data::syslog::SyslogEvent;
+
+use crate::api::{get_station, ApiResult, PlayerRef};
+use crate::GameState;
+
+pub fn get_status(srv: &GameState, player: PlayerRef, id: StationId) -> ApiResult {
+    let mut token = LockToken::root();
+    let station = get_station(srv, &mut token, &player, &id)?;
+    let station = station.read(&mut token).unwrap();
+    Ok(json!({
+        "id": station.id,
+        "position": station.position,
+        "crew": station.crew,
+        "cargo": station.cargo,
+        "idle_crew": station.idle_crew,
+        "trader": station.trader,
+    }))
+}
+
+pub fn scan(srv: &GameState, player: PlayerRef, id: StationId) -> ApiResult {
+    let mut token = LockToken::root();
+    let station = get_station(srv, &mut token, &player, &id)?;
+    let position = station.read(&mut token).unwrap().position;
+    let results = Station::scan(&mut token, &srv.galaxy, &position);
+    Ok(serde_json::to_value(&results).unwrap())
+}
+
+pub fn list_shipyard(srv: &GameState, player: PlayerRef, id: StationId) -> ApiResult {
+    let mut token = LockToken::root();
+    let station = get_station(srv, &mut token, &player, &id)?;
+    let station = station.read(&mut token).unwrap();
+    let mut ships = vec![];
+    for ship in station.shipyard.iter() {
+        ships.push(json!({
+            "id": ship.id,
+            "modules": ship.modules,
+            "reactor_power": ship.reactor_power,
+            "cargo_capacity": ship.cargo.capacity,
+            "fuel_tank_capacity": ship.fuel_tank_capacity,
+            "hull_decay_capacity": ship.hull_decay_capacity,
+            "price": ship.compute_price(),
+        }));
+    }
+    Ok(json!({ "ships": ships }))
+}
+
+pub fn buy_ship(
+    srv: &GameState,
+    player: PlayerRef,
+    station_id: StationId,
+    ship_id: ShipId,
+) -> ApiResult {
+    let mut token = LockToken::root();
+    let station = get_station(srv, &mut token, &player, &station_id)?;
+    let mut player = player.write(&mut token).unwrap();
+    let (player, token) = player.split();
+    let mut station = station.write(token).unwrap();
+    let price = station
+        .shipyard
+        .iter()
+        .find(|ship| ship.id == ship_id)
+        .map(|ship| ship.compute_price());
+    let id = player.buy_ship(&mut station, ship_id)?;
+    if let Some(price) = price {
+        let evt = SyslogEvent::ShipBought {
+            station: station_id,
+            ship: id,
+            price,
+        };
+        srv.syslog.event(&player.id, evt);
+    }
+    if let Some(new) = station.shipyard.last() {
+        let evt = SyslogEvent::ShipyardRestocked {
+            station: station_id,
+            ship: new.id,
+        };
+        srv.syslog.event(&player.id, evt);
+    }
+    Ok(json!({ "shipId": id }))
+}
+
+pub fn list_ship_upgrades(srv: &GameState, player: PlayerRef, id: StationId) -> ApiResult {
+    let mut token = LockToken::root();
+    let station = get_station(srv, &mut token, &player, &id)?;
+    let station = station.read(&mut token).unwrap();
+    let mut res = BTreeMap::new();
+    for upgr in ShipUpgrade::iter() {
+        res.insert(
+            upgr,
+            json!({
+                "price": station.get_ship_upgrade_price(&upgr),
+                "description": upgr.description(),
+            }),
+        );
+    }
+    Ok(serde_json::to_value(res).unwrap())
+}
+
+pub fn buy_ship_upgrade(
+    srv: &GameState,
+    player: PlayerRef,
+    station_id: StationId,
+    ship_id: ShipId,
+    upgrade: ShipUpgrade,
+) -> ApiResult {
+    let mut token = LockToken::root();
+    let station = get_station(srv, &mut token, &player, &station_id)?;
+    let mut player = player.write(&mut token).unwrap();
+    let (player, token) = player.split();
+    let mut station = station.write(token).unwrap();
+    let cost = player.buy_ship_upgrade(&mut station, &ship_id, &upgrade)?;
+    let evt = SyslogEvent::UpgradeInstalled {
+        ship: ship_id,
+        upgrade,
+        cost,
+    };
+    srv.syslog.event(&player.id, evt);
+    Ok(json!({ "cost": cost }))
+}
+
+pub fn list_module_prices(srv: &GameState, player: PlayerRef, id: StationId) -> ApiResult {
+    let _station = get_station(srv, &mut LockToken::root(), &player, &id)?;
+    // TODO (#22) Price based on station
+    let mut res: BTreeMap<ShipModuleType, f64> = BTreeMap::new();
+    for smod in ShipModuleType::iter() {
+        let price = smod.get_price_buy();
+        res.insert(smod, price);
+    }
+    Ok(serde_json::to_value(res).unwrap())
+}
+
+pub fn buy_ship_module(
+    srv: &GameState,
+    player: PlayerRef,
+    station_id: StationId,
+    ship_id: ShipId,
+    modtype: ShipModuleType,
+) -> ApiResult {
+    let mut token = LockToken::root();
+    let mut player = player.write(&mut token).unwrap();
+    let module = player.buy_ship_module(&station_id, &ship_id, modtype)?;
+    let evt = SyslogEvent::ModuleInstalled {
+        ship: ship_id,
+        module,
+        modtype,
+        cost: modtype.get_price_buy(),
+    };
+    srv.syslog.event(&player.id, evt);
+    Ok(json!({ "id": module }))
+}
+
+pub fn list_module_upgrades(
+    srv: &GameState,
+    player: PlayerRef,
+    station_id: StationId,
+    ship_id: ShipId,
+) -> ApiResult {
+    let mut token = LockToken::root();
+    let station = get_station(srv, &mut token, &player, &station_id)?;
+    let mut player = player.read(&mut token).unwrap();
+    let (player, token) = player.split();
+    let Some(ship) = player.ships.get(&ship_id) else {
+        return Err(Errcode::ShipNotFound(ship_id));
+    };
+    if ship.position != station.read(token).unwrap().position {
+        return Err(Errcode::ShipNotInStation);
+    }
+
+    let mut res = BTreeMap::new();
+    for (id, smod) in ship.modules.iter() {
+        res.insert(
+            id,
+            json!({
+                "module-type": smod.modtype,
+                "price": smod.price_next_rank(),
+            }),
+        );
+    }
+    Ok(serde_json::to_value(res).unwrap())
+}
+
+pub fn buy_module_upgrade(
+    srv: &GameState,
+    player: PlayerRef,
+    station_id: StationId,
+    ship_id: ShipId,
+    mod_id: ShipModuleId,
+) -> ApiResult {
+    let mut token = LockToken::root();
+    let station = get_station(srv, &mut token, &player, &station_id)?;
+    let mut player = player.write(&mut token).unwrap();
+    let (player, token) = player.split();
+    let station = station.read(token).unwrap();
+    let (cost, rank) = player.buy_ship_module_upgrade(&station, &ship_id, &mod_id)?;
+    let evt = SyslogEvent::ModuleUpgraded {
+        ship: ship_id,
+        module: mod_id,
+        rank,
+        cost,
+    };
+    srv.syslog.event(&player.id, evt);
+    Ok(json!({
+        "new-rank": rank,
+        "cost": cost,
+    }))
+}
+
+pub fn buy_cargo(srv: &GameState, player: PlayerRef, id: StationId, amnt: usize) -> ApiResult {
+    let mut token = LockToken::root();
+    let station = get_station(srv, &mut token, &player, &id)?;
+    let mut player = player.write(&mut token).unwrap();
+    let (player, token) = player.split();
+    let mut station = station.write(token).unwrap();
+    station
+        .buy_cargo(player, &amnt)
+        .map(|v| serde_json::to_value(v).unwrap())
+}
+
+pub fn list_station_upgrades(srv: &GameState, player: PlayerRef, id: StationId) -> ApiResult {
+    let mut token = LockToken::root();
+    let station = get_station(srv, &mut token, &player, &id)?;
+    let station = station.read(&mut token).unwrap();
+    let cargoprice = station.cargo_price();
+    let traderprice = station.trader.map(|trader| {
+        let cm = station.crew.0.get(&trader).unwrap();
+        cm.price_next_rank()
+    });
+    Ok(json!({
+        "cargo-expansion": cargoprice,
+        "trader-upgrade": traderprice,
+    }))
+}
+
+pub fn refuel_ship(
+    srv: &GameState,
+    player: PlayerRef,
+    station_id: StationId,
+    ship_id: ShipId,
+) -> ApiResult {
+    let mut token = LockToken::root();
+    let station = get_station(srv, &mut token, &player, &station_id)?;
+    let mut player = player.write(&mut token).unwrap();
+    let (player, token) = player.split();
+    let mut station = station.write(token).unwrap();
+    let Some(ship) = player.ships.get_mut(&ship_id) else {
+        return Err(Errcode::ShipNotFound(ship_id));
+    };
+    station.refuel_ship(ship).map(|v| json!({"added-fuel": v}))
+}
+
+pub fn repair_ship(
+    srv: &GameState,
+    player: PlayerRef,
+    station_id: StationId,
+    ship_id: ShipId,
+) -> ApiResult {
+    let mut token = LockToken::root();
+    let station = get_station(srv, &mut token, &player, &station_id)?;
+    let mut player = player.write(&mut token).unwrap();
+    let (player, token) = player.split();
+    let mut station = station.write(token).unwrap();
+    let Some(ship) = player.ships.get_mut(&ship_id) else {
+        return Err(Errcode::ShipNotFound(ship_id));
+    };
+    station.repair_ship(ship).map(|v| json!({"added-hull": v}))
+}
@@ -0,0 +1,235 @@
+use std::io;
+
+use ntex::service::{fn_factory_with_config, fn_service};
+use ntex::util::{select, Either};
+use ntex::web::{self, ws, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use serde_json::json;
+
+use simeis_data::errors::Errcode;
+use simeis_data::lock::LockToken;
+use simeis_data::player::PlayerId;
+use simeis_data::syslog::{SyslogEntry, SyslogHistories, SyslogSeq};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::api::{ApiResult, PlayerRef};
+use crate::GameState;
+
+const SYSLOG_DEFAULT_LIMIT: usize = 100;
+const CLIENT_NAME_MAX_LEN: usize = 64;
+
+#[derive(Deserialize)]
+pub struct SyslogQuery {
+    pub since: Option<SyslogSeq>,
+    // Name chosen by the client, to read from its own acknowledged cursor
+    pub client: Option<String>,
+    // Comma-separated list of event types
+    pub types: Option<String>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Deserialize)]
+pub struct SyslogAckQuery {
+    pub client: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct SyslogStreamQuery {
+    pub since: Option<SyslogSeq>,
+}
+
+pub fn entry_to_json(tstart: f64, entry: &SyslogEntry) -> serde_json::Value {
+    let s: &'static str = entry.event.clone().into();
+    serde_json::json!({
+        "seq": entry.seq,
+        "timestamp": tstart + entry.timestamp,
+        "type": s,
+        "event": entry.event,
+    })
+}
+
+fn check_client(client: Option<&String>) -> Result<&str, Errcode> {
+    match client {
+        Some(c) if !c.is_empty() && c.len() <= CLIENT_NAME_MAX_LEN => Ok(c),
+        _ => Err(Errcode::InvalidArgument("client")),
+    }
+}
+
+// Events are not removed when read, clients either pass the last sequence number they got,
+//     or acknowledge the events they processed under their own name
+// Without any of them, the newest events are returned, so clients polling that way still see new ones
+pub fn get_events(srv: &GameState, player: PlayerRef, qry: &SyslogQuery) -> ApiResult {
+    let mut token = LockToken::root();
+    let player_id = player.read(&mut token).unwrap().id;
+    let types = qry
+        .types
+        .as_ref()
+        .map(|t| t.split(',').map(|t| t.trim()).collect::<Vec<&str>>());
+    let limit = qry.limit.unwrap_or(SYSLOG_DEFAULT_LIMIT);
+    let client = qry
+        .client
+        .as_ref()
+        .map(|c| check_client(Some(c)))
+        .transpose()?;
+
+    let mut all = srv.syslog_history.read(&mut token).unwrap();
+    let (all, token) = all.split();
+    let Some(history) = all.get(&player_id) else {
+        return Ok(json!({"nb": 0, "events": [], "last_seq": 0}));
+    };
+    let history = history.read(token).unwrap();
+    let acked = client.map(|c| history.acked(c));
+    let since = qry.since.or(acked);
+    let events = history.since(since.unwrap_or(0)).filter(|e| {
+        let s: &'static str = e.event.clone().into();
+        types
+            .as_ref()
+            .is_none_or(|types| types.iter().any(|t| t.eq_ignore_ascii_case(s)))
+    });
+    let events = match since {
+        Some(_) => events.take(limit).collect::<Vec<&SyslogEntry>>(),
+        None => {
+            let mut newest = events.rev().take(limit).collect::<Vec<&SyslogEntry>>();
+            newest.reverse();
+            newest
+        }
+    };
+    let res = events
+        .into_iter()
+        .map(|e| entry_to_json(srv.tstart, e))
+        .collect::<Vec<serde_json::Value>>();
+    Ok(json!({
+        "nb": res.len(),
+        "events": res,
+        "first_seq": history.first_seq(),
+        "last_seq": history.last_seq(),
+        "acked": acked,
+    }))
+}
+
+pub fn ack_events(
+    srv: &GameState,
+    player: PlayerRef,
+    client: Option<&String>,
+    seq: SyslogSeq,
+) -> ApiResult {
+    let client = check_client(client)?;
+    let mut token = LockToken::root();
+    let player_id = player.read(&mut token).unwrap().id;
+    let mut all = srv.syslog_history.read(&mut token).unwrap();
+    let (all, token) = all.split();
+    let Some(history) = all.get(&player_id) else {
+        return Ok(json!({"acked": 0}));
+    };
+    let acked = history.write(token).unwrap().ack(client, seq);
+    Ok(json!({"acked": acked}))
+}
+
+pub fn get_stats(srv: &GameState) -> ApiResult {
+    let stats = &srv.syslog_stats;
+    Ok(json!({
+        "pending": stats.pending(),
+        "delivered": stats.delivered(),
+        "last_latency": stats.last_latency(),
+        "max_latency": stats.max_latency(),
+    }))
+}
+
+async fn handle_frame(frame: ws::Frame) -> Result<Option<ws::Message>, io::Error> {
+    Ok(match frame {
+        ws::Frame::Ping(msg) => Some(ws::Message::Pong(msg)),
+        ws::Frame::Close(_) => Some(ws::Message::Close(Some(ws::CloseCode::Normal.into()))),
+        _ => None,
+    })
+}
+
+// Pushes the events of the player on the websocket as they are produced
+// If `since` is given, replays the events that came after this sequence number first
+async fn push_events(
+    sink: ws::WsSink,
+    history: SyslogHistories,
+    mut notified: broadcast::Receiver<PlayerId>,
+    player: PlayerId,
+    tstart: f64,
+    since: Option<SyslogSeq>,
+) {
+    let mut last_seq = since.unwrap_or_else(|| {
+        let mut token = LockToken::root();
+        let mut all = history.read(&mut token).unwrap();
+        let (all, token) = all.split();
+        all.get(&player)
+            .map(|h| h.read(token).unwrap().last_seq())
+            .unwrap_or(0)
+    });
+
+    loop {
+        let mut messages = vec![];
+        // The locks and their token don't live across the awaits
+        {
+            let mut token = LockToken::root();
+            let mut all = history.read(&mut token).unwrap();
+            let (all, token) = all.split();
+            if let Some(hist) = all.get(&player) {
+                let hist = hist.read(token).unwrap();
+                if hist.first_seq() > last_seq + 1 {
+                    messages.push(serde_json::json!({
+                        "type": "EventsLost",
+                        "from": last_seq + 1,
+                        "to": hist.first_seq() - 1,
+                    }));
+                }
+                for entry in hist.since(last_seq) {
+                    messages.push(entry_to_json(tstart, entry));
+                }
+                last_seq = hist.last_seq();
+            }
+        }
+
+        for msg in messages {
+            let msg = ws::Message::Text(msg.to_string().into());
+            if sink.send(msg).await.is_err() {
+                return;
+            }
+        }
+
+        // After a lag, the notifications we missed may have been for this player
+        let wait_event = async {
+            loop {
+                match notified.recv().await {
+                    Ok(id) if id != player => continue,
+                    Ok(_) | Err(RecvError::Lagged(_)) => return true,
+                    Err(RecvError::Closed) => return false,
+                }
+            }
+        };
+        match select(sink.io().on_disconnect(), wait_event).await {
+            Either::Right(true) => continue,
+            _ => return,
+        }
+    }
+}
+
+pub async fn stream_events(
+    srv: &GameState,
+    req: HttpRequest,
+    player: PlayerRef,
+    since: Option<SyslogSeq>,
+) -> Result<HttpResponse, web::Error> {
+    let player = player.read(&mut LockToken::root()).unwrap().id;
+    let history = srv.syslog_history.clone();
+    let notify = srv.syslog_notify.clone();
+    let tstart = srv.tstart;
+    ws::start::<_, _, web::Error>(
+        req,
+        fn_factory_with_config(move |sink: ws::WsSink| {
+            let history = history.clone();
+            // Subscribed before reading the history, no event can fall in between
+            let notified = notify.subscribe();
+            async move {
+                ntex::rt::spawn(push_events(sink, history, notified, player, tstart, since));
+                Ok::<_, web::Error>(fn_service(handle_frame))
+            }
+        }),
+    )
+    .await
+}
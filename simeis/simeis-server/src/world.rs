@@ -0,0 +1,213 @@
+// Several games running in the same server, each one in its own named world
+// A request picks its world with the "Simeis-World" header or a "/worlds/{name}" path prefix,
+//     and goes to the default world (the first one) without any
+use std::ops::Deref;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+use ntex::http::{Payload, Uri};
+use ntex::service::{Middleware, Service, ServiceCtx};
+use ntex::web::error::{ErrorRenderer, StateExtractorError};
+use ntex::web::{FromRequest, HttpRequest, WebRequest, WebResponse};
+use serde_json::json;
+
+use simeis_data::errors::Errcode;
+use simeis_data::game::{Game, GameConfig};
+use simeis_data::lock::LockToken;
+
+use crate::api::ApiResult;
+
+pub const WORLD_HEADER: &str = "simeis-world";
+const WORLD_PREFIX: &str = "/worlds/";
+
+pub struct Worlds {
+    // In the order they were configured, the first one is the default
+    worlds: Vec<(String, Game)>,
+}
+
+impl Worlds {
+    pub fn init(configs: Vec<(String, GameConfig)>) -> (Vec<JoinHandle<()>>, Worlds) {
+        let mut threads = vec![];
+        let mut worlds = vec![];
+        for (name, config) in configs {
+            let (thread, game) = Game::init(config);
+            threads.push(thread);
+            worlds.push((name, game));
+        }
+        (threads, Worlds { worlds })
+    }
+
+    pub fn get(&self, name: Option<&str>) -> Option<&Game> {
+        self.find(name).map(|(_, game)| game)
+    }
+
+    fn find(&self, name: Option<&str>) -> Option<&(String, Game)> {
+        match name {
+            Some(name) => self.worlds.iter().find(|(n, _)| n == name),
+            None => self.worlds.first(),
+        }
+    }
+
+    pub fn stop(&self, threads: Vec<JoinHandle<()>>) {
+        for ((name, game), thread) in self.worlds.iter().zip(threads) {
+            log::info!("Stopping world {name}");
+            game.clone().stop(thread);
+        }
+    }
+
+    pub fn list(&self) -> ApiResult {
+        let worlds = self
+            .worlds
+            .iter()
+            .enumerate()
+            .map(|(n, (name, game))| {
+                let mut token = LockToken::root();
+                json!({
+                    "name": name,
+                    "default": n == 0,
+                    "players": game.players.read(&mut token).unwrap().len(),
+                    "time": game.clock.now(),
+                    "paused": game.is_paused(),
+                    "session": game.sessions.read(&mut token).unwrap().current.phase,
+                })
+            })
+            .collect::<Vec<_>>();
+        Ok(json!({ "worlds": worlds }))
+    }
+}
+
+// World picked by the request, set by the WorldRouter middleware
+struct WorldName(String);
+
+// Name and game of the world picked by a request that went through the WorldRouter
+pub fn request_world<E>(req: &WebRequest<E>) -> Option<(String, Game)> {
+    let worlds = req.app_state::<Arc<Worlds>>()?;
+    let name = req.extensions().get::<WorldName>()?.0.clone();
+    let game = worlds.get(Some(&name))?.clone();
+    Some((name, game))
+}
+
+// Game of the world picked by the request
+pub struct GameState {
+    pub world: String,
+    game: Game,
+}
+
+impl Deref for GameState {
+    type Target = Game;
+
+    fn deref(&self) -> &Game {
+        &self.game
+    }
+}
+
+impl<E: ErrorRenderer> FromRequest<E> for GameState {
+    type Error = StateExtractorError;
+
+    async fn from_request(req: &HttpRequest, _: &mut Payload) -> Result<Self, Self::Error> {
+        let worlds = req
+            .app_state::<Arc<Worlds>>()
+            .ok_or(StateExtractorError::NotConfigured)?;
+        let name = req.extensions().get::<WorldName>().map(|w| w.0.clone());
+        worlds
+            .find(name.as_deref())
+            .map(|(world, game)| GameState {
+                world: world.clone(),
+                game: game.clone(),
+            })
+            .ok_or(StateExtractorError::NotConfigured)
+    }
+}
+
+// Splits "/worlds/{name}/rest" into the name and "/rest"
+fn split_world_prefix(path: &str) -> Option<(&str, &str)> {
+    let rest = path.strip_prefix(WORLD_PREFIX)?;
+    match rest.find('/') {
+        Some(n) => Some((&rest[..n], &rest[n..])),
+        None => Some((rest, "/")),
+    }
+}
+
+pub struct WorldRouter(pub Arc<Worlds>);
+
+impl<S> Middleware<S> for WorldRouter {
+    type Service = WorldRouterMiddleware<S>;
+
+    fn create(&self, service: S) -> Self::Service {
+        WorldRouterMiddleware {
+            service,
+            worlds: self.0.clone(),
+        }
+    }
+}
+
+pub struct WorldRouterMiddleware<S> {
+    service: S,
+    worlds: Arc<Worlds>,
+}
+
+impl<S, E> Service<WebRequest<E>> for WorldRouterMiddleware<S>
+where
+    S: Service<WebRequest<E>, Response = WebResponse>,
+{
+    type Response = WebResponse;
+    type Error = S::Error;
+
+    ntex::forward_poll!(service);
+    ntex::forward_ready!(service);
+    ntex::forward_shutdown!(service);
+
+    async fn call(
+        &self,
+        mut req: WebRequest<E>,
+        ctx: ServiceCtx<'_, Self>,
+    ) -> Result<Self::Response, Self::Error> {
+        let mut name = req
+            .headers()
+            .get(WORLD_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_string());
+
+        // The prefix is removed before the routing, the routes are the same in every world
+        if let Some((world, path)) = split_world_prefix(req.path()) {
+            let world = world.to_string();
+            let uri = match req.query_string() {
+                "" => path.to_string(),
+                query => format!("{path}?{query}"),
+            };
+            if let Ok(uri) = uri.parse::<Uri>() {
+                req.head_mut().uri = uri.clone();
+                req.match_info_mut().set(uri);
+            }
+            name = Some(world);
+        }
+
+        if self.worlds.get(name.as_deref()).is_none() {
+            let err = Err(Errcode::NoSuchWorld(name.unwrap_or_default()));
+            let res = if req.path().starts_with("/v2/") {
+                crate::api_v2::build_response(err)
+            } else {
+                crate::api::build_response(err)
+            };
+            return Ok(req.into_response(res));
+        }
+        // Always set, the request extensions are reused from one request to the other
+        let name = name.unwrap_or_else(|| self.worlds.worlds[0].0.clone());
+        req.extensions_mut().insert(WorldName(name));
+        ctx.call(&self.service, req).await
+    }
+}
+
+#[test]
+fn test_world_prefix() {
+    assert_eq!(
+        split_world_prefix("/worlds/practice/player/1"),
+        Some(("practice", "/player/1"))
+    );
+    assert_eq!(
+        split_world_prefix("/worlds/practice"),
+        Some(("practice", "/"))
+    );
+    assert_eq!(split_world_prefix("/worlds"), None);
+    assert_eq!(split_world_prefix("/player/1"), None);
+}